@@ -8,6 +8,8 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::Result;
 use serde::Deserialize;
@@ -22,15 +24,135 @@ pub struct Config {
     pub default_sched: Option<SupportedSched>,
     pub default_mode: Option<SchedMode>,
     pub scheds: HashMap<String, Sched>,
+    pub rules: Vec<Rule>,
+    pub restart_policy: RestartPolicy,
+    pub power_policy: PowerPolicy,
 }
 
 #[derive(Debug, PartialEq, Default, Serialize, Deserialize)]
 pub struct Sched {
+    /// Path of the scheduler binary, the scheduler name is looked up in PATH if not set
+    pub path: Option<String>,
     pub auto_mode: Option<Vec<String>>,
     pub gaming_mode: Option<Vec<String>>,
     pub lowlatency_mode: Option<Vec<String>>,
     pub powersave_mode: Option<Vec<String>>,
     pub server_mode: Option<Vec<String>>,
+    /// Custom modes by name, in addition to the built-in ones
+    #[serde(default)]
+    pub modes: HashMap<String, Vec<String>>,
+}
+
+impl Sched {
+    /// Get the flags of all configured modes, paired with the name of the mode in the config
+    pub fn mode_flags(&self) -> Vec<(String, &Vec<String>)> {
+        let builtin_modes = [
+            ("auto_mode", &self.auto_mode),
+            ("gaming_mode", &self.gaming_mode),
+            ("lowlatency_mode", &self.lowlatency_mode),
+            ("powersave_mode", &self.powersave_mode),
+            ("server_mode", &self.server_mode),
+        ];
+        let mut mode_flags = builtin_modes
+            .into_iter()
+            .filter_map(|(mode_name, flags)| Some((mode_name.to_owned(), flags.as_ref()?)))
+            .collect::<Vec<_>>();
+
+        let mut custom_modes = self.modes.iter().collect::<Vec<_>>();
+        custom_modes.sort();
+        mode_flags.extend(
+            custom_modes
+                .into_iter()
+                .map(|(mode_name, flags)| (format!("modes.{mode_name}"), flags)),
+        );
+        mode_flags
+    }
+}
+
+/// Rule for switching the scheduler automatically while a matching process is running.
+/// All of the set matchers have to match the process.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Scheduler to switch to while the rule is active
+    pub sched: SupportedSched,
+    /// Scheduler mode to switch to while the rule is active, defaults to Auto
+    pub mode: Option<SchedMode>,
+    /// Exact match against the process name (`/proc/<pid>/comm`)
+    pub comm: Option<String>,
+    /// Substring match against the process command line
+    pub cmdline: Option<String>,
+    /// Prefix match against the cgroup v2 path of the process, compared by
+    /// path components
+    pub cgroup: Option<String>,
+}
+
+/// Policy for restarting the scheduler after it exited with a failure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestartPolicy {
+    /// Maximum number of restarts before giving up on the scheduler
+    pub max_restarts: u32,
+    /// Delay before the first restart in milliseconds, doubled on every further restart
+    pub backoff_ms: u64,
+    /// Upper limit for the delay between restarts in milliseconds
+    pub max_backoff_ms: u64,
+    /// Scheduler to switch to once the failed scheduler ran out of restarts
+    pub fallback_sched: Option<SupportedSched>,
+    /// Scheduler mode for the fallback scheduler, defaults to Auto
+    pub fallback_mode: Option<SchedMode>,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            fallback_sched: None,
+            fallback_mode: None,
+        }
+    }
+}
+
+/// Policy for switching the mode of the running scheduler with the power
+/// profile of power-profiles-daemon and the AC/battery state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerPolicy {
+    /// Whether the mode is switched automatically, disabled by default
+    pub enabled: bool,
+    /// Mode for the "power-saver" profile
+    pub power_saver: Option<SchedMode>,
+    /// Mode for the "balanced" profile
+    pub balanced: Option<SchedMode>,
+    /// Mode for the "performance" profile
+    pub performance: Option<SchedMode>,
+    /// Mode while running on battery, regardless of the profile
+    pub on_battery: Option<SchedMode>,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            power_saver: Some(SchedMode::PowerSave),
+            balanced: Some(SchedMode::Auto),
+            performance: Some(SchedMode::Gaming),
+            on_battery: None,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Get the delay before the given restart, starting from 1
+    pub fn backoff(&self, restart: u32) -> Duration {
+        let factor = 1u64 << restart.saturating_sub(1).min(16);
+        Duration::from_millis(
+            self.backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms),
+        )
+    }
 }
 
 /// Initialize config from first found config path, overwise fallback to default config
@@ -73,25 +195,64 @@ fn parse_config_content(file_content: &str) -> Result<Config> {
         anyhow::bail!("The config file is empty!")
     }
     let config: Config = toml::from_str(file_content)?;
+
+    for (scx_name, sched_config) in &config.scheds {
+        if let Err(err) = validate_sched_config(scx_name, sched_config) {
+            log::warn!("Ignoring scheduler {scx_name}: {err}");
+        }
+    }
+
     Ok(config)
 }
 
+/// Check whether the scheduler entry of the config can be used
+pub fn validate_sched_config(scx_name: &str, sched_config: &Sched) -> Result<SupportedSched> {
+    let scx_sched = SupportedSched::from_str(scx_name)?;
+
+    if let Some(path) = &sched_config.path {
+        if !Path::new(path).is_file() {
+            anyhow::bail!("the binary {path} does not exist");
+        }
+    }
+
+    Ok(scx_sched)
+}
+
+/// Get all schedulers supported with the given config, the built-in schedulers
+/// followed by the valid custom schedulers declared in the config
+pub fn get_supported_scheds(config: &Config) -> Vec<SupportedSched> {
+    let mut custom_scheds = config
+        .scheds
+        .iter()
+        .filter_map(|(scx_name, sched_config)| validate_sched_config(scx_name, sched_config).ok())
+        .filter(|scx_sched| matches!(scx_sched, SupportedSched::Custom(_)))
+        .collect::<Vec<_>>();
+    custom_scheds.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    SupportedSched::BUILTIN
+        .into_iter()
+        .chain(custom_scheds)
+        .collect()
+}
+
+/// Get the binary to execute for the given scheduler
+pub fn get_sched_binary(config: &Config, scx_sched: &SupportedSched) -> String {
+    config
+        .scheds
+        .get(scx_sched.as_str())
+        .and_then(|sched_config| sched_config.path.clone())
+        .unwrap_or_else(|| scx_sched.as_str().to_owned())
+}
+
 pub fn get_default_config() -> Config {
-    let supported_scheds = [
-        SupportedSched::Bpfland,
-        SupportedSched::Rusty,
-        SupportedSched::Lavd,
-        SupportedSched::Flash,
-        SupportedSched::P2DQ,
-        SupportedSched::Tickless,
-        SupportedSched::Rustland,
-        SupportedSched::Cosmos,
-    ];
-    let scheds_map = HashMap::from(supported_scheds.map(|x| init_default_config_entry(x)));
+    let scheds_map = HashMap::from(SupportedSched::BUILTIN.map(init_default_config_entry));
     Config {
         default_sched: None,
         default_mode: Some(SchedMode::Auto),
         scheds: scheds_map,
+        rules: vec![],
+        restart_policy: RestartPolicy::default(),
+        power_policy: PowerPolicy::default(),
     }
 }
 
@@ -101,8 +262,7 @@ pub fn get_scx_flags_for_mode(
     scx_sched: &SupportedSched,
     sched_mode: SchedMode,
 ) -> Vec<String> {
-    let scx_name: &str = scx_sched.clone().into();
-    if let Some(sched_config) = config.scheds.get(scx_name) {
+    if let Some(sched_config) = config.scheds.get(scx_sched.as_str()) {
         let scx_flags = extract_scx_flags_from_config(sched_config, &sched_mode);
 
         // try to exact flags from config, otherwise fallback to hardcoded default
@@ -120,6 +280,95 @@ pub fn get_scx_flags_for_mode(
     }
 }
 
+/// Get the scx flags for the mode name, which is either a built-in or a custom mode.
+/// Returns None if the scheduler has no mode with this name.
+pub fn get_scx_flags_for_mode_name(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    mode_name: &str,
+) -> Option<Vec<String>> {
+    match SchedMode::from_str(mode_name) {
+        Ok(sched_mode) => Some(get_scx_flags_for_mode(config, scx_sched, sched_mode)),
+        Err(_) => get_scx_flags_for_custom_mode(config, scx_sched, mode_name),
+    }
+}
+
+/// Describe the differences between two configs, one line per changed setting
+pub fn diff_config(old_config: &Config, new_config: &Config) -> Vec<String> {
+    let mut changes = vec![];
+
+    if old_config.default_sched != new_config.default_sched {
+        changes.push(format!(
+            "default_sched: {:?} -> {:?}",
+            old_config
+                .default_sched
+                .as_ref()
+                .map(SupportedSched::as_str),
+            new_config
+                .default_sched
+                .as_ref()
+                .map(SupportedSched::as_str),
+        ));
+    }
+    if old_config.default_mode != new_config.default_mode {
+        changes.push(format!(
+            "default_mode: {:?} -> {:?}",
+            old_config.default_mode, new_config.default_mode
+        ));
+    }
+
+    let mut scx_names = old_config
+        .scheds
+        .keys()
+        .chain(new_config.scheds.keys())
+        .collect::<Vec<_>>();
+    scx_names.sort();
+    scx_names.dedup();
+    for scx_name in scx_names {
+        match (
+            old_config.scheds.get(scx_name),
+            new_config.scheds.get(scx_name),
+        ) {
+            (None, Some(_)) => changes.push(format!("scheds.{scx_name}: added")),
+            (Some(_), None) => changes.push(format!("scheds.{scx_name}: removed")),
+            (Some(old_sched), Some(new_sched)) if old_sched != new_sched => {
+                changes.push(format!("scheds.{scx_name}: changed"))
+            }
+            _ => {}
+        }
+    }
+
+    if old_config.rules != new_config.rules {
+        changes.push(format!(
+            "rules: {} -> {} rule(s)",
+            old_config.rules.len(),
+            new_config.rules.len()
+        ));
+    }
+    if old_config.restart_policy != new_config.restart_policy {
+        changes.push("restart_policy: changed".to_owned());
+    }
+    if old_config.power_policy != new_config.power_policy {
+        changes.push("power_policy: changed".to_owned());
+    }
+
+    changes
+}
+
+/// Get the scx flags for the given custom mode, if the scheduler defines such mode
+pub fn get_scx_flags_for_custom_mode(
+    config: &Config,
+    scx_sched: &SupportedSched,
+    mode_name: &str,
+) -> Option<Vec<String>> {
+    config
+        .scheds
+        .get(scx_sched.as_str())?
+        .modes
+        .get(mode_name)
+        .cloned()
+}
+
 /// Extract the scx flags from config
 fn extract_scx_flags_from_config(
     sched_config: &Sched,
@@ -137,6 +386,7 @@ fn extract_scx_flags_from_config(
 /// Get Sched object for configuration object
 fn get_default_sched_for_config(scx_sched: &SupportedSched) -> Sched {
     Sched {
+        path: None,
         auto_mode: Some(
             get_default_scx_flags_for_mode(scx_sched, SchedMode::Auto)
                 .into_iter()
@@ -167,6 +417,7 @@ fn get_default_sched_for_config(scx_sched: &SupportedSched) -> Sched {
                 .map(String::from)
                 .collect(),
         ),
+        modes: HashMap::new(),
     }
 }
 
@@ -228,16 +479,15 @@ fn get_default_scx_flags_for_mode(scx_sched: &SupportedSched, sched_mode: SchedM
         SupportedSched::Rustland => vec![],
         // scx_cosmos doesn't support any of these modes
         SupportedSched::Cosmos => vec![],
+        // custom schedulers get their flags from the config only
+        SupportedSched::Custom(_) => vec![],
     }
 }
 
 /// Initializes entry for config sched map
 fn init_default_config_entry(scx_sched: SupportedSched) -> (String, Sched) {
     let default_modes = get_default_sched_for_config(&scx_sched);
-    (
-        <SupportedSched as Into<&str>>::into(scx_sched).to_owned(),
-        default_modes,
-    )
+    (scx_sched.as_str().to_owned(), default_modes)
 }
 
 #[cfg(test)]
@@ -361,6 +611,239 @@ auto_mode = ["--help"]
         );
     }
 
+    #[test]
+    fn test_rules_config() {
+        let config_str = r#"
+default_sched = "scx_bpfland"
+
+[[rules]]
+comm = "cs2"
+sched = "scx_lavd"
+mode = "Gaming"
+
+[[rules]]
+cmdline = "make -j"
+cgroup = "/user.slice"
+sched = "scx_flash"
+"#;
+
+        let parsed_config = parse_config_content(config_str).expect("Failed to parse config");
+
+        assert_eq!(
+            parsed_config.rules,
+            vec![
+                Rule {
+                    sched: SupportedSched::Lavd,
+                    mode: Some(SchedMode::Gaming),
+                    comm: Some("cs2".to_owned()),
+                    cmdline: None,
+                    cgroup: None,
+                },
+                Rule {
+                    sched: SupportedSched::Flash,
+                    mode: None,
+                    comm: None,
+                    cmdline: Some("make -j".to_owned()),
+                    cgroup: Some("/user.slice".to_owned()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restart_policy_config() {
+        let config_str = r#"
+default_mode = "Auto"
+
+[restart_policy]
+max_restarts = 3
+backoff_ms = 1000
+fallback_sched = "scx_bpfland"
+"#;
+
+        let parsed_config = parse_config_content(config_str).expect("Failed to parse config");
+        let restart_policy = &parsed_config.restart_policy;
+
+        assert_eq!(restart_policy.max_restarts, 3);
+        assert_eq!(restart_policy.max_backoff_ms, 30_000);
+        assert_eq!(restart_policy.fallback_sched, Some(SupportedSched::Bpfland));
+        assert_eq!(restart_policy.fallback_mode, None);
+
+        assert_eq!(restart_policy.backoff(1), Duration::from_millis(1000));
+        assert_eq!(restart_policy.backoff(3), Duration::from_millis(4000));
+        assert_eq!(restart_policy.backoff(10), Duration::from_millis(30_000));
+        assert_eq!(
+            restart_policy.backoff(u32::MAX),
+            Duration::from_millis(30_000)
+        );
+    }
+
+    #[test]
+    fn test_custom_modes_config() {
+        let config_str = r#"
+default_mode = "Auto"
+
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "performance"]
+
+[scheds.scx_bpfland.modes]
+build-farm = ["-p", "-s", "20000"]
+latency-batch = ["-s", "5000"]
+"#;
+
+        let parsed_config = parse_config_content(config_str).expect("Failed to parse config");
+
+        let bpfland_flags =
+            get_scx_flags_for_custom_mode(&parsed_config, &SupportedSched::Bpfland, "build-farm");
+        assert_eq!(
+            bpfland_flags,
+            Some(vec!["-p".to_owned(), "-s".to_owned(), "20000".to_owned()])
+        );
+
+        let bpfland_flags =
+            get_scx_flags_for_mode(&parsed_config, &SupportedSched::Bpfland, SchedMode::Gaming);
+        assert_eq!(bpfland_flags, vec!["-m", "performance"]);
+
+        assert_eq!(
+            get_scx_flags_for_custom_mode(&parsed_config, &SupportedSched::Bpfland, "gaming"),
+            None
+        );
+        assert_eq!(
+            get_scx_flags_for_custom_mode(&parsed_config, &SupportedSched::Lavd, "build-farm"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_custom_scheds_config() {
+        let config_str = r#"
+default_sched = "scx_layered"
+
+[scheds.scx_layered]
+path = "/bin/sh"
+auto_mode = ["--run-example"]
+
+[scheds.scx_inhouse]
+gaming_mode = ["--fast"]
+
+[scheds.scx_missing]
+path = "/nonexistent/scx_missing"
+
+[scheds.not_a_scheduler]
+auto_mode = []
+"#;
+
+        let parsed_config = parse_config_content(config_str).expect("Failed to parse config");
+        let scx_layered = SupportedSched::Custom("scx_layered".to_owned());
+        let scx_inhouse = SupportedSched::Custom("scx_inhouse".to_owned());
+
+        assert_eq!(parsed_config.default_sched, Some(scx_layered.clone()));
+
+        let supported_scheds = get_supported_scheds(&parsed_config);
+        assert_eq!(supported_scheds.len(), SupportedSched::BUILTIN.len() + 2);
+        assert_eq!(
+            supported_scheds[SupportedSched::BUILTIN.len()..],
+            [scx_inhouse.clone(), scx_layered.clone()]
+        );
+
+        assert_eq!(get_sched_binary(&parsed_config, &scx_layered), "/bin/sh");
+        assert_eq!(
+            get_sched_binary(&parsed_config, &scx_inhouse),
+            "scx_inhouse"
+        );
+        assert_eq!(
+            get_sched_binary(&parsed_config, &SupportedSched::Lavd),
+            "scx_lavd"
+        );
+
+        assert_eq!(
+            get_scx_flags_for_mode(&parsed_config, &scx_layered, SchedMode::Auto),
+            vec!["--run-example"]
+        );
+        assert_eq!(
+            get_scx_flags_for_mode(&parsed_config, &scx_inhouse, SchedMode::Gaming),
+            vec!["--fast"]
+        );
+        assert!(get_scx_flags_for_mode(&parsed_config, &scx_inhouse, SchedMode::Auto).is_empty());
+    }
+
+    #[test]
+    fn test_power_policy_config() {
+        let config_str = r#"
+[power_policy]
+enabled = true
+performance = "LowLatency"
+on_battery = "PowerSave"
+"#;
+
+        let parsed_config = parse_config_content(config_str).expect("Failed to parse config");
+        let power_policy = parsed_config.power_policy;
+        assert!(power_policy.enabled);
+        assert_eq!(power_policy.power_saver, Some(SchedMode::PowerSave));
+        assert_eq!(power_policy.balanced, Some(SchedMode::Auto));
+        assert_eq!(power_policy.performance, Some(SchedMode::LowLatency));
+        assert_eq!(power_policy.on_battery, Some(SchedMode::PowerSave));
+
+        let parsed_config =
+            parse_config_content("default_mode = \"Auto\"").expect("Failed to parse config");
+        assert!(!parsed_config.power_policy.enabled);
+    }
+
+    #[test]
+    fn test_diff_config() {
+        let old_config = parse_config_content(
+            r#"
+default_sched = "scx_bpfland"
+
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "performance"]
+
+[scheds.scx_lavd]
+gaming_mode = ["--performance"]
+"#,
+        )
+        .expect("Failed to parse config");
+        let new_config = parse_config_content(
+            r#"
+default_sched = "scx_lavd"
+default_mode = "Gaming"
+
+[scheds.scx_bpfland]
+gaming_mode = ["-m", "all"]
+
+[scheds.scx_flash]
+gaming_mode = ["-m", "all"]
+
+[[rules]]
+sched = "scx_lavd"
+comm = "cs2"
+"#,
+        )
+        .expect("Failed to parse config");
+
+        assert!(diff_config(&old_config, &old_config).is_empty());
+        assert_eq!(
+            diff_config(&old_config, &new_config),
+            vec![
+                r#"default_sched: Some("scx_bpfland") -> Some("scx_lavd")"#,
+                "default_mode: None -> Some(Gaming)",
+                "scheds.scx_bpfland: changed",
+                "scheds.scx_flash: added",
+                "scheds.scx_lavd: removed",
+                "rules: 0 -> 1 rule(s)",
+            ]
+        );
+
+        assert_eq!(
+            get_scx_flags_for_mode_name(&new_config, &SupportedSched::Bpfland, "gaming"),
+            Some(vec!["-m".to_owned(), "all".to_owned()])
+        );
+        assert_eq!(
+            get_scx_flags_for_mode_name(&new_config, &SupportedSched::Bpfland, "build-farm"),
+            None
+        );
+    }
+
     #[test]
     fn test_empty_config() {
         let config_str = "";
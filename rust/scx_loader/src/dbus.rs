@@ -24,9 +24,35 @@ pub trait LoaderClient {
         scx_args: &[String],
     ) -> zbus::Result<()>;
 
+    /// Starts the specified scheduler with the given mode name. The mode is
+    /// either one of the built-in modes (e.g. "gaming") or a custom mode
+    /// defined in the config.
+    fn start_scheduler_with_mode_name(
+        &self,
+        scx_name: SupportedSched,
+        mode_name: &str,
+    ) -> zbus::Result<()>;
+
     /// Stops the currently running scheduler.
     fn stop_scheduler(&self) -> zbus::Result<()>;
 
+    /// Validates the config file and the flags of every scheduler mode
+    /// against the options of the installed schedulers. Returns the list of
+    /// found errors, followed by the warnings about options not shown in the
+    /// help of the scheduler, prefixed with "warning: ". The list is empty for
+    /// a valid config.
+    fn validate_config(&self) -> zbus::Result<Vec<String>>;
+
+    /// Re-reads the config file and applies it without restarting the
+    /// daemon. The running scheduler is restarted if its flags changed.
+    /// Returns the changes and the validation errors of the new config, a
+    /// config with errors is not applied and no changes are returned.
+    fn reload_config(&self) -> zbus::Result<(Vec<String>, Vec<String>)>;
+
+    /// Returns the last lines printed by the schedulers, each tagged with the
+    /// scheduler and mode name. A `max_lines` of 0 returns all kept lines.
+    fn get_scheduler_log(&self, max_lines: u32) -> zbus::Result<Vec<String>>;
+
     /// Method for switching to the specified scheduler with the given mode.
     /// This method will stop the currently running scheduler (if any) and
     /// then start the new scheduler.
@@ -42,6 +68,15 @@ pub trait LoaderClient {
         scx_args: &[String],
     ) -> zbus::Result<()>;
 
+    /// Switches to the specified scheduler with the given mode name. The mode
+    /// is either one of the built-in modes (e.g. "gaming") or a custom mode
+    /// defined in the config.
+    fn switch_scheduler_with_mode_name(
+        &self,
+        scx_name: SupportedSched,
+        mode_name: &str,
+    ) -> zbus::Result<()>;
+
     /// The name of the currently running scheduler. If no scheduler is active,
     /// this property will be set to "unknown".
     #[zbus(property)]
@@ -50,13 +85,59 @@ pub trait LoaderClient {
     /// The currently active scheduler mode.  Scheduler modes allow you to
     /// apply pre-defined configurations to a scheduler that are
     /// optimized for different use cases. If no scheduler is active,
-    /// this property will be set to 0 (Auto).
+    /// this property will be set to 0 (Auto). Custom modes are reported as
+    /// 0 (Auto) as well, see `scheduler_mode_name` for their name.
     #[zbus(property)]
     fn scheduler_mode(&self) -> zbus::Result<SchedMode>;
 
-    /// A list of the schedulers currently supported by the Scheduler Loader.
-    /// The names of the supported schedulers will be listed as strings in
-    /// this array.
+    /// The name of the currently active scheduler mode. This is either the
+    /// name of a built-in mode (e.g. "gaming") or of a custom mode defined
+    /// in the config.
+    #[zbus(property)]
+    fn scheduler_mode_name(&self) -> zbus::Result<String>;
+
+    /// A list of the schedulers currently supported by the Scheduler Loader,
+    /// including the custom schedulers declared in the config. The names of
+    /// the supported schedulers will be listed as strings in this array.
     #[zbus(property)]
     fn supported_schedulers(&self) -> zbus::Result<Vec<String>>;
+
+    /// The number of times the current scheduler was restarted after it
+    /// failed. The counter is reset whenever a scheduler is started or
+    /// switched to.
+    #[zbus(property)]
+    fn restart_count(&self) -> zbus::Result<u32>;
+
+    /// The time the current scheduler was started, in seconds since the Unix
+    /// epoch. Restarts after a failure don't reset it. If no scheduler is
+    /// active, this property will be set to 0.
+    #[zbus(property)]
+    fn started_at(&self) -> zbus::Result<u64>;
+
+    /// The exit reason of the last scheduler which exited on its own, as
+    /// reported by the scheduler (e.g. "runnable task stall (...)"). If no
+    /// scheduler has exited yet, this property will be an empty string.
+    #[zbus(property)]
+    fn last_exit_reason(&self) -> zbus::Result<String>;
+
+    /// Emitted when a scheduler is started, either on request or as the
+    /// fallback for a failed scheduler.
+    #[zbus(signal)]
+    fn scheduler_started(&self, scx_name: String, sched_mode: SchedMode) -> zbus::Result<()>;
+
+    /// Emitted when the running scheduler is stopped, either on request or
+    /// because it exited on its own.
+    #[zbus(signal)]
+    fn scheduler_stopped(&self, scx_name: String) -> zbus::Result<()>;
+
+    /// Emitted when the running scheduler fails. `will_restart` tells
+    /// whether the scheduler is going to be restarted according to the
+    /// restart policy.
+    #[zbus(signal)]
+    fn scheduler_crashed(
+        &self,
+        scx_name: String,
+        exit_reason: String,
+        will_restart: bool,
+    ) -> zbus::Result<()>;
 }
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2025 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fmt;
+
+/// The kind of the scheduler exit, mirrors the kernel `scx_exit_kind`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitKind {
+    /// Unregistered from user space, e.g. the scheduler was stopped
+    Unreg,
+    /// Unregistered by the BPF scheduler itself
+    UnregBPF,
+    /// Unregistered by the kernel, e.g. on CPU hotplug
+    UnregKern,
+    /// Disabled by the sysrq-S key combination
+    SysRq,
+    /// Runtime error in the kernel
+    Error,
+    /// Error triggered by the BPF scheduler via scx_bpf_error()
+    ErrorBPF,
+    /// Watchdog detected a runnable task stall
+    ErrorStall,
+    /// Reason string is not known to us
+    Unknown,
+}
+
+/// Exit information printed by the scheduler through `UserExitInfo::report()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitInfo {
+    pub kind: ExitKind,
+    pub reason: String,
+    pub msg: Option<String>,
+}
+
+impl ExitKind {
+    /// Get the exit kind for the reason string reported by the kernel
+    pub fn from_reason(reason: &str) -> ExitKind {
+        match reason {
+            "unregistered from user space" => ExitKind::Unreg,
+            "unregistered from BPF" => ExitKind::UnregBPF,
+            "unregistered from the main kernel" => ExitKind::UnregKern,
+            "disabled by sysrq-S" => ExitKind::SysRq,
+            "runtime error" => ExitKind::Error,
+            "scx_bpf_error" => ExitKind::ErrorBPF,
+            "runnable task stall" => ExitKind::ErrorStall,
+            _ => ExitKind::Unknown,
+        }
+    }
+}
+
+impl ExitInfo {
+    /// Parse the "EXIT: reason (msg)" line printed by the scheduler on exit.
+    /// The line may be prefixed, e.g. by "Error: " when the exit was an error.
+    pub fn parse(line: &str) -> Option<ExitInfo> {
+        let (_, exit_str) = line.split_once("EXIT: ")?;
+        let exit_str = exit_str.trim_end();
+
+        let (reason, msg) = match exit_str.split_once(" (") {
+            Some((reason, msg)) if msg.ends_with(')') => {
+                (reason, Some(msg[..msg.len() - 1].to_owned()))
+            }
+            _ => (exit_str, None),
+        };
+        if reason.is_empty() {
+            return None;
+        }
+
+        Some(ExitInfo {
+            kind: ExitKind::from_reason(reason),
+            reason: reason.to_owned(),
+            msg,
+        })
+    }
+
+    /// Check whether restarting the scheduler after this exit makes sense.
+    /// Disabling sched_ext with sysrq-S is an explicit request of the user,
+    /// so the scheduler must not be brought back.
+    pub fn should_restart(&self) -> bool {
+        self.kind != ExitKind::SysRq
+    }
+}
+
+impl fmt::Display for ExitInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.msg {
+            Some(msg) => write!(f, "{} ({})", self.reason, msg),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exit_info::*;
+
+    #[test]
+    fn test_parse_exit_info() {
+        let exit_info = ExitInfo::parse("EXIT: unregistered from user space").unwrap();
+        assert_eq!(exit_info.kind, ExitKind::Unreg);
+        assert_eq!(exit_info.msg, None);
+        assert!(exit_info.should_restart());
+
+        let exit_info = ExitInfo::parse(
+            "Error: EXIT: runnable task stall (kworker/0:1[42] failed to run for 30.5s)",
+        )
+        .unwrap();
+        assert_eq!(exit_info.kind, ExitKind::ErrorStall);
+        assert_eq!(exit_info.reason, "runnable task stall");
+        assert_eq!(
+            exit_info.msg.as_deref(),
+            Some("kworker/0:1[42] failed to run for 30.5s")
+        );
+        assert_eq!(
+            exit_info.to_string(),
+            "runnable task stall (kworker/0:1[42] failed to run for 30.5s)"
+        );
+
+        let exit_info = ExitInfo::parse("EXIT: disabled by sysrq-S\n").unwrap();
+        assert_eq!(exit_info.kind, ExitKind::SysRq);
+        assert!(!exit_info.should_restart());
+
+        let exit_info = ExitInfo::parse("EXIT: something new").unwrap();
+        assert_eq!(exit_info.kind, ExitKind::Unknown);
+        assert!(exit_info.should_restart());
+
+        assert_eq!(ExitInfo::parse("09:15:02 [INFO] cpus: 16"), None);
+        assert_eq!(ExitInfo::parse("EXIT: "), None);
+    }
+}
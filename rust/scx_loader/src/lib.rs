@@ -7,35 +7,69 @@
 
 pub mod config;
 pub mod dbus;
+pub mod exit_info;
+pub mod power;
+pub mod rules;
+pub mod sched_log;
+pub mod validate;
 
+use std::fmt;
 use std::str::FromStr;
 
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
 use zvariant::OwnedValue;
 use zvariant::Type;
 use zvariant::Value;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+/// Prefix every scheduler name has to start with
+pub const SCHED_PREFIX: &str = "scx_";
+
+#[derive(Debug, Clone, PartialEq, Type)]
 #[zvariant(signature = "s")]
-#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
 pub enum SupportedSched {
-    #[serde(rename = "scx_bpfland")]
     Bpfland,
-    #[serde(rename = "scx_rusty")]
     Rusty,
-    #[serde(rename = "scx_lavd")]
     Lavd,
-    #[serde(rename = "scx_flash")]
     Flash,
-    #[serde(rename = "scx_p2dq")]
     P2DQ,
-    #[serde(rename = "scx_tickless")]
     Tickless,
-    #[serde(rename = "scx_rustland")]
     Rustland,
-    #[serde(rename = "scx_cosmos")]
     Cosmos,
+    /// Scheduler declared in the config, e.g. "scx_layered"
+    Custom(String),
+}
+
+impl SupportedSched {
+    /// Schedulers which are supported without being declared in the config
+    pub const BUILTIN: [SupportedSched; 8] = [
+        SupportedSched::Bpfland,
+        SupportedSched::Cosmos,
+        SupportedSched::Flash,
+        SupportedSched::Lavd,
+        SupportedSched::P2DQ,
+        SupportedSched::Tickless,
+        SupportedSched::Rustland,
+        SupportedSched::Rusty,
+    ];
+
+    /// Get the name of the scheduler
+    pub fn as_str(&self) -> &str {
+        match self {
+            SupportedSched::Bpfland => "scx_bpfland",
+            SupportedSched::Cosmos => "scx_cosmos",
+            SupportedSched::Flash => "scx_flash",
+            SupportedSched::Lavd => "scx_lavd",
+            SupportedSched::P2DQ => "scx_p2dq",
+            SupportedSched::Tickless => "scx_tickless",
+            SupportedSched::Rustland => "scx_rustland",
+            SupportedSched::Rusty => "scx_rusty",
+            SupportedSched::Custom(scx_name) => scx_name,
+        }
+    }
 }
 
 impl FromStr for SupportedSched {
@@ -51,6 +85,9 @@ impl FromStr for SupportedSched {
             "scx_tickless" => Ok(SupportedSched::Tickless),
             "scx_rustland" => Ok(SupportedSched::Rustland),
             "scx_rusty" => Ok(SupportedSched::Rusty),
+            _ if scx_name.len() > SCHED_PREFIX.len() && scx_name.starts_with(SCHED_PREFIX) => {
+                Ok(SupportedSched::Custom(scx_name.to_owned()))
+            }
             _ => Err(anyhow::anyhow!("{scx_name} is not supported")),
         }
     }
@@ -63,18 +100,30 @@ impl TryFrom<&str> for SupportedSched {
     }
 }
 
-impl From<SupportedSched> for &str {
-    fn from(scx_name: SupportedSched) -> Self {
-        match scx_name {
-            SupportedSched::Bpfland => "scx_bpfland",
-            SupportedSched::Cosmos => "scx_cosmos",
-            SupportedSched::Flash => "scx_flash",
-            SupportedSched::Lavd => "scx_lavd",
-            SupportedSched::P2DQ => "scx_p2dq",
-            SupportedSched::Tickless => "scx_tickless",
-            SupportedSched::Rustland => "scx_rustland",
-            SupportedSched::Rusty => "scx_rusty",
-        }
+/// Deprecated, use `SupportedSched::as_str()` instead. Kept for compatibility with the former
+/// `From<SupportedSched> for &str`.
+impl<'a> From<&'a SupportedSched> for &'a str {
+    fn from(scx_name: &'a SupportedSched) -> Self {
+        scx_name.as_str()
+    }
+}
+
+impl fmt::Display for SupportedSched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for SupportedSched {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SupportedSched {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let scx_name = String::deserialize(deserializer)?;
+        SupportedSched::from_str(&scx_name).map_err(serde::de::Error::custom)
     }
 }
 
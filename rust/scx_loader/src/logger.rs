@@ -6,6 +6,8 @@
 // GNU General Public License version 2.
 
 use std::env;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 
 use colored::Colorize;
 use log::Level;
@@ -16,6 +18,12 @@ struct SimpleLogger;
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
+/// Whether stdout is connected to the systemd journal
+static JOURNAL_STREAM: AtomicBool = AtomicBool::new(false);
+
+/// Target of the log records forwarded from the scheduler output
+pub const SCHED_TARGET: &str = "scx_sched";
+
 impl log::Log for SimpleLogger {
     fn enabled(&self, _: &Metadata) -> bool {
         true
@@ -30,7 +38,16 @@ impl log::Log for SimpleLogger {
                 Level::Debug => "[DEBUG]".white(),
                 Level::Trace => "[TRACE]".black(),
             };
-            println!("{level_str}: {}", record.args());
+            if JOURNAL_STREAM.load(Ordering::Relaxed) {
+                // let journald pick up the priority of the record
+                println!(
+                    "<{}>{level_str}: {}",
+                    syslog_priority(record.level()),
+                    record.args()
+                );
+            } else {
+                println!("{level_str}: {}", record.args());
+            }
         }
     }
 
@@ -40,6 +57,21 @@ impl log::Log for SimpleLogger {
     }
 }
 
+/// Get the syslog priority of the log level, as understood by journald
+fn syslog_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Log the line printed by the scheduler, tagged with the scheduler and mode name
+pub fn log_sched_line(scx_name: &str, mode_name: &str, level: Level, line: &str) {
+    log::log!(target: SCHED_TARGET, level, "{scx_name}[{mode_name}]: {line}");
+}
+
 pub fn init_logger() -> Result<(), log::SetLoggerError> {
     // set log level
     let max_log_level = if let Ok(env_log) = env::var("RUST_LOG") {
@@ -55,5 +87,8 @@ pub fn init_logger() -> Result<(), log::SetLoggerError> {
         log::LevelFilter::Info
     };
 
+    // systemd sets JOURNAL_STREAM when stdout is connected to the journal
+    JOURNAL_STREAM.store(env::var_os("JOURNAL_STREAM").is_some(), Ordering::Relaxed);
+
     log::set_logger(&LOGGER).map(|()| log::set_max_level(max_log_level))
 }
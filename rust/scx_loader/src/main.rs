@@ -8,25 +8,50 @@
 mod logger;
 
 use scx_loader::dbus::LoaderClientProxy;
+use scx_loader::exit_info::ExitInfo;
+use scx_loader::power;
+use scx_loader::power::PowerProfilesProxy;
+use scx_loader::sched_log;
+use scx_loader::sched_log::SchedLog;
 use scx_loader::*;
 
 use std::process::ExitStatus;
 use std::process::Stdio;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
+use log::Level;
 use sysinfo::System;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::BufReader;
 use tokio::process::Child;
 use tokio::process::Command;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use zbus::interface;
+use zbus::object_server::InterfaceRef;
+use zbus::object_server::SignalEmitter;
 use zbus::Connection;
 
+const LOADER_PATH: &str = "/org/scx/Loader";
+
+/// Interval of checking the power profile and AC state for the power policy
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Number of scheduler output lines kept for GetSchedulerLog
+const SCHED_LOG_LINES: usize = 1000;
+
 #[derive(Debug, PartialEq)]
 enum ScxMessage {
     /// Quit the scx_loader
@@ -36,27 +61,72 @@ enum ScxMessage {
     /// Start the scheduler with the given mode
     StartSched((SupportedSched, SchedMode)),
     /// Start the scheduler with the given scx arguments
-    StartSchedArgs((SupportedSched, Vec<String>)),
+    StartSchedArgs((SchedInfo, Vec<String>)),
     /// Switch to another scheduler with the given mode
     SwitchSched((SupportedSched, SchedMode)),
     /// Switch to another scheduler with the given scx arguments
-    SwitchSchedArgs((SupportedSched, Vec<String>)),
+    SwitchSchedArgs((SchedInfo, Vec<String>)),
+    /// Apply the reloaded and validated config
+    ApplyConfig(Arc<config::Config>),
 }
 
 #[derive(Debug, PartialEq)]
 enum RunnerMessage {
     /// Switch to another scheduler with the given scx arguments
-    Switch((SupportedSched, Vec<String>)),
+    Switch((SchedInfo, Vec<String>)),
     /// Start the scheduler with the given scx arguments
-    Start((SupportedSched, Vec<String>)),
+    Start((SchedInfo, Vec<String>)),
     /// Stop the scheduler, if any
     Stop,
 }
 
+#[derive(Debug, PartialEq)]
+enum RunnerEvent {
+    /// The scheduler was spawned and is running
+    Started(SchedInfo),
+    /// The scheduler was stopped on request
+    Stopped,
+    /// The scheduler exited and won't be restarted, with the exit reason
+    Exited((SchedInfo, String)),
+    /// The scheduler failed and is going to be restarted, with the restart count and exit reason
+    Restarting((SchedInfo, u32, String)),
+    /// The scheduler failed and ran out of restarts, with the exit reason
+    Failed((SchedInfo, String)),
+}
+
+/// Scheduler started by the runner, the loader state is updated once it actually runs
+#[derive(Debug, Clone, PartialEq)]
+struct SchedInfo {
+    scx_sched: SupportedSched,
+    sched_mode: SchedMode,
+    mode_name: String,
+    /// Whether the scheduler runs with arguments of the caller instead of a mode
+    custom_args: bool,
+}
+
 struct ScxLoader {
     current_scx: Option<SupportedSched>,
     current_mode: SchedMode,
+    current_mode_name: String,
+    restart_count: u32,
+    last_exit_reason: String,
+    /// When the current scheduler was started, None if none is running
+    started_at: Option<SystemTime>,
+    /// Whether the scheduler was started with arguments of the caller instead of a mode
+    custom_args: bool,
     channel: UnboundedSender<ScxMessage>,
+    config: Arc<config::Config>,
+    /// Publishes the reloaded config to the worker, runner and rules monitor
+    config_tx: watch::Sender<Arc<config::Config>>,
+    sched_log: Arc<Mutex<SchedLog>>,
+}
+
+/// Forwards the output of the scheduler to the log, tagged with the scheduler and mode name
+#[derive(Clone)]
+struct SchedOutput {
+    scx_name: String,
+    mode_name: String,
+    sched_log: Arc<Mutex<SchedLog>>,
 }
 
 #[derive(Parser, Debug)]
@@ -66,13 +136,154 @@ struct Args {
     auto: bool,
 }
 
+impl SchedInfo {
+    /// Scheduler running with the flags of a built-in mode
+    fn with_mode(scx_sched: SupportedSched, sched_mode: SchedMode) -> Self {
+        let mode_name: &str = sched_mode.clone().into();
+        Self {
+            scx_sched,
+            mode_name: mode_name.to_owned(),
+            sched_mode,
+            custom_args: false,
+        }
+    }
+}
+
+impl ScxLoader {
+    /// Get name of the currently running scheduler, "unknown" if none is running
+    fn current_scx_name(&self) -> &str {
+        self.current_scx
+            .as_ref()
+            .map_or("unknown", SupportedSched::as_str)
+    }
+
+    /// Check whether the scheduler is supported with the current config
+    fn validate_sched(&self, scx_name: &SupportedSched) -> zbus::fdo::Result<()> {
+        if !config::get_supported_scheds(&self.config).contains(scx_name) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "{scx_name} is not supported"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Get the scx arguments for the custom mode of the scheduler
+    fn custom_mode_args(
+        &self,
+        scx_name: &SupportedSched,
+        mode_name: &str,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        config::get_scx_flags_for_custom_mode(&self.config, scx_name, mode_name).ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!("{mode_name} is not a mode of {scx_name}"))
+        })
+    }
+
+    /// Set the newly started scheduler and notify clients about the change
+    async fn set_scheduler_started(
+        &mut self,
+        emitter: &SignalEmitter<'_>,
+        sched_info: SchedInfo,
+    ) -> zbus::Result<()> {
+        // the previous scheduler gets stopped on switch
+        if self.current_scx.is_some() {
+            Self::scheduler_stopped(emitter, self.current_scx_name()).await?;
+        }
+
+        self.current_scx = Some(sched_info.scx_sched);
+        self.current_mode = sched_info.sched_mode;
+        self.current_mode_name = sched_info.mode_name;
+        self.custom_args = sched_info.custom_args;
+        self.restart_count = 0;
+        self.started_at = Some(SystemTime::now());
+
+        Self::scheduler_started(emitter, self.current_scx_name(), self.current_mode.clone())
+            .await?;
+        self.current_scheduler_changed(emitter).await?;
+        self.scheduler_mode_changed(emitter).await?;
+        self.scheduler_mode_name_changed(emitter).await?;
+        self.restart_count_changed(emitter).await?;
+        self.started_at_changed(emitter).await
+    }
+
+    /// Unset the stopped scheduler and notify clients about the change
+    async fn set_scheduler_stopped(&mut self, emitter: &SignalEmitter<'_>) -> zbus::Result<()> {
+        if self.current_scx.is_none() {
+            return Ok(());
+        }
+
+        Self::scheduler_stopped(emitter, self.current_scx_name()).await?;
+        self.current_scx = None;
+        self.started_at = None;
+        self.current_scheduler_changed(emitter).await?;
+        self.started_at_changed(emitter).await
+    }
+
+    /// Apply the reloaded config, restarting the scheduler if its flags changed
+    async fn apply_config(
+        &mut self,
+        emitter: &SignalEmitter<'_>,
+        new_config: Arc<config::Config>,
+    ) -> Result<()> {
+        let changes = config::diff_config(&self.config, &new_config);
+        if changes.is_empty() {
+            log::info!("Config reloaded, nothing changed");
+            return Ok(());
+        }
+        log::info!("Config reloaded: {}", changes.join(", "));
+
+        let old_config = std::mem::replace(&mut self.config, new_config.clone());
+        self.config_tx.send_replace(new_config.clone());
+        self.supported_schedulers_changed(emitter).await?;
+
+        // restart the scheduler, if it runs with the flags from the config and those changed
+        let Some(scx_name) = self.current_scx.clone() else {
+            return Ok(());
+        };
+        if self.custom_args {
+            return Ok(());
+        }
+        let mode_name = self.current_mode_name.clone();
+        let old_flags = (
+            config::get_sched_binary(&old_config, &scx_name),
+            config::get_scx_flags_for_mode_name(&old_config, &scx_name, &mode_name),
+        );
+        let new_flags = (
+            config::get_sched_binary(&new_config, &scx_name),
+            config::get_scx_flags_for_mode_name(&new_config, &scx_name, &mode_name),
+        );
+        if old_flags == new_flags {
+            return Ok(());
+        }
+
+        match new_flags.1 {
+            Some(scx_args) => {
+                log::info!("Restarting {scx_name:?} with mode {mode_name:?} for the new flags..");
+                let sched_info = SchedInfo {
+                    scx_sched: scx_name,
+                    sched_mode: self.current_mode.clone(),
+                    mode_name,
+                    custom_args: false,
+                };
+                let _ = self
+                    .channel
+                    .send(ScxMessage::SwitchSchedArgs((sched_info, scx_args)));
+            }
+            None => {
+                log::warn!("Mode {mode_name:?} of {scx_name:?} was removed, keep it running");
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[interface(name = "org.scx.Loader")]
 impl ScxLoader {
     /// Get currently running scheduler, in case non is running return "unknown"
     #[zbus(property)]
     async fn current_scheduler(&self) -> String {
         if let Some(current_scx) = &self.current_scx {
-            let current_scx: &str = current_scx.clone().into();
+            let current_scx = current_scx.as_str();
             log::info!("called {current_scx:?}");
             return current_scx.to_owned();
         }
@@ -85,34 +296,77 @@ impl ScxLoader {
         self.current_mode.clone()
     }
 
+    /// Get name of scheduler mode, which is either a built-in or a custom mode
+    #[zbus(property)]
+    async fn scheduler_mode_name(&self) -> String {
+        self.current_mode_name.clone()
+    }
+
     /// Get list of supported schedulers
     #[zbus(property)]
-    async fn supported_schedulers(&self) -> Vec<&str> {
-        vec![
-            "scx_bpfland",
-            "scx_cosmos",
-            "scx_flash",
-            "scx_lavd",
-            "scx_p2dq",
-            "scx_tickless",
-            "scx_rustland",
-            "scx_rusty",
-        ]
+    async fn supported_schedulers(&self) -> Vec<String> {
+        config::get_supported_scheds(&self.config)
+            .iter()
+            .map(|scx_sched| scx_sched.as_str().to_owned())
+            .collect()
+    }
+
+    /// Get number of restarts of the current scheduler after it failed
+    #[zbus(property)]
+    async fn restart_count(&self) -> u32 {
+        self.restart_count
     }
 
+    /// Get start time of the current scheduler in seconds since the Unix epoch, 0 if none is running
+    #[zbus(property)]
+    async fn started_at(&self) -> u64 {
+        self.started_at
+            .and_then(|started_at| started_at.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since_epoch| since_epoch.as_secs())
+    }
+
+    /// Get exit reason of the last exited scheduler, empty if none has exited yet
+    #[zbus(property)]
+    async fn last_exit_reason(&self) -> String {
+        self.last_exit_reason.clone()
+    }
+
+    /// Emitted when a scheduler is started
+    #[zbus(signal)]
+    async fn scheduler_started(
+        emitter: &SignalEmitter<'_>,
+        scx_name: &str,
+        sched_mode: SchedMode,
+    ) -> zbus::Result<()>;
+
+    /// Emitted when the running scheduler is stopped or exits on its own
+    #[zbus(signal)]
+    async fn scheduler_stopped(emitter: &SignalEmitter<'_>, scx_name: &str) -> zbus::Result<()>;
+
+    /// Emitted when the running scheduler fails, with the exit reason and
+    /// whether it is going to be restarted
+    #[zbus(signal)]
+    async fn scheduler_crashed(
+        emitter: &SignalEmitter<'_>,
+        scx_name: &str,
+        exit_reason: &str,
+        will_restart: bool,
+    ) -> zbus::Result<()>;
+
+    // The scheduler gets started by the runner, the SchedulerStarted signal and the
+    // properties are updated once it actually runs.
     async fn start_scheduler(
         &mut self,
         scx_name: SupportedSched,
         sched_mode: SchedMode,
     ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
+
         log::info!("starting {scx_name:?} with mode {sched_mode:?}..");
 
-        let _ = self.channel.send(ScxMessage::StartSched((
-            scx_name.clone(),
-            sched_mode.clone(),
-        )));
-        self.current_scx = Some(scx_name);
-        self.current_mode = sched_mode;
+        let _ = self
+            .channel
+            .send(ScxMessage::StartSched((scx_name, sched_mode)));
 
         Ok(())
     }
@@ -122,14 +376,18 @@ impl ScxLoader {
         scx_name: SupportedSched,
         scx_args: Vec<String>,
     ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
+
         log::info!("starting {scx_name:?} with args {scx_args:?}..");
 
+        // reset mode to auto
+        let sched_info = SchedInfo {
+            custom_args: true,
+            ..SchedInfo::with_mode(scx_name, SchedMode::Auto)
+        };
         let _ = self
             .channel
-            .send(ScxMessage::StartSchedArgs((scx_name.clone(), scx_args)));
-        self.current_scx = Some(scx_name);
-        // reset mode to auto
-        self.current_mode = SchedMode::Auto;
+            .send(ScxMessage::StartSchedArgs((sched_info, scx_args)));
 
         Ok(())
     }
@@ -139,14 +397,13 @@ impl ScxLoader {
         scx_name: SupportedSched,
         sched_mode: SchedMode,
     ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
+
         log::info!("switching {scx_name:?} with mode {sched_mode:?}..");
 
-        let _ = self.channel.send(ScxMessage::SwitchSched((
-            scx_name.clone(),
-            sched_mode.clone(),
-        )));
-        self.current_scx = Some(scx_name);
-        self.current_mode = sched_mode;
+        let _ = self
+            .channel
+            .send(ScxMessage::SwitchSched((scx_name, sched_mode)));
 
         Ok(())
     }
@@ -156,29 +413,117 @@ impl ScxLoader {
         scx_name: SupportedSched,
         scx_args: Vec<String>,
     ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
+
         log::info!("switching {scx_name:?} with args {scx_args:?}..");
 
+        // reset mode to auto
+        let sched_info = SchedInfo {
+            custom_args: true,
+            ..SchedInfo::with_mode(scx_name, SchedMode::Auto)
+        };
         let _ = self
             .channel
-            .send(ScxMessage::SwitchSchedArgs((scx_name.clone(), scx_args)));
-        self.current_scx = Some(scx_name);
-        // reset mode to auto
-        self.current_mode = SchedMode::Auto;
+            .send(ScxMessage::SwitchSchedArgs((sched_info, scx_args)));
 
         Ok(())
     }
 
-    async fn stop_scheduler(&mut self) -> zbus::fdo::Result<()> {
-        if let Some(current_scx) = &self.current_scx {
-            let scx_name: &str = current_scx.clone().into();
+    async fn start_scheduler_with_mode_name(
+        &mut self,
+        scx_name: SupportedSched,
+        mode_name: String,
+    ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
 
-            log::info!("stopping {scx_name:?}..");
-            let _ = self.channel.send(ScxMessage::StopSched);
-            self.current_scx = None;
+        // built-in modes are started as usual
+        if let Ok(sched_mode) = SchedMode::from_str(&mode_name) {
+            return self.start_scheduler(scx_name, sched_mode).await;
         }
 
+        let scx_args = self.custom_mode_args(&scx_name, &mode_name)?;
+        log::info!("starting {scx_name:?} with custom mode {mode_name:?}..");
+
+        let sched_info = SchedInfo {
+            mode_name,
+            ..SchedInfo::with_mode(scx_name, SchedMode::Auto)
+        };
+        let _ = self
+            .channel
+            .send(ScxMessage::StartSchedArgs((sched_info, scx_args)));
+
+        Ok(())
+    }
+
+    async fn switch_scheduler_with_mode_name(
+        &mut self,
+        scx_name: SupportedSched,
+        mode_name: String,
+    ) -> zbus::fdo::Result<()> {
+        self.validate_sched(&scx_name)?;
+
+        // built-in modes are switched to as usual
+        if let Ok(sched_mode) = SchedMode::from_str(&mode_name) {
+            return self.switch_scheduler(scx_name, sched_mode).await;
+        }
+
+        let scx_args = self.custom_mode_args(&scx_name, &mode_name)?;
+        log::info!("switching {scx_name:?} with custom mode {mode_name:?}..");
+
+        let sched_info = SchedInfo {
+            mode_name,
+            ..SchedInfo::with_mode(scx_name, SchedMode::Auto)
+        };
+        let _ = self
+            .channel
+            .send(ScxMessage::SwitchSchedArgs((sched_info, scx_args)));
+
+        Ok(())
+    }
+
+    // The SchedulerStopped signal is emitted once the runner stopped the scheduler
+    async fn stop_scheduler(&mut self) -> zbus::fdo::Result<()> {
+        log::info!("stopping {:?}..", self.current_scx_name());
+        let _ = self.channel.send(ScxMessage::StopSched);
+
         Ok(())
     }
+
+    /// Get the last lines printed by the schedulers, all kept lines if max_lines is 0
+    async fn get_scheduler_log(&self, max_lines: u32) -> Vec<String> {
+        self.sched_log
+            .lock()
+            .unwrap()
+            .last_lines(max_lines as usize)
+    }
+
+    /// Re-read the config file and apply it, the scheduler is restarted if its
+    /// flags changed. Returns the changes and the validation errors of the new config,
+    /// a config with errors is not applied.
+    async fn reload_config(&self) -> zbus::fdo::Result<(Vec<String>, Vec<String>)> {
+        let (new_config, messages) = load_config()
+            .await
+            .map_err(|err| zbus::fdo::Error::Failed(format!("{err:#}")))?;
+        let Some(new_config) = new_config else {
+            return Ok((vec![], messages));
+        };
+
+        // the worker applies the config, once this call released the interface
+        let changes = config::diff_config(&self.config, &new_config);
+        if !changes.is_empty() {
+            let _ = self.channel.send(ScxMessage::ApplyConfig(new_config));
+        }
+        Ok((changes, messages))
+    }
+
+    /// Check the config file and the flags of every scheduler mode,
+    /// returns the list of found errors followed by the warnings
+    async fn validate_config(&self) -> Vec<String> {
+        match config::init_config() {
+            Ok(config) => validate::validate_config(&config).await.into_messages(),
+            Err(err) => vec![format!("Failed to parse config: {err:#}")],
+        }
+    }
 }
 
 // Monitors CPU utilization and enables scx_lavd when utilization of any CPUs is > 90%
@@ -209,7 +554,7 @@ async fn monitor_cpu_util() -> Result<()> {
                 if running_sched.is_none() {
                     log::info!("CPU Utilization exceeded 90% for 5 seconds, starting scx_lavd");
 
-                    let scx_name: &str = SupportedSched::Lavd.into();
+                    let scx_name = SupportedSched::Lavd.as_str();
                     running_sched = Some(
                         Command::new(scx_name)
                             .spawn()
@@ -248,6 +593,67 @@ async fn monitor_cpu_util() -> Result<()> {
     }
 }
 
+// Watches running processes and switches the scheduler while any of the configured rules matches.
+// Once no rule matches anymore, the default scheduler and mode from the config are restored.
+async fn monitor_app_rules(
+    connection: Connection,
+    config_rx: watch::Receiver<Arc<config::Config>>,
+) -> Result<()> {
+    let loader_client = LoaderClientProxy::new(&connection).await?;
+    let mut active_rule: Option<config::Rule> = None;
+
+    loop {
+        // pick up the rules of the reloaded config
+        let config = config_rx.borrow().clone();
+        let default_sched = &config.default_sched;
+        let default_mode = config.default_mode.clone().unwrap_or(SchedMode::Auto);
+
+        let matching_rule = if config.rules.is_empty() {
+            None
+        } else {
+            let processes = rules::scan_processes();
+            rules::find_matching_rule(&config.rules, &processes).cloned()
+        };
+
+        if matching_rule != active_rule {
+            let res = match &matching_rule {
+                Some(rule) => {
+                    let mode = rule.mode.clone().unwrap_or(SchedMode::Auto);
+                    log::info!(
+                        "Rule {rule:?} matched, switching to {:?} with mode {mode:?}",
+                        rule.sched
+                    );
+
+                    loader_client
+                        .switch_scheduler(rule.sched.clone(), mode)
+                        .await
+                }
+                None => {
+                    if let Some(default_sched) = &default_sched {
+                        log::info!(
+                            "No rule matches anymore, switching back to {default_sched:?} with mode {default_mode:?}"
+                        );
+                        loader_client
+                            .switch_scheduler(default_sched.clone(), default_mode.clone())
+                            .await
+                    } else {
+                        log::info!("No rule matches anymore, stopping scheduler");
+                        loader_client.stop_scheduler().await
+                    }
+                }
+            };
+
+            // keep the previous rule active on failure so that the switch is retried
+            match res {
+                Ok(()) => active_rule = matching_rule,
+                Err(e) => log::error!("Failed to apply scheduler rule: {e}"),
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // initialize the logger
@@ -256,7 +662,7 @@ async fn main() -> Result<()> {
     let args = Args::parse();
 
     // initialize the config
-    let config = config::init_config().context("Failed to initialize config")?;
+    let config = Arc::new(config::init_config().context("Failed to initialize config")?);
 
     // If --auto is passed, start scx_loader as a standard background process
     // that swaps schedulers out automatically
@@ -268,30 +674,44 @@ async fn main() -> Result<()> {
     }
 
     log::info!("Starting as dbus interface");
+    let sched_log = Arc::new(Mutex::new(SchedLog::new(SCHED_LOG_LINES)));
     // setup channel
     let (channel, rx) = tokio::sync::mpsc::unbounded_channel::<ScxMessage>();
 
-    let channel_clone = channel.clone();
-    ctrlc::set_handler(move || {
-        log::info!("shutting down..");
-        let _ = channel_clone.send(ScxMessage::Quit);
-    })
-    .context("Error setting Ctrl-C handler")?;
+    // setup channel for publishing the reloaded config
+    let (config_tx, config_rx) = watch::channel(config.clone());
+
+    // SIGINT and SIGTERM shut the loader down, SIGHUP reloads the config
+    let signals = (
+        signal(SignalKind::interrupt()).context("Error setting SIGINT handler")?,
+        signal(SignalKind::terminate()).context("Error setting SIGTERM handler")?,
+        signal(SignalKind::hangup()).context("Error setting SIGHUP handler")?,
+    );
 
     // register dbus interface
     let connection = Connection::system().await?;
     connection
         .object_server()
         .at(
-            "/org/scx/Loader",
+            LOADER_PATH,
             ScxLoader {
                 current_scx: None,
                 current_mode: SchedMode::Auto,
+                current_mode_name: "auto".to_owned(),
+                restart_count: 0,
+                last_exit_reason: String::new(),
+                started_at: None,
+                custom_args: false,
                 channel: channel.clone(),
+                config: config.clone(),
+                config_tx,
+                sched_log: sched_log.clone(),
             },
         )
         .await?;
 
+    tokio::spawn(handle_signals(signals, channel.clone()));
+
     connection.request_name("org.scx.Loader").await?;
 
     // if user set default scheduler, then start it
@@ -306,20 +726,174 @@ async fn main() -> Result<()> {
             .await?;
     }
 
+    // check the configured scheduler flags in the background, the loader
+    // keeps working with an invalid config, so only warn about it
+    let validate_config = config.clone();
+    tokio::spawn(async move {
+        let report = validate::validate_config(&validate_config).await;
+        for err in report.errors {
+            log::warn!("Invalid config: {err}");
+        }
+        for warning in report.warnings {
+            log::warn!("{warning}, it may be hidden");
+        }
+    });
+
+    // watch for the processes matching the rules, which may be added by a config reload
+    if !config.rules.is_empty() {
+        log::info!("Watching processes for {} rule(s)", config.rules.len());
+    }
+    let rules_future = monitor_app_rules(connection.clone(), config_rx.clone());
+    tokio::spawn(async move {
+        if let Err(err) = rules_future.await {
+            log::error!("Rules monitor failed: {err}");
+        }
+    });
+
+    // follow the power profile and AC state, if the power policy gets enabled
+    if config.power_policy.enabled {
+        log::info!("Switching modes with the power profile and AC state");
+    }
+    let power_future = monitor_power_policy(connection.clone(), config_rx.clone());
+    tokio::spawn(async move {
+        if let Err(err) = power_future.await {
+            log::error!("Power policy monitor failed: {err}");
+        }
+    });
+
     // run worker/receiver loop
-    worker_loop(config, rx).await?;
+    worker_loop(config_rx, rx, connection, sched_log).await?;
 
     Ok(())
 }
 
+// Watches the power profile of power-profiles-daemon and the AC state, and switches the mode
+// of the running scheduler according to the power policy. The mode is only switched when the
+// power state changes, so a mode picked by the user stays until the next change.
+async fn monitor_power_policy(
+    connection: Connection,
+    config_rx: watch::Receiver<Arc<config::Config>>,
+) -> Result<()> {
+    let loader_client = LoaderClientProxy::new(&connection).await?;
+    let ppd_proxy = PowerProfilesProxy::new(&connection).await?;
+    let mut policy_mode: Option<SchedMode> = None;
+
+    loop {
+        tokio::time::sleep(POWER_POLL_INTERVAL).await;
+
+        // pick up the policy of the reloaded config
+        let power_policy = config_rx.borrow().power_policy.clone();
+        if !power_policy.enabled {
+            policy_mode = None;
+            continue;
+        }
+
+        let profile = power::fetch_power_profile(&ppd_proxy).await;
+        let on_battery = power::is_on_battery();
+        let Some(mode) = power_policy.mode_for(profile, on_battery) else {
+            continue;
+        };
+        if policy_mode.as_ref() == Some(&mode) {
+            continue;
+        }
+
+        let res: zbus::Result<()> = async {
+            let current_scheduler = loader_client.current_scheduler().await?;
+            let Ok(scx_sched) = SupportedSched::from_str(&current_scheduler) else {
+                // no scheduler is running
+                return Ok(());
+            };
+            let mode_name: &str = mode.clone().into();
+            if loader_client.scheduler_mode_name().await? == mode_name {
+                return Ok(());
+            }
+
+            log::info!(
+                "Power state changed (profile {profile:?}, on battery: {on_battery}), switching {scx_sched:?} to mode {mode:?}"
+            );
+            loader_client.switch_scheduler(scx_sched, mode.clone()).await
+        }
+        .await;
+
+        // keep the previous mode on failure so that the switch is retried on the next poll
+        match res {
+            Ok(()) => policy_mode = Some(mode),
+            Err(e) => log::error!("Failed to apply power policy mode: {e}"),
+        }
+    }
+}
+
+/// Read and validate the config file. Validating runs every configured scheduler,
+/// so it's done before the worker takes the interface lock for applying the config.
+/// Returns the config, or None if it has errors, and the messages of the validation.
+async fn load_config() -> Result<(Option<Arc<config::Config>>, Vec<String>)> {
+    let new_config = config::init_config().context("Failed to parse config")?;
+    let report = validate::validate_config(&new_config).await;
+    if !report.errors.is_empty() {
+        log::warn!("Not applying the reloaded config, it has errors");
+        return Ok((None, report.into_messages()));
+    }
+    Ok((Some(Arc::new(new_config)), report.into_messages()))
+}
+
+/// Shuts the loader down on SIGINT and SIGTERM, reloads the config on SIGHUP
+async fn handle_signals(
+    (mut sigint, mut sigterm, mut sighup): (
+        tokio::signal::unix::Signal,
+        tokio::signal::unix::Signal,
+        tokio::signal::unix::Signal,
+    ),
+    channel: UnboundedSender<ScxMessage>,
+) {
+    loop {
+        tokio::select! {
+            _ = sigint.recv() => {}
+            _ = sigterm.recv() => {}
+            _ = sighup.recv() => {
+                log::info!("Got SIGHUP, reloading config..");
+                // keep handling the other signals while the config is validated
+                let channel = channel.clone();
+                tokio::spawn(async move {
+                    match load_config().await {
+                        Ok((new_config, messages)) => {
+                            for message in messages {
+                                log::warn!("Reloaded config: {message}");
+                            }
+                            if let Some(new_config) = new_config {
+                                let _ = channel.send(ScxMessage::ApplyConfig(new_config));
+                            }
+                        }
+                        Err(err) => log::error!("Failed to reload config: {err:#}"),
+                    }
+                });
+                continue;
+            }
+        }
+
+        log::info!("shutting down..");
+        let _ = channel.send(ScxMessage::Quit);
+        return;
+    }
+}
+
 async fn worker_loop(
-    config: config::Config,
+    config_rx: watch::Receiver<Arc<config::Config>>,
     mut receiver: UnboundedReceiver<ScxMessage>,
+    connection: Connection,
+    sched_log: Arc<Mutex<SchedLog>>,
 ) -> Result<()> {
     // setup channel for scheduler runner
     let (runner_tx, runner_rx) = tokio::sync::mpsc::channel::<RunnerMessage>(1);
+    // setup channel for events reported by the scheduler runner
+    let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel::<RunnerEvent>();
+
+    let runner_config_rx = config_rx.clone();
+    let run_sched_future = tokio::spawn(async move {
+        handle_child_process(runner_rx, runner_config_rx, sched_log, event_tx).await
+    });
 
-    let run_sched_future = tokio::spawn(async move { handle_child_process(runner_rx).await });
+    let iface_ref: InterfaceRef<ScxLoader> =
+        connection.object_server().interface(LOADER_PATH).await?;
 
     // prepare future for tokio
     tokio::pin!(run_sched_future);
@@ -338,9 +912,17 @@ async fn worker_loop(
                 let _ = res?;
                 continue;
             }
+            Some(event) = event_rx.recv() => {
+                log::debug!("Got runner event : {event:?}");
+                handle_runner_event(&iface_ref, event).await?;
+                continue;
+            }
         };
         log::debug!("Got msg : {msg:?}");
 
+        // use the latest config, it may have been reloaded
+        let config = config_rx.borrow().clone();
+
         match msg {
             ScxMessage::Quit => return Ok(()),
             ScxMessage::StopSched => {
@@ -353,84 +935,164 @@ async fn worker_loop(
                 log::info!("Got event to start scheduler!");
 
                 // get scheduler args for the mode
-                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode);
+                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode.clone());
+                let sched_info = SchedInfo::with_mode(scx_sched, sched_mode);
 
                 // send message with scheduler and asociated args to the runner
                 runner_tx
-                    .send(RunnerMessage::Start((scx_sched, args)))
+                    .send(RunnerMessage::Start((sched_info, args)))
                     .await?;
             }
-            ScxMessage::StartSchedArgs((scx_sched, sched_args)) => {
+            ScxMessage::StartSchedArgs((sched_info, sched_args)) => {
                 log::info!("Got event to start scheduler with args!");
 
                 // send message with scheduler and asociated args to the runner
                 runner_tx
-                    .send(RunnerMessage::Start((scx_sched, sched_args)))
+                    .send(RunnerMessage::Start((sched_info, sched_args)))
                     .await?;
             }
             ScxMessage::SwitchSched((scx_sched, sched_mode)) => {
                 log::info!("Got event to switch scheduler!");
 
                 // get scheduler args for the mode
-                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode);
+                let args = config::get_scx_flags_for_mode(&config, &scx_sched, sched_mode.clone());
+                let sched_info = SchedInfo::with_mode(scx_sched, sched_mode);
 
                 // send message with scheduler and asociated args to the runner
                 runner_tx
-                    .send(RunnerMessage::Switch((scx_sched, args)))
+                    .send(RunnerMessage::Switch((sched_info, args)))
                     .await?;
             }
-            ScxMessage::SwitchSchedArgs((scx_sched, sched_args)) => {
+            ScxMessage::SwitchSchedArgs((sched_info, sched_args)) => {
                 log::info!("Got event to switch scheduler with args!");
 
                 // send message with scheduler and asociated args to the runner
                 runner_tx
-                    .send(RunnerMessage::Switch((scx_sched, sched_args)))
+                    .send(RunnerMessage::Switch((sched_info, sched_args)))
                     .await?;
             }
+            ScxMessage::ApplyConfig(new_config) => {
+                let emitter = iface_ref.signal_emitter();
+                let mut iface = iface_ref.get_mut().await;
+                iface.apply_config(emitter, new_config).await?;
+            }
+        }
+    }
+}
+
+/// Update the loader state and notify clients about what the runner did with the scheduler
+async fn handle_runner_event(
+    iface_ref: &InterfaceRef<ScxLoader>,
+    event: RunnerEvent,
+) -> Result<()> {
+    let emitter = iface_ref.signal_emitter();
+    let mut iface = iface_ref.get_mut().await;
+
+    match event {
+        RunnerEvent::Started(sched_info) => {
+            iface.set_scheduler_started(emitter, sched_info).await?;
+        }
+        RunnerEvent::Stopped => {
+            iface.set_scheduler_stopped(emitter).await?;
+        }
+        RunnerEvent::Exited((sched_info, exit_reason)) => {
+            log::info!("{:?} exited: {exit_reason}", sched_info.scx_sched);
+            iface.last_exit_reason = exit_reason;
+            iface.last_exit_reason_changed(emitter).await?;
+            iface.set_scheduler_stopped(emitter).await?;
+        }
+        RunnerEvent::Restarting((sched_info, restart_count, exit_reason)) => {
+            ScxLoader::scheduler_crashed(
+                emitter,
+                sched_info.scx_sched.as_str(),
+                &exit_reason,
+                true,
+            )
+            .await?;
+            iface.restart_count = restart_count;
+            iface.last_exit_reason = exit_reason;
+            iface.restart_count_changed(emitter).await?;
+            iface.last_exit_reason_changed(emitter).await?;
+        }
+        RunnerEvent::Failed((sched_info, exit_reason)) => {
+            log::error!(
+                "{:?} failed and won't be restarted anymore: {exit_reason}",
+                sched_info.scx_sched
+            );
+            ScxLoader::scheduler_crashed(
+                emitter,
+                sched_info.scx_sched.as_str(),
+                &exit_reason,
+                false,
+            )
+            .await?;
+            iface.last_exit_reason = exit_reason;
+            iface.last_exit_reason_changed(emitter).await?;
+
+            // the runner reports the fallback scheduler as started, if there is one
+            iface.set_scheduler_stopped(emitter).await?;
         }
     }
+
+    Ok(())
 }
 
-async fn handle_child_process(mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>) -> Result<()> {
+async fn handle_child_process(
+    mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>,
+    config_rx: watch::Receiver<Arc<config::Config>>,
+    sched_log: Arc<Mutex<SchedLog>>,
+    event_tx: UnboundedSender<RunnerEvent>,
+) -> Result<()> {
     let mut task: Option<tokio::task::JoinHandle<Result<Option<ExitStatus>>>> = None;
     let mut cancel_token = Arc::new(tokio_util::sync::CancellationToken::new());
 
     while let Some(message) = rx.recv().await {
+        let config = config_rx.borrow().clone();
+
+        // the task finishes once the scheduler exited or ran out of restarts
+        if task.as_ref().is_some_and(|task| task.is_finished()) {
+            task = None;
+        }
+
         match message {
-            RunnerMessage::Switch((scx_sched, sched_args)) => {
-                // stop the sched if its running
-                stop_scheduler(&mut task, &mut cancel_token).await;
+            RunnerMessage::Switch((sched_info, sched_args)) => {
+                // stop the sched if its running, the new one is only reported once it started
+                if stop_scheduler(&mut task, &mut cancel_token).await {
+                    let _ = event_tx.send(RunnerEvent::Stopped);
+                }
 
                 // overwise start scheduler
-                match start_scheduler(scx_sched, sched_args, cancel_token.clone()).await {
-                    Ok(handle) => {
-                        task = Some(handle);
-                        log::debug!("Scheduler started");
-                    }
-                    Err(err) => {
-                        log::error!("Failed to start scheduler: {err}");
-                    }
-                }
+                task = Some(start_scheduler(
+                    &config,
+                    sched_info,
+                    sched_args,
+                    sched_log.clone(),
+                    event_tx.clone(),
+                    cancel_token.clone(),
+                ));
+                log::debug!("Scheduler started");
             }
-            RunnerMessage::Start((scx_sched, sched_args)) => {
+            RunnerMessage::Start((sched_info, sched_args)) => {
                 // check if sched is running or not
                 if task.is_some() {
                     log::error!("Scheduler wasn't finished yet. Stop already running scheduler!");
                     continue;
                 }
                 // overwise start scheduler
-                match start_scheduler(scx_sched, sched_args, cancel_token.clone()).await {
-                    Ok(handle) => {
-                        task = Some(handle);
-                        log::debug!("Scheduler started");
-                    }
-                    Err(err) => {
-                        log::error!("Failed to start scheduler: {err}");
-                    }
-                }
+                task = Some(start_scheduler(
+                    &config,
+                    sched_info,
+                    sched_args,
+                    sched_log.clone(),
+                    event_tx.clone(),
+                    cancel_token.clone(),
+                ));
+                log::debug!("Scheduler started");
             }
             RunnerMessage::Stop => {
-                stop_scheduler(&mut task, &mut cancel_token).await;
+                if stop_scheduler(&mut task, &mut cancel_token).await {
+                    let _ = event_tx.send(RunnerEvent::Stopped);
+                }
             }
         }
     }
@@ -438,103 +1100,423 @@ async fn handle_child_process(mut rx: tokio::sync::mpsc::Receiver<RunnerMessage>
     Ok(())
 }
 
-/// Start the scheduler with the given arguments
-async fn start_scheduler(
-    scx_crate: SupportedSched,
+/// Start the scheduler with the given arguments, restarting it according to the restart policy
+/// when it fails. Once it ran out of restarts, the fallback scheduler of the restart policy is
+/// started instead.
+fn start_scheduler(
+    config: &config::Config,
+    sched_info: SchedInfo,
     args: Vec<String>,
+    sched_log: Arc<Mutex<SchedLog>>,
+    event_tx: UnboundedSender<RunnerEvent>,
     cancel_token: Arc<tokio_util::sync::CancellationToken>,
-) -> Result<tokio::task::JoinHandle<Result<Option<ExitStatus>>>> {
+) -> tokio::task::JoinHandle<Result<Option<ExitStatus>>> {
+    let restart_policy = config.restart_policy.clone();
+    let mut sched_bin = config::get_sched_binary(config, &sched_info.scx_sched);
+    let mut sched_output = SchedOutput::new(
+        &sched_info.scx_sched,
+        &sched_info.mode_name,
+        sched_log.clone(),
+    );
+    let mut args = args;
+    let mut sched_info = sched_info;
+
+    // get scheduler args for the fallback mode
+    let mut fallback = restart_policy.fallback_sched.clone().map(|fallback_sched| {
+        let fallback_mode = restart_policy
+            .fallback_mode
+            .clone()
+            .unwrap_or(SchedMode::Auto);
+        let fallback_args =
+            config::get_scx_flags_for_mode(config, &fallback_sched, fallback_mode.clone());
+        (
+            config::get_sched_binary(config, &fallback_sched),
+            SchedInfo::with_mode(fallback_sched, fallback_mode),
+            fallback_args,
+        )
+    });
+
     // Ensure the child process exit is handled correctly in the runtime
-    let handle = tokio::spawn(async move {
-        let mut retries = 0u32;
-        let max_retries = 5u32;
+    tokio::spawn(async move {
+        let mut restarts = 0u32;
+        // whether the scheduler was reported as started, it may only come up after restarts
+        let mut started = false;
 
         let mut last_status: Option<ExitStatus> = None;
 
-        while retries < max_retries {
-            let child = spawn_scheduler(scx_crate.clone(), args.clone()).await;
-
-            let mut failed = false;
-            if let Ok(mut child) = child {
-                tokio::select! {
-                    status = child.wait() => {
-                        let status = status.expect("child process encountered an error");
-                        last_status = Some(status);
-                        if !status.success() {
-                            failed = true;
-                        }
-                        log::debug!("Child process exited with status: {status:?}");
+        loop {
+            let child = spawn_scheduler(&sched_bin, args.clone()).await;
+
+            let exit_reason = match child {
+                Ok(mut child) => {
+                    if !started {
+                        started = true;
+                        let _ = event_tx.send(RunnerEvent::Started(sched_info.clone()));
                     }
 
-                    _ = cancel_token.cancelled() => {
-                        log::debug!("Received cancellation signal");
-                        // Send SIGINT
-                        if let Some(child_id) = child.id() {
-                            nix::sys::signal::kill(
-                                nix::unistd::Pid::from_raw(child_id as i32),
-                                nix::sys::signal::SIGINT,
-                            ).context("Failed to send termination signal to the child")?;
+                    let stdout_future = tokio::spawn(forward_child_output(
+                        child.stdout.take(),
+                        sched_output.clone(),
+                        Level::Info,
+                    ));
+                    let stderr_future = tokio::spawn(forward_child_output(
+                        child.stderr.take(),
+                        sched_output.clone(),
+                        Level::Warn,
+                    ));
+
+                    tokio::select! {
+                        status = child.wait() => {
+                            let status = status.expect("child process encountered an error");
+                            last_status = Some(status);
+                            log::debug!("Child process exited with status: {status:?}");
+
+                            let stdout_exit_info = stdout_future.await.ok().flatten();
+                            let exit_info = stderr_future.await.ok().flatten().or(stdout_exit_info);
+                            let exit_reason = exit_info
+                                .as_ref()
+                                .map_or_else(|| status.to_string(), ExitInfo::to_string);
+
+                            // no need to restart, if the scheduler exited cleanly
+                            // or the exit reason tells us to not come back
+                            if status.success()
+                                || exit_info.is_some_and(|exit_info| !exit_info.should_restart())
+                            {
+                                let _ = event_tx.send(RunnerEvent::Exited((sched_info.clone(), exit_reason)));
+                                break;
+                            }
+                            exit_reason
                         }
-                        let status = child.wait().await.expect("child process encountered an error");
-                        last_status = Some(status);
-                        break;
-                    }
-                };
-            } else {
-                log::debug!("Failed to spawn child process");
-                failed = true;
-            }
 
-            // retrying if failed, otherwise exit
-            if !failed {
-                break;
+                        _ = cancel_token.cancelled() => {
+                            log::debug!("Received cancellation signal");
+                            // Send SIGINT
+                            if let Some(child_id) = child.id() {
+                                nix::sys::signal::kill(
+                                    nix::unistd::Pid::from_raw(child_id as i32),
+                                    nix::sys::signal::SIGINT,
+                                ).context("Failed to send termination signal to the child")?;
+                            }
+                            let status = child.wait().await.expect("child process encountered an error");
+                            last_status = Some(status);
+                            break;
+                        }
+                    }
+                }
+                Err(err) => {
+                    log::debug!("Failed to spawn child process");
+                    format!("{err:#}")
+                }
+            };
+
+            // give up, once the scheduler ran out of restarts
+            if restarts >= restart_policy.max_restarts {
+                log::error!(
+                    "Scheduler failed: {exit_reason} (restarted {restarts} times, giving up)"
+                );
+                let _ = event_tx.send(RunnerEvent::Failed((sched_info.clone(), exit_reason)));
+
+                // switch to the fallback scheduler, unless it is the one which failed
+                match fallback.take() {
+                    Some((fallback_bin, fallback_info, fallback_args))
+                        if fallback_info.scx_sched != sched_info.scx_sched =>
+                    {
+                        log::info!(
+                            "Switching to fallback scheduler {:?} with mode {:?}",
+                            fallback_info.scx_sched,
+                            fallback_info.mode_name
+                        );
+                        sched_bin = fallback_bin;
+                        sched_output = SchedOutput::new(
+                            &fallback_info.scx_sched,
+                            &fallback_info.mode_name,
+                            sched_log.clone(),
+                        );
+                        args = fallback_args;
+                        sched_info = fallback_info;
+                        restarts = 0;
+                        started = false;
+                        continue;
+                    }
+                    _ => break,
+                }
             }
 
-            retries += 1;
+            restarts += 1;
+            let backoff = restart_policy.backoff(restarts);
             log::error!(
-                "Failed to start scheduler (attempt {}/{})",
-                retries,
-                max_retries,
+                "Scheduler failed: {exit_reason} (restart {}/{} in {:?})",
+                restarts,
+                restart_policy.max_restarts,
+                backoff,
             );
+            let _ = event_tx.send(RunnerEvent::Restarting((
+                sched_info.clone(),
+                restarts,
+                exit_reason,
+            )));
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = cancel_token.cancelled() => {
+                    log::debug!("Received cancellation signal");
+                    break;
+                }
+            }
         }
 
         Ok(last_status)
-    });
-
-    Ok(handle)
+    })
 }
 
 /// Starts the scheduler as a child process and returns child object to manage lifecycle by the
 /// caller.
-async fn spawn_scheduler(scx_crate: SupportedSched, args: Vec<String>) -> Result<Child> {
-    let sched_bin_name: &str = scx_crate.into();
+async fn spawn_scheduler(sched_bin_name: &str, args: Vec<String>) -> Result<Child> {
     log::info!("starting {sched_bin_name} command");
 
     let mut cmd = Command::new(sched_bin_name);
     // set arguments
     cmd.args(args);
 
-    // by default child IO handles are inherited from parent process
-
     // pipe stdin of child proc to /dev/null
     cmd.stdin(Stdio::null());
 
+    // pipe stdout and stderr of child proc to us, to log the output and catch the exit reason
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
     // spawn process
-    let child = cmd.spawn().expect("failed to spawn command");
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn {sched_bin_name}"))?;
 
     Ok(child)
 }
 
+impl SchedOutput {
+    fn new(scx_sched: &SupportedSched, mode_name: &str, sched_log: Arc<Mutex<SchedLog>>) -> Self {
+        Self {
+            scx_name: scx_sched.as_str().to_owned(),
+            mode_name: mode_name.to_owned(),
+            sched_log,
+        }
+    }
+
+    /// Log the line and keep it for GetSchedulerLog, lines without a known
+    /// log level are logged with the default level
+    fn forward_line(&self, line: &str, default_level: Level) {
+        let level = sched_log::parse_log_level(line).unwrap_or(default_level);
+        logger::log_sched_line(&self.scx_name, &self.mode_name, level, line);
+
+        self.sched_log
+            .lock()
+            .unwrap()
+            .push(format!("{}[{}]: {line}", self.scx_name, self.mode_name));
+    }
+}
+
+/// Forwards the output of the scheduler to the log and returns the exit info the
+/// scheduler reported, if any
+async fn forward_child_output<R: AsyncRead + Unpin>(
+    output: Option<R>,
+    sched_output: SchedOutput,
+    default_level: Level,
+) -> Option<ExitInfo> {
+    let mut lines = BufReader::new(output?).lines();
+    let mut exit_info = None;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        sched_output.forward_line(&line, default_level);
+        if let Some(parsed_exit_info) = ExitInfo::parse(&line) {
+            exit_info = Some(parsed_exit_info);
+        }
+    }
+
+    exit_info
+}
+
 async fn stop_scheduler(
     task: &mut Option<tokio::task::JoinHandle<Result<Option<ExitStatus>>>>,
     cancel_token: &mut Arc<tokio_util::sync::CancellationToken>,
-) {
-    if let Some(task) = task.take() {
-        log::debug!("Stopping already running scheduler..");
-        cancel_token.cancel();
-        let status = task.await;
-        log::debug!("Scheduler was stopped with status: {:?}", status);
-        // Create a new cancellation token
-        *cancel_token = Arc::new(tokio_util::sync::CancellationToken::new());
+) -> bool {
+    let Some(task) = task.take() else {
+        return false;
+    };
+    log::debug!("Stopping already running scheduler..");
+    cancel_token.cancel();
+    let status = task.await;
+    log::debug!("Scheduler was stopped with status: {:?}", status);
+    // Create a new cancellation token
+    *cancel_token = Arc::new(tokio_util::sync::CancellationToken::new());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SCHED: &str = "scx_test";
+    const TEST_FALLBACK: &str = "scx_test_fallback";
+
+    // Both schedulers are shell scripts, the test scheduler runs the script passed as
+    // arguments and the fallback keeps running until it gets stopped.
+    fn test_config() -> config::Config {
+        let mut config = config::get_default_config();
+        config.scheds.insert(
+            TEST_SCHED.to_owned(),
+            config::Sched {
+                path: Some("/bin/sh".to_owned()),
+                ..Default::default()
+            },
+        );
+        config.scheds.insert(
+            TEST_FALLBACK.to_owned(),
+            config::Sched {
+                path: Some("/bin/sh".to_owned()),
+                auto_mode: Some(vec!["-c".to_owned(), "sleep 30".to_owned()]),
+                ..Default::default()
+            },
+        );
+        config.restart_policy = config::RestartPolicy {
+            max_restarts: 1,
+            backoff_ms: 10,
+            max_backoff_ms: 10,
+            fallback_sched: Some(SupportedSched::Custom(TEST_FALLBACK.to_owned())),
+            fallback_mode: None,
+        };
+        config
+    }
+
+    fn test_info(scx_sched: &str) -> SchedInfo {
+        SchedInfo::with_mode(
+            SupportedSched::Custom(scx_sched.to_owned()),
+            SchedMode::Auto,
+        )
+    }
+
+    fn start_msg(script: &str) -> RunnerMessage {
+        RunnerMessage::Start((
+            test_info(TEST_SCHED),
+            vec!["-c".to_owned(), script.to_owned()],
+        ))
+    }
+
+    async fn next_event(event_rx: &mut UnboundedReceiver<RunnerEvent>) -> RunnerEvent {
+        tokio::time::timeout(Duration::from_secs(10), event_rx.recv())
+            .await
+            .expect("timed out waiting for a runner event")
+            .expect("runner event channel closed")
+    }
+
+    #[tokio::test]
+    async fn test_runner_restart_and_fallback() {
+        let (runner_tx, runner_rx) = tokio::sync::mpsc::channel::<RunnerMessage>(1);
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel::<RunnerEvent>();
+        let (_config_tx, config_rx) = watch::channel(Arc::new(test_config()));
+        let sched_log = Arc::new(Mutex::new(SchedLog::new(SCHED_LOG_LINES)));
+        let runner = tokio::spawn(handle_child_process(
+            runner_rx,
+            config_rx,
+            sched_log.clone(),
+            event_tx,
+        ));
+
+        // the scheduler can be started again after it exited
+        for _ in 0..2 {
+            runner_tx.send(start_msg("exit 0")).await.unwrap();
+            assert_eq!(
+                next_event(&mut event_rx).await,
+                RunnerEvent::Started(test_info(TEST_SCHED))
+            );
+            assert_eq!(
+                next_event(&mut event_rx).await,
+                RunnerEvent::Exited((test_info(TEST_SCHED), "exit status: 0".to_owned()))
+            );
+        }
+
+        // a failing scheduler is restarted, then replaced by the fallback
+        runner_tx
+            .send(start_msg("echo failing; exit 1"))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Started(test_info(TEST_SCHED))
+        );
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Restarting((test_info(TEST_SCHED), 1, "exit status: 1".to_owned()))
+        );
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Failed((test_info(TEST_SCHED), "exit status: 1".to_owned()))
+        );
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Started(test_info(TEST_FALLBACK))
+        );
+
+        // the fallback is running, so another start is refused until it's stopped
+        runner_tx.send(start_msg("exit 0")).await.unwrap();
+        runner_tx.send(RunnerMessage::Stop).await.unwrap();
+        assert_eq!(next_event(&mut event_rx).await, RunnerEvent::Stopped);
+        runner_tx.send(start_msg("exit 0")).await.unwrap();
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Started(test_info(TEST_SCHED))
+        );
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Exited((test_info(TEST_SCHED), "exit status: 0".to_owned()))
+        );
+
+        // stopping after the scheduler exited on its own doesn't report it again
+        runner_tx.send(RunnerMessage::Stop).await.unwrap();
+
+        let log = sched_log.lock().unwrap().last_lines(0);
+        assert!(log.contains(&format!("{TEST_SCHED}[auto]: failing")));
+
+        // the start while the fallback was running didn't run the scheduler
+        drop(runner_tx);
+        runner.await.unwrap().unwrap();
+        assert!(event_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_runner_switch() {
+        let (runner_tx, runner_rx) = tokio::sync::mpsc::channel::<RunnerMessage>(1);
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel::<RunnerEvent>();
+        let (_config_tx, config_rx) = watch::channel(Arc::new(test_config()));
+        let sched_log = Arc::new(Mutex::new(SchedLog::new(SCHED_LOG_LINES)));
+        let runner = tokio::spawn(handle_child_process(
+            runner_rx, config_rx, sched_log, event_tx,
+        ));
+
+        runner_tx.send(start_msg("exec sleep 30")).await.unwrap();
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Started(test_info(TEST_SCHED))
+        );
+
+        // the running scheduler is reported as stopped before the new one starts
+        runner_tx
+            .send(RunnerMessage::Switch((
+                test_info(TEST_FALLBACK),
+                vec!["-c".to_owned(), "exit 0".to_owned()],
+            )))
+            .await
+            .unwrap();
+        assert_eq!(next_event(&mut event_rx).await, RunnerEvent::Stopped);
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Started(test_info(TEST_FALLBACK))
+        );
+        assert_eq!(
+            next_event(&mut event_rx).await,
+            RunnerEvent::Exited((test_info(TEST_FALLBACK), "exit status: 0".to_owned()))
+        );
+
+        drop(runner_tx);
+        runner.await.unwrap().unwrap();
     }
 }
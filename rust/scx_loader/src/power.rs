@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2025 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::PowerPolicy;
+use crate::SchedMode;
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+#[zbus::proxy(
+    interface = "net.hadess.PowerProfiles",
+    default_service = "net.hadess.PowerProfiles",
+    default_path = "/net/hadess/PowerProfiles"
+)]
+pub trait PowerProfiles {
+    #[zbus(property)]
+    fn active_profile(&self) -> zbus::Result<String>;
+}
+
+/// Power profile as reported by power-profiles-daemon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerProfile {
+    PowerSaver,
+    Balanced,
+    Performance,
+    Unknown,
+}
+
+impl PowerProfile {
+    /// Get the power profile for the profile name used by power-profiles-daemon
+    pub fn from_ppd(profile: &str) -> PowerProfile {
+        match profile {
+            "power-saver" => PowerProfile::PowerSaver,
+            "balanced" => PowerProfile::Balanced,
+            "performance" => PowerProfile::Performance,
+            _ => PowerProfile::Unknown,
+        }
+    }
+}
+
+impl PowerPolicy {
+    /// Get the scheduler mode for the power state, None if the policy has no
+    /// mode for it. The battery mode takes precedence over the profile.
+    pub fn mode_for(&self, profile: PowerProfile, on_battery: bool) -> Option<SchedMode> {
+        if on_battery {
+            if let Some(battery_mode) = &self.on_battery {
+                return Some(battery_mode.clone());
+            }
+        }
+
+        match profile {
+            PowerProfile::PowerSaver => self.power_saver.clone(),
+            PowerProfile::Balanced => self.balanced.clone(),
+            PowerProfile::Performance => self.performance.clone(),
+            PowerProfile::Unknown => None,
+        }
+    }
+}
+
+/// Get the active power profile from power-profiles-daemon
+pub async fn fetch_power_profile(proxy: &PowerProfilesProxy<'_>) -> PowerProfile {
+    match proxy.active_profile().await {
+        Ok(profile) => PowerProfile::from_ppd(&profile),
+        Err(err) => {
+            log::debug!("Failed to fetch the active power profile from ppd: {err}");
+            PowerProfile::Unknown
+        }
+    }
+}
+
+/// Check whether the system runs on battery
+pub fn is_on_battery() -> bool {
+    is_on_battery_in(Path::new(POWER_SUPPLY_PATH))
+}
+
+/// Check whether the system is powered by its battery, i.e. a system battery and an external
+/// power supply are present and none of the external power supplies is online.
+/// Systems without a battery, e.g. desktops with UCSI ports, never run on battery.
+fn is_on_battery_in(power_supply_path: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(power_supply_path) else {
+        return false;
+    };
+
+    let mut has_battery = false;
+    let mut has_external_supply = false;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let supply_path = entry.path();
+        let read_attr = |attr: &str| {
+            fs::read_to_string(supply_path.join(attr))
+                .unwrap_or_default()
+                .trim_end()
+                .to_owned()
+        };
+
+        match read_attr("type").as_str() {
+            // batteries of peripherals, e.g. mice, have the device scope
+            "Battery" => has_battery |= read_attr("scope") != "Device",
+            "Mains" | "USB" => {
+                has_external_supply = true;
+                if read_attr("online") == "1" {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    has_battery && has_external_supply
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::config::PowerPolicy;
+    use crate::power::*;
+
+    fn add_supply(power_supply_path: &Path, name: &str, supply_type: &str, online: &str) {
+        let supply_path = power_supply_path.join(name);
+        fs::create_dir_all(&supply_path).unwrap();
+        fs::write(supply_path.join("type"), format!("{supply_type}\n")).unwrap();
+        fs::write(supply_path.join("online"), format!("{online}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_power_policy_mode() {
+        let mut policy = PowerPolicy::default();
+        assert_eq!(
+            policy.mode_for(PowerProfile::PowerSaver, false),
+            Some(SchedMode::PowerSave)
+        );
+        assert_eq!(
+            policy.mode_for(PowerProfile::Performance, true),
+            Some(SchedMode::Gaming)
+        );
+        assert_eq!(policy.mode_for(PowerProfile::Unknown, false), None);
+
+        policy.on_battery = Some(SchedMode::PowerSave);
+        policy.balanced = None;
+        assert_eq!(
+            policy.mode_for(PowerProfile::Performance, true),
+            Some(SchedMode::PowerSave)
+        );
+        assert_eq!(policy.mode_for(PowerProfile::Balanced, false), None);
+
+        assert_eq!(
+            PowerProfile::from_ppd("power-saver"),
+            PowerProfile::PowerSaver
+        );
+        assert_eq!(PowerProfile::from_ppd("turbo"), PowerProfile::Unknown);
+    }
+
+    #[test]
+    fn test_is_on_battery() {
+        let power_supply_path =
+            std::env::temp_dir().join(format!("scx_loader-power-{}", std::process::id()));
+
+        // desktop without any power supply entries
+        assert!(!is_on_battery_in(&power_supply_path));
+
+        // desktop with an offline USB-C port and a wireless mouse
+        add_supply(
+            &power_supply_path,
+            "ucsi-source-psy-USBC000:002",
+            "USB",
+            "0",
+        );
+        add_supply(&power_supply_path, "hidpp_battery_0", "Battery", "0");
+        fs::write(
+            power_supply_path.join("hidpp_battery_0").join("scope"),
+            "Device\n",
+        )
+        .unwrap();
+        assert!(!is_on_battery_in(&power_supply_path));
+
+        add_supply(&power_supply_path, "BAT0", "Battery", "0");
+        assert!(is_on_battery_in(&power_supply_path));
+
+        add_supply(&power_supply_path, "AC", "Mains", "0");
+        assert!(is_on_battery_in(&power_supply_path));
+
+        add_supply(
+            &power_supply_path,
+            "ucsi-source-psy-USBC000:001",
+            "USB",
+            "1",
+        );
+        assert!(!is_on_battery_in(&power_supply_path));
+
+        fs::remove_dir_all(&power_supply_path).unwrap();
+    }
+}
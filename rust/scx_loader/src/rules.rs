@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2025 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::Rule;
+
+/// Information about a running process, which is used for matching rules
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProcessInfo {
+    /// The process name as found in `/proc/<pid>/comm`
+    pub comm: String,
+    /// The command line arguments separated by a space
+    pub cmdline: String,
+    /// The cgroup v2 path of the process
+    pub cgroup: String,
+}
+
+impl Rule {
+    /// Check whether the rule matches the given process. All of the set
+    /// matchers have to match, a rule without any matcher never matches.
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        if self.comm.is_none() && self.cmdline.is_none() && self.cgroup.is_none() {
+            return false;
+        }
+
+        let comm_matches = self.comm.as_ref().is_none_or(|comm| *comm == process.comm);
+        let cmdline_matches = self
+            .cmdline
+            .as_ref()
+            .is_none_or(|cmdline| process.cmdline.contains(cmdline.as_str()));
+        let cgroup_matches = self
+            .cgroup
+            .as_ref()
+            .is_none_or(|cgroup| Path::new(&process.cgroup).starts_with(cgroup));
+
+        comm_matches && cmdline_matches && cgroup_matches
+    }
+}
+
+/// Find the first rule (in config order) which matches any of the given processes
+pub fn find_matching_rule<'a>(rules: &'a [Rule], processes: &[ProcessInfo]) -> Option<&'a Rule> {
+    rules
+        .iter()
+        .find(|rule| processes.iter().any(|process| rule.matches(process)))
+}
+
+/// Collect information about all currently running processes
+pub fn scan_processes() -> Vec<ProcessInfo> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        log::warn!("Failed to read /proc");
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.bytes().all(|c| c.is_ascii_digit()))
+        })
+        // the process may exit while we are reading it, just skip it then
+        .filter_map(|entry| read_process_info(&entry.path()))
+        .collect()
+}
+
+fn read_process_info(proc_path: &Path) -> Option<ProcessInfo> {
+    let comm = fs::read_to_string(proc_path.join("comm")).ok()?;
+    let cmdline = fs::read(proc_path.join("cmdline")).ok()?;
+    let cgroup = fs::read_to_string(proc_path.join("cgroup")).unwrap_or_default();
+
+    Some(ProcessInfo {
+        comm: comm.trim_end().to_owned(),
+        cmdline: parse_cmdline(&cmdline),
+        cgroup: parse_cgroup(&cgroup),
+    })
+}
+
+/// Convert the NUL separated content of `/proc/<pid>/cmdline` into a single string
+fn parse_cmdline(content: &[u8]) -> String {
+    content
+        .split(|c| *c == b'\0')
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the cgroup v2 path from the content of `/proc/<pid>/cgroup`
+fn parse_cgroup(content: &str) -> String {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .unwrap_or_default()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Rule;
+    use crate::rules::*;
+    use crate::SchedMode;
+    use crate::SupportedSched;
+
+    fn test_process(comm: &str, cmdline: &str, cgroup: &str) -> ProcessInfo {
+        ProcessInfo {
+            comm: comm.to_owned(),
+            cmdline: cmdline.to_owned(),
+            cgroup: cgroup.to_owned(),
+        }
+    }
+
+    fn test_rule(comm: Option<&str>, cmdline: Option<&str>, cgroup: Option<&str>) -> Rule {
+        Rule {
+            sched: SupportedSched::Lavd,
+            mode: Some(SchedMode::Gaming),
+            comm: comm.map(String::from),
+            cmdline: cmdline.map(String::from),
+            cgroup: cgroup.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let process = test_process(
+            "cs2",
+            "/home/user/.steam/cs2 -steam -novid",
+            "/user.slice/user-1000.slice/app-steam.scope",
+        );
+
+        assert!(test_rule(Some("cs2"), None, None).matches(&process));
+        assert!(test_rule(None, Some("-novid"), None).matches(&process));
+        assert!(test_rule(None, None, Some("/user.slice/user-1000.slice")).matches(&process));
+        assert!(test_rule(Some("cs2"), Some("steam"), Some("/user.slice")).matches(&process));
+
+        assert!(!test_rule(Some("cs"), None, None).matches(&process));
+        assert!(!test_rule(Some("cs2"), Some("-windowed"), None).matches(&process));
+        assert!(!test_rule(None, None, Some("/system.slice")).matches(&process));
+        assert!(!test_rule(None, None, Some("/user.slice/user-1000.slice/app")).matches(&process));
+        assert!(test_rule(None, None, Some("/user.slice/user-1000.slice/")).matches(&process));
+        assert!(!test_rule(None, None, None).matches(&process));
+    }
+
+    #[test]
+    fn test_find_matching_rule() {
+        let rules = vec![
+            test_rule(Some("cs2"), None, None),
+            test_rule(None, Some("make"), None),
+        ];
+        let processes = vec![
+            test_process("bash", "/bin/bash", "/"),
+            test_process("make", "make -j16", "/"),
+        ];
+
+        assert_eq!(find_matching_rule(&rules, &processes), Some(&rules[1]));
+        assert_eq!(find_matching_rule(&rules, &processes[..1]), None);
+        assert_eq!(find_matching_rule(&[], &processes), None);
+    }
+
+    #[test]
+    fn test_parse_proc_content() {
+        assert_eq!(
+            parse_cmdline(b"/usr/bin/foo\0--bar\0baz\0"),
+            "/usr/bin/foo --bar baz"
+        );
+        assert_eq!(parse_cmdline(b""), "");
+
+        assert_eq!(
+            parse_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n"),
+            "/user.slice/user-1000.slice/session-2.scope"
+        );
+        assert_eq!(
+            parse_cgroup("1:name=systemd:/init.scope\n0::/init.scope\n"),
+            "/init.scope"
+        );
+        assert_eq!(parse_cgroup(""), "");
+    }
+}
@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2025 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::VecDeque;
+
+use log::Level;
+
+/// Ring buffer with the last lines printed by the schedulers
+#[derive(Debug)]
+pub struct SchedLog {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SchedLog {
+    pub fn new(capacity: usize) -> SchedLog {
+        SchedLog {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Append the line, dropping the oldest one when the buffer is full
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Get the last `count` lines, oldest first. Zero returns all lines.
+    pub fn last_lines(&self, count: usize) -> Vec<String> {
+        let skip = match count {
+            0 => 0,
+            _ => self.lines.len().saturating_sub(count),
+        };
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Detect the log level of a line printed by the scheduler. Schedulers log
+/// through simplelog or env_logger, which put the level into brackets at the
+/// start of the line, e.g. "09:15:02 [WARN] ..." or "[2025-01-01T00:00:00Z ERROR scx_rusty] ...".
+pub fn parse_log_level(line: &str) -> Option<Level> {
+    if line.starts_with("Error: ") || (line.starts_with("thread '") && line.contains("panicked")) {
+        return Some(Level::Error);
+    }
+
+    let start = line.find('[')?;
+    let end = start + line[start..].find(']')?;
+    // don't look into the message itself
+    if start > 32 {
+        return None;
+    }
+
+    line[start + 1..end]
+        .split_whitespace()
+        .find_map(|word| match word {
+            "ERROR" => Some(Level::Error),
+            "WARN" => Some(Level::Warn),
+            "INFO" => Some(Level::Info),
+            "DEBUG" => Some(Level::Debug),
+            "TRACE" => Some(Level::Trace),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sched_log::*;
+
+    #[test]
+    fn test_sched_log() {
+        let mut sched_log = SchedLog::new(3);
+        for i in 0..5 {
+            sched_log.push(format!("line {i}"));
+        }
+
+        assert_eq!(sched_log.last_lines(0), vec!["line 2", "line 3", "line 4"]);
+        assert_eq!(sched_log.last_lines(2), vec!["line 3", "line 4"]);
+        assert_eq!(sched_log.last_lines(10).len(), 3);
+
+        let mut sched_log = SchedLog::new(0);
+        sched_log.push("line".to_owned());
+        assert!(sched_log.last_lines(0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(
+            parse_log_level("09:15:02 [INFO] cpus: 16"),
+            Some(Level::Info)
+        );
+        assert_eq!(
+            parse_log_level("09:15:02 [WARN] failed to read energy profile"),
+            Some(Level::Warn)
+        );
+        assert_eq!(
+            parse_log_level("[2025-03-01T09:15:02Z ERROR scx_rusty] load balancer failed"),
+            Some(Level::Error)
+        );
+        assert_eq!(
+            parse_log_level("Error: EXIT: runnable task stall (kworker/0:1[42] failed to run)"),
+            Some(Level::Error)
+        );
+        assert_eq!(
+            parse_log_level("thread 'main' panicked at src/main.rs:10:5:"),
+            Some(Level::Error)
+        );
+
+        assert_eq!(parse_log_level("[cpus]   16 util  12.3"), None);
+        assert_eq!(
+            parse_log_level("running tasks: kworker/0:1[42] is in [ERROR] state"),
+            None
+        );
+        assert_eq!(parse_log_level(""), None);
+    }
+}
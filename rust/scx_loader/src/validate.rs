@@ -0,0 +1,437 @@
+// SPDX-License-Identifier: GPL-2.0
+//
+// Copyright (c) 2025 Vladislav Nepogodin <vnepogodin@cachyos.org>
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::process::Stdio;
+use std::slice::Iter;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use tokio::process::Command;
+
+use crate::config;
+use crate::config::Config;
+use crate::SupportedSched;
+
+/// Time to wait for the scheduler to print its help
+const HELP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prefix of the warnings in the list returned by `ValidationReport::into_messages()`
+pub const WARNING_PREFIX: &str = "warning: ";
+
+/// Value taken by an option
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OptionValue {
+    /// Flag without a value, e.g. `--verbose`
+    None,
+    /// Option with a required value, e.g. `--slice-us <SLICE_US>`
+    Required,
+    /// Option with an optional value, e.g. `--stats[=<STATS>]`
+    Optional,
+}
+
+/// Arguments a scheduler accepts, as described by the `--help` output of clap
+#[derive(Debug, Default, PartialEq)]
+pub struct ArgsSchema {
+    /// Short options, with the value the option takes
+    short: HashMap<char, OptionValue>,
+    /// Long options without the leading "--", with the value the option takes
+    long: HashMap<String, OptionValue>,
+    /// Whether positional arguments are accepted
+    positional: bool,
+}
+
+impl ArgsSchema {
+    /// Parse the options out of the help printed by clap
+    pub fn from_help(help: &str) -> ArgsSchema {
+        let mut schema = ArgsSchema::default();
+
+        for line in help.lines() {
+            if line.trim_end() == "Arguments:" {
+                schema.positional = true;
+                continue;
+            }
+
+            // options are indented by at most 6 spaces, descriptions are
+            // indented further and may start with a dash as well
+            let option_str = line.trim_start();
+            let indent = line.len() - option_str.len();
+            if indent == 0 || indent > 6 || !option_str.starts_with('-') {
+                continue;
+            }
+            if option_str[1..].starts_with(char::is_whitespace) {
+                continue;
+            }
+
+            // the short help has the description on the same line
+            let option_str = option_str.split("  ").next().unwrap_or_default();
+            let value = if option_str.contains("[=<") || option_str.contains(" [<") {
+                OptionValue::Optional
+            } else if option_str.contains(" <") {
+                OptionValue::Required
+            } else {
+                OptionValue::None
+            };
+
+            for token in option_str.split([' ', ',']) {
+                if let Some(long) = token.strip_prefix("--") {
+                    let long = long.split(['=', '[']).next().unwrap_or_default();
+                    if !long.is_empty() {
+                        schema.long.insert(long.to_owned(), value);
+                    }
+                } else if let Some(short) = token.strip_prefix('-') {
+                    if let Some(short) = short.chars().next() {
+                        schema.short.insert(short, value);
+                    }
+                }
+            }
+        }
+
+        schema
+    }
+
+    /// Get the schema by running the scheduler binary with `--help`.
+    /// Returns None if the binary is not installed.
+    pub async fn from_binary(sched_bin: &str) -> Result<Option<ArgsSchema>> {
+        let output = Command::new(sched_bin)
+            .arg("--help")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+
+        let output = match tokio::time::timeout(HELP_TIMEOUT, output).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(err)) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Ok(Err(err)) => return Err(err).with_context(|| format!("failed to run {sched_bin}")),
+            Err(_) => bail!("{sched_bin} --help timed out"),
+        };
+        if !output.status.success() {
+            bail!("{sched_bin} --help failed with {}", output.status);
+        }
+
+        let help = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(ArgsSchema::from_help(&help)))
+    }
+
+    /// Check the arguments against the schema. Options hidden from the help can't be told apart
+    /// from misspelled ones, so unknown options are returned as warnings instead of failing.
+    pub fn validate(&self, args: &[String]) -> Result<Vec<String>> {
+        let mut warnings = vec![];
+        let mut args_iter = args.iter().peekable();
+
+        while let Some(arg) = args_iter.next() {
+            if arg == "--" {
+                // everything after the separator is positional
+                if !self.positional && args_iter.next().is_some() {
+                    bail!("unexpected positional arguments after '--'");
+                }
+                break;
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                match self.long.get(name) {
+                    None => {
+                        warnings.push(format!("'--{name}' is not shown in the help"));
+                        if value.is_none() {
+                            skip_value(&mut args_iter);
+                        }
+                    }
+                    Some(OptionValue::Required)
+                        if value.is_none() && args_iter.next().is_none() =>
+                    {
+                        bail!("missing value for '--{name}'")
+                    }
+                    Some(OptionValue::Optional) if value.is_none() => {
+                        skip_value(&mut args_iter);
+                    }
+                    Some(OptionValue::None) if value.is_some() => {
+                        bail!("'--{name}' doesn't take a value")
+                    }
+                    _ => {}
+                }
+            } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+                // short flags can be combined, e.g. "-vD", and the value can be attached, e.g. "-s5000"
+                let mut chars = shorts.chars();
+                while let Some(short) = chars.next() {
+                    match self.short.get(&short) {
+                        None => {
+                            // the rest may be the value of the unknown option
+                            warnings.push(format!("'-{short}' is not shown in the help"));
+                            if chars.as_str().is_empty() {
+                                skip_value(&mut args_iter);
+                            }
+                            break;
+                        }
+                        Some(OptionValue::Required) => {
+                            if chars.as_str().is_empty() && args_iter.next().is_none() {
+                                bail!("missing value for '-{short}'");
+                            }
+                            break;
+                        }
+                        Some(OptionValue::Optional) => {
+                            if chars.as_str().is_empty() {
+                                skip_value(&mut args_iter);
+                            }
+                            break;
+                        }
+                        Some(OptionValue::None) => {}
+                    }
+                }
+            } else if !self.positional {
+                bail!("unexpected argument '{arg}'");
+            }
+        }
+
+        Ok(warnings)
+    }
+}
+
+/// Skip the value of an option which may take one, unless the next argument looks like an option
+fn skip_value(args_iter: &mut Peekable<Iter<String>>) {
+    args_iter.next_if(|arg| !arg.starts_with('-'));
+}
+
+/// Problems found in the config
+#[derive(Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Errors which make the scheduler fail to start
+    pub errors: Vec<String>,
+    /// Options not shown in the help, which may be hidden or misspelled
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Returns the errors followed by the warnings, prefixed with `WARNING_PREFIX`
+    pub fn into_messages(self) -> Vec<String> {
+        let warnings = self
+            .warnings
+            .into_iter()
+            .map(|warning| format!("{WARNING_PREFIX}{warning}"));
+        self.errors.into_iter().chain(warnings).collect()
+    }
+}
+
+/// Validate the config and the flags of every scheduler mode against the
+/// `--help` output of the scheduler. Schedulers which are not installed are skipped.
+pub async fn validate_config(config: &Config) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let mut scx_names = config.scheds.keys().collect::<Vec<_>>();
+    scx_names.sort();
+
+    for scx_name in scx_names {
+        let sched_config = &config.scheds[scx_name];
+        if let Err(err) = config::validate_sched_config(scx_name, sched_config) {
+            report.errors.push(format!("{scx_name}: {err}"));
+            continue;
+        }
+
+        let Ok(scx_sched) = SupportedSched::from_str(scx_name) else {
+            continue;
+        };
+        let sched_bin = config::get_sched_binary(config, &scx_sched);
+        let schema = match ArgsSchema::from_binary(&sched_bin).await {
+            Ok(Some(schema)) => schema,
+            Ok(None) => {
+                log::debug!("Skipping validation of {scx_name}, {sched_bin} is not installed");
+                continue;
+            }
+            Err(err) => {
+                report.errors.push(format!("{scx_name}: {err:#}"));
+                continue;
+            }
+        };
+
+        for (mode_name, flags) in sched_config.mode_flags() {
+            match schema.validate(flags) {
+                Ok(warnings) => report.warnings.extend(
+                    warnings
+                        .into_iter()
+                        .map(|warning| format!("{scx_name} {mode_name}: {warning}")),
+                ),
+                Err(err) => report.errors.push(format!("{scx_name} {mode_name}: {err}")),
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::*;
+
+    const HELP: &str = r#"scx_flash: A deadline-based scheduler
+
+Usage: scx_flash [OPTIONS] [SPECS]...
+
+Arguments:
+  [SPECS]...
+          Optional specs
+
+Options:
+      --exit-dump-len <EXIT_DUMP_LEN>
+          Exit debug dump buffer length. 0 indicates default
+
+          [default: 0]
+
+  -s, --slice-us <SLICE_US>
+          Maximum scheduling slice duration in microseconds
+
+          [default: 4096]
+
+  -I, --idle-us <IDLE_US>
+          Throttle the running CPUs by periodically injecting idle cycles.
+
+          -1 disables the throttling.
+
+          [default: 32]
+
+  -D, --direct-dispatch
+          Enable direct dispatch
+
+      --stats[=<STATS>]
+          Enable stats monitoring with the specified interval
+
+  -m, --primary-domain <PRIMARY_DOMAIN>
+          Primary domain.
+
+          Possible values:
+          - performance: fast cpus
+          - powersave: slow cpus
+
+          [default: auto]
+
+  -v, --verbose
+          Enable verbose output
+
+  -h, --help
+          Print help (see a summary with '-h')
+"#;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_help() {
+        let schema = ArgsSchema::from_help(HELP);
+
+        assert!(schema.positional);
+        assert_eq!(
+            schema.long.get("exit-dump-len"),
+            Some(&OptionValue::Required)
+        );
+        assert_eq!(schema.long.get("slice-us"), Some(&OptionValue::Required));
+        assert_eq!(schema.long.get("direct-dispatch"), Some(&OptionValue::None));
+        assert_eq!(schema.long.get("stats"), Some(&OptionValue::Optional));
+        assert_eq!(schema.short.get(&'s'), Some(&OptionValue::Required));
+        assert_eq!(schema.short.get(&'D'), Some(&OptionValue::None));
+        assert_eq!(schema.short.get(&'h'), Some(&OptionValue::None));
+        // descriptions and possible values are not options
+        assert_eq!(schema.short.get(&'1'), None);
+        assert_eq!(schema.short.len(), 6);
+        assert_eq!(schema.long.len(), 8);
+
+        let schema = ArgsSchema::from_help(
+            "Usage: scx_foo [OPTIONS]\n\nOptions:\n  -v, --verbose  Enable verbose output\n",
+        );
+        assert!(!schema.positional);
+        assert_eq!(schema.short.get(&'v'), Some(&OptionValue::None));
+        assert_eq!(schema.long.get("verbose"), Some(&OptionValue::None));
+    }
+
+    #[test]
+    fn test_validate_args() {
+        let schema = ArgsSchema::from_help(HELP);
+
+        assert!(schema.validate(&args(&[])).is_ok());
+        assert!(schema
+            .validate(&args(&["-m", "powersave", "-I", "-1", "-D", "-s", "20000"]))
+            .is_ok());
+        assert!(schema
+            .validate(&args(&[
+                "--slice-us=5000",
+                "--primary-domain",
+                "all",
+                "-vD"
+            ]))
+            .is_ok());
+        assert!(schema.validate(&args(&["-s5000", "spec.json"])).is_ok());
+        assert!(schema
+            .validate(&args(&["--stats", "--stats=1.0", "-v"]))
+            .is_ok());
+
+        // options hidden from the help are only warned about
+        assert_eq!(
+            schema.validate(&args(&["--performance"])).unwrap(),
+            vec!["'--performance' is not shown in the help"]
+        );
+        assert_eq!(
+            schema.validate(&args(&["-vx", "-D"])).unwrap(),
+            vec!["'-x' is not shown in the help"]
+        );
+        assert!(schema.validate(&args(&["-v"])).unwrap().is_empty());
+
+        assert!(schema.validate(&args(&["-s"])).is_err());
+        assert!(schema.validate(&args(&["--slice-us"])).is_err());
+        assert!(schema.validate(&args(&["--verbose=1"])).is_err());
+
+        let schema = ArgsSchema::from_help("Options:\n  -p, --partial\n      --stats [<STATS>]\n");
+        assert!(schema.validate(&args(&["-p"])).is_ok());
+        assert!(schema.validate(&args(&["spec.json"])).is_err());
+        assert!(schema.validate(&args(&["--", "spec.json"])).is_err());
+        // values of optional and hidden options are not positional arguments
+        assert!(schema.validate(&args(&["--stats", "1.0", "-p"])).is_ok());
+        assert!(schema.validate(&args(&["--hidden", "1", "-p"])).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_config() {
+        let sched_bin =
+            std::env::temp_dir().join(format!("scx_loader-validate-{}", std::process::id()));
+        std::fs::write(&sched_bin, format!("#!/bin/sh\ncat <<'EOF'\n{HELP}EOF\n")).unwrap();
+        std::fs::set_permissions(
+            &sched_bin,
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+
+        let config = Config {
+            scheds: HashMap::from([(
+                "scx_flash".to_owned(),
+                config::Sched {
+                    path: Some(sched_bin.to_string_lossy().into_owned()),
+                    auto_mode: Some(args(&["--slcie-us", "5000", "-D"])),
+                    gaming_mode: Some(args(&["--slice-us"])),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let messages = validate_config(&config).await.into_messages();
+        std::fs::remove_file(&sched_bin).unwrap();
+
+        // the misspelled option is reported, even though it doesn't fail the validation
+        assert_eq!(
+            messages,
+            vec![
+                "scx_flash gaming_mode: missing value for '--slice-us'",
+                "warning: scx_flash auto_mode: '--slcie-us' is not shown in the help",
+            ]
+        );
+    }
+}
@@ -3,6 +3,7 @@ use scx_stats_derive::Stats;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::env::args;
+use std::time::Duration;
 
 // Hacky definition sharing. See stats_def.rs.h.
 include!("stats_defs.rs.h");
@@ -45,4 +46,12 @@ fn main() {
         .request::<serde_json::Value>("stats_meta", vec![])
         .unwrap();
     println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+
+    println!("\n===== Subscribing to \"stats\" with \"fields\"=\"at,name\":");
+    let samples = client
+        .subscribe::<serde_json::Value>(vec![], Duration::from_millis(500), &["at", "name"])
+        .unwrap();
+    for resp in samples.take(3) {
+        println!("{:?}", resp);
+    }
 }
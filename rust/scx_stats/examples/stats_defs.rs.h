@@ -6,7 +6,7 @@
 #[stat(desc = "domain statistics", _om_prefix="d_", _om_label="domain_name")]
 struct DomainStats {
     pub name: String,
-    #[stat(desc = "an event counter")]
+    #[stat(desc = "an event counter", _om_counter)]
     pub events: u64,
     #[stat(desc = "a gauge number")]
     pub pressure: f64,
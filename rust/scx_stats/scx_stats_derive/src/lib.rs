@@ -1,5 +1,5 @@
 use quote::{format_ident, quote, quote_spanned};
-use scx_stats::{StatsData, StatsKind, StatsMetaAux};
+use scx_stats::{StatsData, StatsKind, StatsMetaAux, StatsParamsAux};
 use std::sync::atomic::{AtomicU64, Ordering};
 use syn::parse_macro_input;
 use syn::spanned::Spanned;
@@ -13,6 +13,7 @@ pub fn stat(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let (meta, ident, paths) = (stats_aux.meta, stats_aux.ident, stats_aux.paths);
 
     let mut output = proc_macro2::TokenStream::new();
+    let mut nested_hashes = vec![];
 
     for (_fname, field) in meta.fields.iter() {
         match &field.data {
@@ -28,12 +29,14 @@ pub fn stat(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                           struct #assert_id where #path: scx_stats::Meta;
                     };
                     output.extend(assert.into_iter());
+                    nested_hashes.push(quote! { <#path as scx_stats::Meta>::schema_hash() });
                 }
             }
         }
     }
 
     let body = serde_json::to_string(&meta).unwrap();
+    let own_hash = meta.schema_hash();
     let trait_body = quote! {
     #[rustfmt::skip]
     impl scx_stats::Meta for #ident {
@@ -41,6 +44,10 @@ pub fn stat(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             let body = #body;
             scx_stats::serde_json::from_str(body).unwrap()
         }
+
+        fn schema_hash() -> u64 {
+            scx_stats::schema_hash_combine(#own_hash, &[#(#nested_hashes),*])
+        }
     }
     };
     output.extend(trait_body);
@@ -48,6 +55,47 @@ pub fn stat(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     output.into()
 }
 
+#[proc_macro_derive(Params, attributes(param))]
+pub fn params(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let params_aux = parse_macro_input!(input as StatsParamsAux);
+    let (params, ident) = (params_aux.params, params_aux.ident);
+
+    let body = serde_json::to_string(&params).unwrap();
+    let names: Vec<&String> = params.keys().collect();
+    let fields: Vec<_> = names.iter().map(|name| format_ident!("{}", name)).collect();
+
+    let output = quote! {
+    #[rustfmt::skip]
+    impl scx_stats::Params for #ident {
+        fn params_meta() -> std::collections::BTreeMap<String, scx_stats::StatsParamMeta> {
+            let body = #body;
+            scx_stats::serde_json::from_str(body).unwrap()
+        }
+
+        fn get_param(&self, name: &str) -> Option<scx_stats::serde_json::Value> {
+            match name {
+                #(#names => scx_stats::serde_json::to_value(&self.#fields).ok(),)*
+                _ => None,
+            }
+        }
+
+        fn store_param(
+            &mut self,
+            name: &str,
+            value: scx_stats::serde_json::Value,
+        ) -> scx_stats::serde_json::Result<()> {
+            match name {
+                #(#names => self.#fields = scx_stats::serde_json::from_value(value)?,)*
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+    };
+
+    output.into()
+}
+
 #[proc_macro_attribute]
 pub fn stat_doc(
     _attr: proc_macro::TokenStream,
@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use scx_stats::prelude::*;
+use scx_stats::printer::{StatsFormat, StatsPrinter, StatsRecorder, StatsReplay};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Print the statistics of any scx_stats server, following the metadata it
+/// reports.
+#[derive(Debug, Parser)]
+#[command(name = "scxstats", version)]
+struct Opts {
+    /// UNIX domain socket path of the stats server to connect to.
+    #[clap(short = 'p', long, default_value = "/var/run/scx/root/stats")]
+    path: String,
+
+    /// Stats target to request. The table and CSV formats expect the
+    /// target to report the top-level statistics struct.
+    #[clap(short = 't', long, default_value = "top")]
+    target: String,
+
+    /// Interval between samples in seconds.
+    #[clap(short = 'i', long, default_value = "1.0", value_parser = parse_interval)]
+    interval: f64,
+
+    /// Exit after printing this many samples. 0 keeps printing.
+    #[clap(short = 'n', long, default_value = "0")]
+    count: usize,
+
+    /// Output format: table, json (JSON lines) or csv.
+    #[clap(short = 'f', long, default_value = "table")]
+    format: StatsFormat,
+
+    /// Print the field descriptions in the table format.
+    #[clap(short = 'd', long)]
+    desc: bool,
+
+    /// Also record the samples to FILE for later replay.
+    #[clap(short = 'r', long, value_name = "FILE")]
+    record: Option<String>,
+
+    /// Print the samples recorded in FILE instead of connecting to the
+    /// server.
+    #[clap(long, value_name = "FILE", conflicts_with = "record")]
+    replay: Option<String>,
+
+    /// Print the tunable parameters with their current values and exit.
+    #[clap(long, conflicts_with = "replay")]
+    params: bool,
+
+    /// Set the tunable parameter NAME to VALUE and exit. Can be repeated.
+    #[clap(long, value_name = "NAME=VALUE", conflicts_with = "replay")]
+    set: Vec<String>,
+}
+
+/// Parse an interval in seconds, which has to be positive and fit a Duration.
+fn parse_interval(arg: &str) -> Result<f64, String> {
+    let interval = arg.parse::<f64>().map_err(|err| err.to_string())?;
+    if interval <= 0.0 || Duration::try_from_secs_f64(interval).is_err() {
+        return Err(format!("{arg} is not a positive number of seconds"));
+    }
+    Ok(interval)
+}
+
+fn params(opts: &Opts) -> Result<()> {
+    let mut client = StatsClient::new().set_path(&opts.path).connect()?;
+
+    for assignment in opts.set.iter() {
+        let (name, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected NAME=VALUE, got {:?}", assignment))?;
+        let value = client
+            .set_param(name, value)
+            .with_context(|| format!("setting {:?}", name))?;
+        println!("{} = {}", name, value);
+    }
+    if !opts.params {
+        return Ok(());
+    }
+
+    let metas = client.request::<BTreeMap<String, StatsParamMeta>>("params_meta", vec![])?;
+    let values = client.request::<BTreeMap<String, Value>>("get_param", vec![])?;
+    let nwidth = metas.keys().map(|name| name.len()).max().unwrap_or(0);
+
+    for (name, meta) in metas.iter() {
+        let value = values.get(name).unwrap_or(&Value::Null);
+        let range = match (meta.min, meta.max) {
+            (None, None) => String::new(),
+            (min, max) => format!(
+                " [{}, {}]",
+                min.map_or("-".into(), |v| v.to_string()),
+                max.map_or("-".into(), |v| v.to_string())
+            ),
+        };
+        print!(
+            "{:nw$} = {} ({}{})",
+            name,
+            value,
+            meta.kind,
+            range,
+            nw = nwidth
+        );
+        match &meta.desc {
+            Some(desc) => println!(" : {}", desc),
+            None => println!(),
+        }
+    }
+    Ok(())
+}
+
+fn replay(opts: &Opts, path: &str) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("opening {:?}", path))?;
+    let replay = StatsReplay::new(BufReader::new(file))?;
+    let mut printer =
+        StatsPrinter::new(replay.metas().clone(), opts.format)?.set_descriptions(opts.desc);
+    let mut stdout = std::io::stdout().lock();
+
+    for (idx, sample) in replay.enumerate() {
+        if opts.count > 0 && idx >= opts.count {
+            break;
+        }
+        let (_ts, stats) = sample?;
+        printer.print(&mut stdout, &stats)?;
+    }
+    Ok(())
+}
+
+fn monitor(opts: &Opts) -> Result<()> {
+    let mut client = StatsClient::new().set_path(&opts.path).connect()?;
+    let metas = client.request::<BTreeMap<String, StatsMeta>>("stats_meta", vec![])?;
+
+    let mut printer = StatsPrinter::new(metas, opts.format)?.set_descriptions(opts.desc);
+    let mut recorder = match &opts.record {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("creating {:?}", path))?;
+            Some(StatsRecorder::new(BufWriter::new(file), printer.metas())?)
+        }
+        None => None,
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    let mut output = |stats: Value| -> Result<()> {
+        printer.print(&mut stdout, &stats)?;
+        stdout.flush()?;
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&stats)?;
+        }
+        Ok(())
+    };
+
+    let args = vec![("target".to_string(), opts.target.clone())];
+    let intv = Duration::from_secs_f64(opts.interval);
+    let count = match opts.count {
+        0 => usize::MAX,
+        v => v,
+    };
+
+    match client.subscribe::<Value>(args.clone(), intv, &[]) {
+        Ok(samples) => {
+            for stats in samples.take(count) {
+                output(stats?)?;
+            }
+        }
+        Err(e) if e.downcast_ref::<StatsErrno>().is_some() => {
+            // The server doesn't support subscriptions, poll instead.
+            let mut client = StatsClient::new().set_path(&opts.path).connect()?;
+            for idx in 0..count {
+                if idx > 0 {
+                    sleep(intv);
+                }
+                output(client.request::<Value>("stats", args.clone())?)?;
+            }
+        }
+        Err(e) => Err(e)?,
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    if opts.params || !opts.set.is_empty() {
+        return params(&opts);
+    }
+
+    match &opts.replay {
+        Some(path) => replay(&opts, path),
+        None => monitor(&opts),
+    }
+}
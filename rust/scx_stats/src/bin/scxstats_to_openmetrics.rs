@@ -0,0 +1,45 @@
+use anyhow::Result;
+use clap::Parser;
+use scx_stats::openmetrics::OpenMetricsExporter;
+use scx_stats::StatsListenAddr;
+
+/// Serve the statistics of a scx_stats server in the OpenMetrics format.
+#[derive(Debug, Parser)]
+#[command(name = "scxstats_to_openmetrics", version)]
+struct Opts {
+    /// UNIX domain socket path of the stats server to connect to.
+    #[clap(short = 'p', long, default_value = "/var/run/scx/root/stats")]
+    path: String,
+
+    /// Address of the stats server to connect to instead of --path, either
+    /// "unix:PATH", "abstract:NAME" (or "@NAME") or a TCP socket address.
+    #[clap(short = 'a', long, conflicts_with = "path")]
+    addr: Option<StatsListenAddr>,
+
+    /// Address to serve "/metrics" on, in the same form as --addr.
+    #[clap(short = 'l', long, default_value = "127.0.0.1:9190")]
+    listen: StatsListenAddr,
+
+    /// Enable verbose output.
+    #[clap(short = 'v', long, action = clap::ArgAction::Count)]
+    verbose: u8,
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    let level = match opts.verbose {
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    simple_logger::SimpleLogger::new()
+        .with_level(level)
+        .env()
+        .init()?;
+
+    let addr = opts
+        .addr
+        .unwrap_or_else(|| StatsListenAddr::Unix(opts.path.into()));
+    OpenMetricsExporter::new(addr).serve(&opts.listen)
+}
@@ -1,19 +1,24 @@
-use crate::{StatsErrno, StatsRequest, StatsResponse};
+use crate::transport::StatsStream;
+use crate::{StatsEncoding, StatsErrno, StatsListenAddr, StatsRequest};
 use anyhow::{anyhow, bail, Result};
-use log::trace;
+use log::{debug, trace};
 use serde::Deserialize;
-use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::UnixStream;
+use std::collections::BTreeMap;
+use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub struct StatsClient {
     base_path: PathBuf,
     sched_path: PathBuf,
     stats_path: PathBuf,
     path: Option<PathBuf>,
+    addr: Option<StatsListenAddr>,
 
-    stream: Option<UnixStream>,
-    reader: Option<BufReader<UnixStream>>,
+    encoding: StatsEncoding,
+
+    stream: Option<StatsStream>,
+    reader: Option<BufReader<StatsStream>>,
 }
 
 impl StatsClient {
@@ -23,6 +28,9 @@ impl StatsClient {
             sched_path: PathBuf::from("root"),
             stats_path: PathBuf::from("stats"),
             path: None,
+            addr: None,
+
+            encoding: StatsEncoding::Json,
 
             stream: None,
             reader: None,
@@ -49,34 +57,66 @@ impl StatsClient {
         self
     }
 
+    /// Connect to an abstract UNIX domain socket or a TCP port instead of
+    /// the UNIX domain socket at the path.
+    pub fn set_addr(mut self, addr: StatsListenAddr) -> Self {
+        self.addr = Some(addr);
+        self
+    }
+
+    /// Request responses in `encoding`. It's negotiated with a "hello"
+    /// request on connect and JSON is used if the server doesn't support
+    /// it. See `encoding()` for the encoding in use.
+    pub fn set_encoding(mut self, encoding: StatsEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn encoding(&self) -> StatsEncoding {
+        self.encoding
+    }
+
     pub fn connect(mut self) -> Result<Self> {
         if self.path.is_none() {
             self.path = Some(self.base_path.join(&self.sched_path).join(&self.stats_path));
         }
-        let path = &self.path.as_ref().unwrap();
+        let addr = match &self.addr {
+            Some(v) => v.clone(),
+            None => StatsListenAddr::Unix(self.path.clone().unwrap()),
+        };
 
-        let stream = UnixStream::connect(path)?;
+        let stream = StatsStream::connect(&addr)?;
         self.stream = Some(stream.try_clone()?);
         self.reader = Some(BufReader::new(stream));
+
+        let encoding = std::mem::take(&mut self.encoding);
+        if encoding != StatsEncoding::Json {
+            let args = vec![("encodings".into(), encoding.name().into())];
+            match self.request::<serde_json::Value>("hello", args) {
+                Ok(resp) => {
+                    if let Some(name) = resp.get("encoding").and_then(|v| v.as_str()) {
+                        self.encoding = name.parse()?;
+                    }
+                }
+                Err(e) if e.downcast_ref::<StatsErrno>().is_some() => {
+                    debug!("server doesn't support encoding negotiation ({})", e);
+                }
+                Err(e) => Err(e)?,
+            }
+        }
+
         Ok(self)
     }
 
-    pub fn send_request<T>(&mut self, req: &StatsRequest) -> Result<T>
+    fn recv_response<T>(&mut self) -> Result<T>
     where
         T: for<'a> Deserialize<'a>,
     {
-        if self.stream.is_none() {
-            bail!("not connected");
-        }
-
-        let req = serde_json::to_string(&req)? + "\n";
-        trace!("Sending: {}", req.trim());
-        self.stream.as_ref().unwrap().write_all(req.as_bytes())?;
-
-        let mut line = String::new();
-        self.reader.as_mut().unwrap().read_line(&mut line)?;
-        trace!("Received: {}", line.trim());
-        let mut resp: StatsResponse = serde_json::from_str(&line)?;
+        let mut resp = match self.encoding.decode(self.reader.as_mut().unwrap())? {
+            Some(v) => v,
+            None => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?,
+        };
+        trace!("Received: {:?}", &resp);
 
         let (errno, resp) = (
             resp.errno,
@@ -90,10 +130,154 @@ impl StatsClient {
         Ok(serde_json::from_value(resp)?)
     }
 
+    pub fn send_request<T>(&mut self, req: &StatsRequest) -> Result<T>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        if self.stream.is_none() {
+            bail!("not connected");
+        }
+
+        let req = serde_json::to_string(&req)? + "\n";
+        trace!("Sending: {}", req.trim());
+        self.stream.as_mut().unwrap().write_all(req.as_bytes())?;
+
+        self.recv_response()
+    }
+
     pub fn request<T>(&mut self, req: &str, args: Vec<(String, String)>) -> Result<T>
     where
         T: for<'a> Deserialize<'a>,
     {
         self.send_request(&StatsRequest::new(req, args))
     }
+
+    /// Current value of the tunable parameter `name`.
+    pub fn get_param(&mut self, name: &str) -> Result<serde_json::Value> {
+        let mut resp: BTreeMap<String, serde_json::Value> =
+            self.request("get_param", vec![("name".into(), name.into())])?;
+        resp.remove(name)
+            .ok_or_else(|| anyhow!("{:?} missing in response", name))
+    }
+
+    /// Set the tunable parameter `name` and return the value in effect.
+    /// The server checks `value` against the parameter's type and range.
+    pub fn set_param(&mut self, name: &str, value: &str) -> Result<serde_json::Value> {
+        let args = vec![("name".into(), name.into()), ("value".into(), value.into())];
+        let mut resp: BTreeMap<String, serde_json::Value> = self.request("set", args)?;
+        resp.remove(name)
+            .ok_or_else(|| anyhow!("{:?} missing in response", name))
+    }
+
+    /// Subscribe to the stats selected by `args` as with a "stats" request.
+    /// The server pushes a sample every `intv`, which must be at least a
+    /// millisecond, until the returned iterator is dropped. The connection
+    /// is dedicated to the subscription, so the client is consumed.
+    /// `fields` limits the samples to the given top-level fields if not
+    /// empty.
+    ///
+    /// The first sample is received before returning so that servers
+    /// which don't support subscriptions fail here with `StatsErrno`. A
+    /// new client has to be connected to poll such servers.
+    pub fn subscribe<T>(
+        mut self,
+        mut args: Vec<(String, String)>,
+        intv: Duration,
+        fields: &[&str],
+    ) -> Result<StatsSubscription<T>>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        args.push(("interval_ms".into(), intv.as_millis().to_string()));
+        if !fields.is_empty() {
+            args.push(("fields".into(), fields.join(",")));
+        }
+
+        let first = self.send_request::<T>(&StatsRequest::new("subscribe", args))?;
+
+        Ok(StatsSubscription {
+            client: self,
+            first: Some(first),
+            done: false,
+        })
+    }
+}
+
+/// Iterator over the samples pushed by the server after
+/// `StatsClient::subscribe()`. It ends after the first error, which
+/// includes the server closing the connection.
+pub struct StatsSubscription<T> {
+    client: StatsClient,
+    first: Option<T>,
+    done: bool,
+}
+
+impl<T> Iterator for StatsSubscription<T>
+where
+    T: for<'a> Deserialize<'a>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if let Some(first) = self.first.take() {
+            return Some(Ok(first));
+        }
+        if self.done {
+            return None;
+        }
+
+        let res = self.client.recv_response();
+        self.done = res.is_err();
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{StatsServer, StatsServerData};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_subscribe() {
+        let path = std::env::temp_dir().join(format!("scx_stats-sub-{}", std::process::id()));
+        let seq = Arc::new(AtomicU64::new(0));
+        let sdata = StatsServerData::<(), ()>::new().add_stats(
+            "top",
+            Box::new(move |_args, _chan| {
+                let at = seq.fetch_add(1, Ordering::Relaxed);
+                Ok(serde_json::json!({ "at": at, "name": "test" }))
+            }),
+        );
+        let _server = StatsServer::new(sdata).set_path(&path).launch().unwrap();
+        let connect = || StatsClient::new().set_path(&path).connect().unwrap();
+
+        // samples are pushed at the interval and limited to the fields
+        let samples: Vec<serde_json::Value> = connect()
+            .subscribe(vec![], Duration::from_millis(10), &["at"])
+            .unwrap()
+            .take(3)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(samples.len(), 3);
+        for (prev, cur) in samples.iter().zip(&samples[1..]) {
+            assert!(cur["at"].as_u64().unwrap() > prev["at"].as_u64().unwrap());
+        }
+        assert!(samples.iter().all(|v| v.get("name").is_none()));
+
+        // zero intervals are refused instead of pushing samples in a loop
+        let err = connect()
+            .subscribe::<serde_json::Value>(vec![], Duration::ZERO, &[])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<StatsErrno>().map(|e| e.0),
+            Some(libc::EINVAL)
+        );
+
+        // servers refusing the subscription are polled over a new connection
+        let stats: serde_json::Value = connect().request("stats", vec![]).unwrap();
+        assert_eq!(stats["name"], "test");
+    }
 }
@@ -0,0 +1,129 @@
+use crate::StatsResponse;
+use anyhow::{bail, Result};
+use std::io::BufRead;
+use std::str::FromStr;
+
+/// Wire encoding of the responses on a connection.
+///
+/// Requests are always sent as JSON lines. Responses are JSON lines by
+/// default. After a "hello" request has selected a binary encoding, each
+/// response is sent as a 32bit big-endian length followed by that many
+/// bytes of encoded response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StatsEncoding {
+    #[default]
+    Json,
+    Cbor,
+    MsgPack,
+}
+
+impl StatsEncoding {
+    pub const ALL: [StatsEncoding; 3] = [Self::Json, Self::Cbor, Self::MsgPack];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Cbor => "cbor",
+            Self::MsgPack => "msgpack",
+        }
+    }
+
+    /// Encode `resp` including the framing.
+    pub fn encode(&self, resp: &StatsResponse) -> Result<Vec<u8>> {
+        let payload = match self {
+            Self::Json => return Ok((serde_json::to_string(resp)? + "\n").into_bytes()),
+            Self::Cbor => {
+                let mut buf = vec![];
+                ciborium::into_writer(resp, &mut buf)?;
+                buf
+            }
+            Self::MsgPack => rmp_serde::to_vec_named(resp)?,
+        };
+
+        let len = match u32::try_from(payload.len()) {
+            Ok(v) => v,
+            Err(_) => bail!("response too large ({} bytes)", payload.len()),
+        };
+        let mut buf = Vec::with_capacity(4 + payload.len());
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend(payload);
+        Ok(buf)
+    }
+
+    /// Read and decode the next response. Returns None on EOF.
+    pub fn decode<R: BufRead>(&self, reader: &mut R) -> Result<Option<StatsResponse>> {
+        if *self == Self::Json {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(serde_json::from_str(&line)?));
+        }
+
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => Err(e)?,
+        }
+        let mut payload = vec![0u8; u32::from_be_bytes(len) as usize];
+        reader.read_exact(&mut payload)?;
+
+        Ok(Some(match self {
+            Self::Json => unreachable!(),
+            Self::Cbor => ciborium::from_reader(payload.as_slice())?,
+            Self::MsgPack => rmp_serde::from_slice(&payload)?,
+        }))
+    }
+}
+
+impl FromStr for StatsEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Self::ALL.iter().find(|enc| enc.name() == s) {
+            Some(enc) => Ok(*enc),
+            None => bail!("unknown encoding {:?}", s),
+        }
+    }
+}
+
+impl std::fmt::Display for StatsEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_roundtrip() {
+        let resp = StatsResponse {
+            errno: 0,
+            args: [(
+                "resp".to_string(),
+                json!({"at": 12345, "load": 1.5, "doms": {"0": {"name": "dom0"}}}),
+            )]
+            .into_iter()
+            .collect(),
+        };
+
+        for enc in StatsEncoding::ALL {
+            let mut buf = enc.encode(&resp).unwrap();
+            buf.extend(enc.encode(&resp).unwrap());
+
+            let mut reader = BufReader::new(buf.as_slice());
+            for _ in 0..2 {
+                let decoded = enc.decode(&mut reader).unwrap().unwrap();
+                assert_eq!(decoded.errno, 0);
+                assert_eq!(decoded.args, resp.args, "{}", enc);
+            }
+            assert!(enc.decode(&mut reader).unwrap().is_none());
+            assert_eq!(enc.name().parse::<StatsEncoding>().unwrap(), enc);
+        }
+    }
+}
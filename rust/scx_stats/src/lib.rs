@@ -1,19 +1,39 @@
 pub use serde_json;
 
+// Let the code generated by scx_stats_derive refer to this crate in tests.
+#[cfg(test)]
+extern crate self as scx_stats;
+
 mod stats;
 pub use stats::{
     Meta, StatsAttr, StatsData, StatsField, StatsFieldAttrs, StatsKind, StatsMeta, StatsMetaAux,
     StatsStructAttrs,
 };
 
+mod schema;
+pub use schema::{diff_stats_meta, schema_hash_combine, StatsSchema, StatsSchemaChange};
+
+mod params;
+pub use params::{Params, StatsParamBound, StatsParamKind, StatsParamMeta, StatsParamsAux};
+
+mod encoding;
+pub use encoding::StatsEncoding;
+
+mod transport;
+pub use transport::{StatsAccess, StatsListenAddr, StatsPeer};
+
 mod server;
 pub use server::{
-    StatsCloser, StatsErrno, StatsOpener, StatsOps, StatsReader, StatsReaderSend, StatsReaderSync,
-    StatsRequest, StatsResponse, StatsServer, StatsServerData, ToJson,
+    StatsCloser, StatsErrno, StatsOpener, StatsOps, StatsParamGetter, StatsParamOps,
+    StatsParamSetter, StatsReader, StatsReaderSend, StatsReaderSync, StatsRequest, StatsResponse,
+    StatsServer, StatsServerData, ToJson,
 };
 
 mod client;
-pub use client::StatsClient;
+pub use client::{StatsClient, StatsSubscription};
+
+pub mod openmetrics;
+pub mod printer;
 
 pub mod prelude {
     pub use crate::*;
@@ -0,0 +1,548 @@
+//! Translate the stats of a scx_stats server into the OpenMetrics text format.
+//!
+//! The translation is driven by the `stats_meta` of the server and the
+//! following user attributes:
+//!
+//! - `_om_prefix` (struct): Prefixed to the field names of the struct to form
+//!   the metric names.
+//! - `_om_label` (struct or field): Name of the label which holds the dict
+//!   keys. For a dict of structs, the attribute of the struct is used unless
+//!   the dict field sets one itself.
+//! - `_om_skip` (field): The field is not exported.
+//! - `_om_counter` (field): The field is a monotonically increasing counter
+//!   instead of a gauge.
+use crate::transport::StatsListener;
+use crate::{StatsClient, StatsData, StatsKind, StatsListenAddr, StatsMeta};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PathElem {
+    Field(String),
+    DictKeys(String),
+}
+
+#[derive(Clone, Debug)]
+struct MetricFamily {
+    name: String,
+    help: String,
+    kind: MetricKind,
+    path: Vec<PathElem>,
+}
+
+/// Translates stats responses into OpenMetrics families following the
+/// `stats_meta` of the server.
+#[derive(Clone, Debug)]
+pub struct OpenMetrics {
+    families: Vec<MetricFamily>,
+}
+
+impl OpenMetrics {
+    pub fn new(metas: &BTreeMap<String, StatsMeta>) -> Result<Self> {
+        let top = metas
+            .values()
+            .find(|meta| meta.attrs.top.is_some())
+            .ok_or_else(|| anyhow!("top-level stats metadata missing"))?;
+
+        let mut om = Self { families: vec![] };
+        let mut nesting = BTreeSet::new();
+        om.add_struct(metas, top, &[], &mut nesting)?;
+
+        let mut names = BTreeSet::new();
+        for family in om.families.iter() {
+            if !names.insert(&family.name) {
+                bail!("duplicate OpenMetrics metric name {:?}", &family.name);
+            }
+        }
+
+        Ok(om)
+    }
+
+    fn add_struct(
+        &mut self,
+        metas: &BTreeMap<String, StatsMeta>,
+        meta: &StatsMeta,
+        path: &[PathElem],
+        nesting: &mut BTreeSet<String>,
+    ) -> Result<()> {
+        if !nesting.insert(meta.name.clone()) {
+            bail!("loop in stats meta detected, {} already nested", &meta.name);
+        }
+
+        let prefix = meta.attrs.user.get("_om_prefix").map_or("", |v| v.as_str());
+
+        for (fname, field) in meta.fields.iter() {
+            if field.attrs.user.contains_key("_om_skip") {
+                debug!("skipping {}.{} due to _om_skip", &meta.name, fname);
+                continue;
+            }
+
+            let mut fpath = path.to_vec();
+            fpath.push(PathElem::Field(fname.clone()));
+
+            let datum = match &field.data {
+                StatsData::Datum(kind) => kind,
+                StatsData::Dict { key: _, datum } => {
+                    let label = match (field.attrs.user.get("_om_label"), datum) {
+                        (Some(label), _) => label.clone(),
+                        (None, StatsKind::Struct(sname)) => metas
+                            .get(sname)
+                            .and_then(|m| m.attrs.user.get("_om_label"))
+                            .ok_or_else(|| {
+                                anyhow!("{}.{} is a dict but has no _om_label", &meta.name, fname)
+                            })?
+                            .clone(),
+                        (None, _) => {
+                            bail!("{}.{} is a dict but has no _om_label", &meta.name, fname)
+                        }
+                    };
+                    if !is_valid_name(&label) || label.contains(':') {
+                        bail!(
+                            "{}.{} has invalid _om_label {:?}",
+                            &meta.name,
+                            fname,
+                            &label
+                        );
+                    }
+                    fpath.push(PathElem::DictKeys(label));
+                    datum
+                }
+                StatsData::Array(_) => {
+                    info!("{}.{} is an array, skipping", &meta.name, fname);
+                    continue;
+                }
+            };
+
+            match datum {
+                StatsKind::I64 | StatsKind::U64 | StatsKind::Float => {
+                    let kind = match field.attrs.user.contains_key("_om_counter") {
+                        true => MetricKind::Counter,
+                        false => MetricKind::Gauge,
+                    };
+                    let mut name = format!("{}{}", prefix, fname);
+                    if kind == MetricKind::Counter {
+                        if let Some(stripped) = name.strip_suffix("_total") {
+                            name = stripped.to_string();
+                        }
+                    }
+                    if !is_valid_name(&name) {
+                        bail!(
+                            "{}.{} has invalid metric name {:?}",
+                            &meta.name,
+                            fname,
+                            &name
+                        );
+                    }
+                    self.families.push(MetricFamily {
+                        name,
+                        help: field.attrs.desc.clone().unwrap_or_default(),
+                        kind,
+                        path: fpath,
+                    });
+                }
+                StatsKind::Struct(sname) => {
+                    let inner = metas
+                        .get(sname)
+                        .ok_or_else(|| anyhow!("unknown stats meta name {}", sname))?;
+                    self.add_struct(metas, inner, &fpath, nesting)?;
+                }
+                StatsKind::String => {
+                    debug!("{}.{} is a string, skipping", &meta.name, fname);
+                }
+            }
+        }
+
+        nesting.remove(&meta.name);
+        Ok(())
+    }
+
+    /// Names of the exported metric families.
+    pub fn names(&self) -> Vec<&str> {
+        self.families.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    fn collect<'a>(
+        value: &'a Value,
+        path: &[PathElem],
+        labels: &mut Vec<(String, String)>,
+        samples: &mut Vec<(Vec<(String, String)>, &'a Value)>,
+    ) {
+        match path.split_first() {
+            None => samples.push((labels.clone(), value)),
+            Some((PathElem::Field(name), rest)) => {
+                if let Some(v) = value.get(name) {
+                    Self::collect(v, rest, labels, samples);
+                }
+            }
+            Some((PathElem::DictKeys(label), rest)) => {
+                if let Some(dict) = value.as_object() {
+                    for (key, v) in dict.iter() {
+                        labels.push((label.clone(), key.clone()));
+                        Self::collect(v, rest, labels, samples);
+                        labels.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Render the response of a "stats" request in the OpenMetrics text
+    /// format, including the terminating "# EOF".
+    pub fn render(&self, stats: &Value) -> String {
+        let mut out = String::new();
+
+        for family in self.families.iter() {
+            let (kind, suffix) = match family.kind {
+                MetricKind::Gauge => ("gauge", ""),
+                MetricKind::Counter => ("counter", "_total"),
+            };
+            writeln!(out, "# TYPE {} {}", &family.name, kind).unwrap();
+            if !family.help.is_empty() {
+                writeln!(
+                    out,
+                    "# HELP {} {}",
+                    &family.name,
+                    escape(&family.help, false)
+                )
+                .unwrap();
+            }
+
+            let mut samples = vec![];
+            Self::collect(stats, &family.path, &mut vec![], &mut samples);
+
+            for (labels, value) in samples {
+                let value = match format_value(value) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                write!(out, "{}{}", &family.name, suffix).unwrap();
+                if !labels.is_empty() {
+                    let labels: Vec<String> = labels
+                        .iter()
+                        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v, true)))
+                        .collect();
+                    write!(out, "{{{}}}", labels.join(",")).unwrap();
+                }
+                writeln!(out, " {}", value).unwrap();
+            }
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn escape(s: &str, quote: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quote => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_value(value: &Value) -> Option<String> {
+    let num = value.as_number()?;
+    if num.is_f64() {
+        let v = num.as_f64()?;
+        Some(match v {
+            v if v.is_nan() => "NaN".into(),
+            v if v == f64::INFINITY => "+Inf".into(),
+            v if v == f64::NEG_INFINITY => "-Inf".into(),
+            v => format!("{}", v),
+        })
+    } else {
+        Some(num.to_string())
+    }
+}
+
+/// Serves the stats of a scx_stats server on "/metrics" over HTTP.
+///
+/// The connection to the stats server is established on the first scrape
+/// and re-established, including the metadata, after any failure so that
+/// the exporter survives scheduler restarts.
+pub struct OpenMetricsExporter {
+    stats_addr: StatsListenAddr,
+    conn: Option<(StatsClient, OpenMetrics)>,
+}
+
+impl OpenMetricsExporter {
+    /// Export the stats of the server listening on `stats_addr`.
+    pub fn new(stats_addr: StatsListenAddr) -> Self {
+        Self {
+            stats_addr,
+            conn: None,
+        }
+    }
+
+    /// Request the stats and render them in the OpenMetrics text format.
+    pub fn scrape(&mut self) -> Result<String> {
+        if self.conn.is_none() {
+            let mut client = StatsClient::new()
+                .set_addr(self.stats_addr.clone())
+                .connect()?;
+            let metas = client.request::<BTreeMap<String, StatsMeta>>("stats_meta", vec![])?;
+            let om = OpenMetrics::new(&metas)?;
+            debug!("exporting {:?}", om.names());
+            self.conn = Some((client, om));
+        }
+
+        let (client, om) = self.conn.as_mut().unwrap();
+        match client.request::<Value>("stats", vec![]) {
+            Ok(stats) => Ok(om.render(&stats)),
+            Err(e) => {
+                self.conn = None;
+                Err(e)
+            }
+        }
+    }
+
+    fn respond<S: Read + Write>(&mut self, stream: S) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let path = target.split('?').next().unwrap_or("");
+
+        let (status, content_type, body) = match (method, path) {
+            ("GET", "/metrics") => match self.scrape() {
+                Ok(body) => ("200 OK", OPENMETRICS_CONTENT_TYPE, body),
+                Err(e) => {
+                    warn!("failed to scrape {} ({:#})", &self.stats_addr, &e);
+                    (
+                        "503 Service Unavailable",
+                        "text/plain; charset=utf-8",
+                        format!("{:#}\n", &e),
+                    )
+                }
+            },
+            ("GET", _) => ("404 Not Found", "text/plain; charset=utf-8", "".into()),
+            _ => (
+                "405 Method Not Allowed",
+                "text/plain; charset=utf-8",
+                "".into(),
+            ),
+        };
+
+        let stream = reader.get_mut();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        )?;
+        stream.write_all(body.as_bytes())?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Serve "/metrics" on `addr`. Requests are handled one at a time as
+    /// each scrape is a single round-trip to the stats server. Unlike the
+    /// stats themselves, the metrics can be served on any TCP address.
+    pub fn serve(&mut self, addr: &StatsListenAddr) -> Result<()> {
+        let listener = match addr {
+            StatsListenAddr::Tcp(sockaddr) => StatsListener::Tcp(
+                TcpListener::bind(sockaddr).with_context(|| format!("binding to {}", sockaddr))?,
+            ),
+            StatsListenAddr::Unix(path) => {
+                let res = std::fs::remove_file(path);
+                if let Err(e) = &res {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        res.with_context(|| format!("deleting {:?}", path))?;
+                    }
+                }
+                StatsListener::bind(addr)?
+            }
+            StatsListenAddr::Abstract(_) => StatsListener::bind(addr)?,
+        };
+        match listener.local_addr(addr) {
+            StatsListenAddr::Tcp(sockaddr) => {
+                info!("serving OpenMetrics on http://{}/metrics", sockaddr)
+            }
+            addr => info!("serving OpenMetrics on {}", addr),
+        }
+
+        let timeout = Some(Duration::from_secs(5));
+        loop {
+            let stream = listener.accept().and_then(|stream| {
+                stream.set_timeout(timeout)?;
+                Ok(stream)
+            });
+            if let Err(e) = stream
+                .map_err(anyhow::Error::from)
+                .and_then(|s| self.respond(s))
+            {
+                warn!("OpenMetrics connection failed ({:#})", &e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{StatsServer, StatsServerData};
+    use serde_json::json;
+
+    fn metas() -> BTreeMap<String, StatsMeta> {
+        serde_json::from_value(json!({
+            "ClusterStats": {
+                "name": "ClusterStats",
+                "top": "true",
+                "fields": {
+                    "name": {"datum": "string"},
+                    "at": {"datum": "u64", "desc": "timestamp"},
+                    "nr_dispatched": {"datum": "u64", "user": {"_om_counter": "true"}},
+                    "bitmap": {"array": "u64", "user": {"_om_skip": "true"}},
+                    "lat": {"datum": {"struct": "LatStats"}},
+                    "doms_dict": {
+                        "dict": {"key": "u64", "datum": {"struct": "DomainStats"}},
+                        "desc": "domain statistics",
+                    },
+                },
+            },
+            "DomainStats": {
+                "name": "DomainStats",
+                "user": {"_om_prefix": "d_", "_om_label": "domain_name"},
+                "fields": {
+                    "load": {"datum": "float", "desc": "load \"sum\"\nof tasks"},
+                    "util": {
+                        "dict": {"key": "u64", "datum": "float"},
+                        "user": {"_om_label": "cpu"},
+                    },
+                },
+            },
+            "LatStats": {
+                "name": "LatStats",
+                "user": {"_om_prefix": "lat_"},
+                "fields": {
+                    "avg_us": {"datum": "float"},
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let om = OpenMetrics::new(&metas()).unwrap();
+        let stats = json!({
+            "name": "cluster",
+            "at": 42,
+            "nr_dispatched": 1000,
+            "bitmap": [1, 2],
+            "lat": {"avg_us": 12.5},
+            "doms_dict": {
+                "0": {"load": 1.5, "util": {"0": 0.25, "1": 0.5}},
+                "1": {"load": 2.0, "util": {}},
+            },
+        });
+
+        assert_eq!(
+            om.render(&stats),
+            "# TYPE at gauge\n\
+             # HELP at timestamp\n\
+             at 42\n\
+             # TYPE d_load gauge\n\
+             # HELP d_load load \"sum\"\\nof tasks\n\
+             d_load{domain_name=\"0\"} 1.5\n\
+             d_load{domain_name=\"1\"} 2\n\
+             # TYPE d_util gauge\n\
+             d_util{domain_name=\"0\",cpu=\"0\"} 0.25\n\
+             d_util{domain_name=\"0\",cpu=\"1\"} 0.5\n\
+             # TYPE lat_avg_us gauge\n\
+             lat_avg_us 12.5\n\
+             # TYPE nr_dispatched counter\n\
+             nr_dispatched_total 1000\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_meta() {
+        let mut metas = metas();
+        metas
+            .get_mut("DomainStats")
+            .unwrap()
+            .attrs
+            .user
+            .remove("_om_label");
+        assert!(OpenMetrics::new(&metas).is_err());
+
+        let mut metas = self::metas();
+        metas
+            .get_mut("LatStats")
+            .unwrap()
+            .attrs
+            .user
+            .insert("_om_prefix".into(), "d_".into());
+        let lat = metas.get_mut("LatStats").unwrap();
+        let load = lat.fields.remove("avg_us").unwrap();
+        lat.fields.insert("load".into(), load);
+        assert!(OpenMetrics::new(&metas).is_err());
+    }
+
+    #[test]
+    fn test_scrape() {
+        let path = std::env::temp_dir().join(format!("scx_stats-om-{}", std::process::id()));
+        let name = format!("scx_stats-om-{}", std::process::id());
+        let mut sdata = StatsServerData::<(), ()>::new().add_stats(
+            "top",
+            Box::new(|_args, _chan| Ok(json!({"at": 42, "lat": {"avg_us": 12.5}}))),
+        );
+        for meta in metas().into_values() {
+            sdata = sdata.add_meta(meta);
+        }
+        let _server = StatsServer::new(sdata)
+            .set_path(&path)
+            .add_listener(StatsListenAddr::Abstract(name.clone()))
+            .launch()
+            .unwrap();
+
+        // the exporter connects to any of the addresses the server listens on
+        for addr in [StatsListenAddr::Unix(path), StatsListenAddr::Abstract(name)] {
+            let mut exporter = OpenMetricsExporter::new(addr);
+            let metrics = exporter.scrape().unwrap();
+            assert!(metrics.starts_with("# TYPE at gauge\n# HELP at timestamp\nat 42\n"));
+            assert!(metrics.contains("lat_avg_us 12.5\n"));
+        }
+    }
+}
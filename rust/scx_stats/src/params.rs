@@ -0,0 +1,491 @@
+//! Tunable parameters which can be read and changed at runtime with the
+//! "params_meta", "get_param" and "set" requests.
+use crate::StatsErrno;
+use anyhow::{anyhow, Result};
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use syn::parse::{Parse, ParseBuffer};
+use syn::spanned::Spanned;
+use syn::{Attribute, Error, Fields, Ident, ItemStruct, Lit, LitStr, Token, Type};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatsParamKind {
+    #[serde(rename = "bool")]
+    Bool,
+    #[serde(rename = "i64")]
+    I64,
+    #[serde(rename = "u64")]
+    U64,
+    #[serde(rename = "float")]
+    Float,
+    #[serde(rename = "string")]
+    String,
+}
+
+impl StatsParamKind {
+    pub fn new(ty: &Type) -> syn::Result<Self> {
+        if let Type::Path(path) = ty {
+            if let Some(ident) = path.path.get_ident() {
+                match ident.to_string().as_str() {
+                    "bool" => return Ok(Self::Bool),
+                    "i8" | "i16" | "i32" | "i64" | "isize" => return Ok(Self::I64),
+                    "u8" | "u16" | "u32" | "u64" | "usize" => return Ok(Self::U64),
+                    "f32" | "f64" => return Ok(Self::Float),
+                    "String" => return Ok(Self::String),
+                    _ => {}
+                }
+            }
+        }
+        Err(Error::new(
+            ty.span(),
+            format!("scx_stats: Unsupported param type {}", ty.to_token_stream()),
+        ))
+    }
+}
+
+impl std::fmt::Display for StatsParamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Bool => write!(f, "bool"),
+            Self::I64 => write!(f, "i64"),
+            Self::U64 => write!(f, "u64"),
+            Self::Float => write!(f, "float"),
+            Self::String => write!(f, "string"),
+        }
+    }
+}
+
+/// Bound of a parameter's range or a parsed numeric value. Integers are kept
+/// in their native type so that they compare exactly, floats are only
+/// involved if either side is a float.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StatsParamBound {
+    U64(u64),
+    I64(i64),
+    Float(f64),
+}
+
+impl StatsParamBound {
+    fn as_f64(&self) -> f64 {
+        match *self {
+            Self::U64(v) => v as f64,
+            Self::I64(v) => v as f64,
+            Self::Float(v) => v,
+        }
+    }
+}
+
+impl From<u64> for StatsParamBound {
+    fn from(v: u64) -> Self {
+        Self::U64(v)
+    }
+}
+
+impl From<i64> for StatsParamBound {
+    fn from(v: i64) -> Self {
+        Self::I64(v)
+    }
+}
+
+impl From<f64> for StatsParamBound {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl PartialEq for StatsParamBound {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for StatsParamBound {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (*self, *other) {
+            (Self::U64(a), Self::U64(b)) => Some(a.cmp(&b)),
+            (Self::I64(a), Self::I64(b)) => Some(a.cmp(&b)),
+            // negative i64's are below any u64, the rest fits in u64
+            (Self::I64(a), Self::U64(b)) => Some(match u64::try_from(a) {
+                Ok(a) => a.cmp(&b),
+                Err(_) => Ordering::Less,
+            }),
+            (Self::U64(_), Self::I64(_)) => other.partial_cmp(self).map(Ordering::reverse),
+            (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+        }
+    }
+}
+
+impl std::fmt::Display for StatsParamBound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::U64(v) => write!(f, "{}", v),
+            Self::I64(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Type, range and description of a tunable parameter.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatsParamMeta {
+    #[serde(rename = "type")]
+    pub kind: StatsParamKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<StatsParamBound>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<StatsParamBound>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+}
+
+impl StatsParamMeta {
+    pub fn new(kind: StatsParamKind) -> Self {
+        Self {
+            kind,
+            min: None,
+            max: None,
+            desc: None,
+        }
+    }
+
+    pub fn set_range<T: Into<StatsParamBound>>(mut self, min: Option<T>, max: Option<T>) -> Self {
+        self.min = min.map(Into::into);
+        self.max = max.map(Into::into);
+        self
+    }
+
+    pub fn set_desc(mut self, desc: &str) -> Self {
+        self.desc = Some(desc.to_string());
+        self
+    }
+
+    /// Parse `value` as sent in a "set" request. Fails with `EINVAL` if it
+    /// isn't of the parameter's type and `ERANGE` if it's out of range.
+    pub fn parse(&self, value: &str) -> Result<Value> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            anyhow!("invalid {} value {:?} ({})", self.kind, value, e)
+                .context(StatsErrno(libc::EINVAL))
+        };
+
+        let (parsed, num) = match self.kind {
+            StatsParamKind::Bool => (
+                Value::from(value.parse::<bool>().map_err(|e| invalid(&e))?),
+                None,
+            ),
+            StatsParamKind::I64 => {
+                let v = value.parse::<i64>().map_err(|e| invalid(&e))?;
+                (Value::from(v), Some(StatsParamBound::I64(v)))
+            }
+            StatsParamKind::U64 => {
+                let v = value.parse::<u64>().map_err(|e| invalid(&e))?;
+                (Value::from(v), Some(StatsParamBound::U64(v)))
+            }
+            StatsParamKind::Float => {
+                let v = value.parse::<f64>().map_err(|e| invalid(&e))?;
+                if !v.is_finite() {
+                    Err(invalid(&"not finite"))?;
+                }
+                (Value::from(v), Some(StatsParamBound::Float(v)))
+            }
+            StatsParamKind::String => (Value::from(value), None),
+        };
+
+        if let Some(num) = num {
+            if self.min.is_some_and(|min| num < min) || self.max.is_some_and(|max| num > max) {
+                Err(anyhow!(
+                    "{} out of range [{}, {}]",
+                    value,
+                    self.min.map_or("-".into(), |v| v.to_string()),
+                    self.max.map_or("-".into(), |v| v.to_string())
+                )
+                .context(StatsErrno(libc::ERANGE)))?;
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// A struct of tunable parameters. Usually implemented with the `Params`
+/// derive macro from `scx_stats_derive`.
+pub trait Params {
+    fn params_meta() -> BTreeMap<String, StatsParamMeta>;
+
+    /// Current value of `name`, None if there's no such parameter.
+    fn get_param(&self, name: &str) -> Option<Value>;
+
+    /// Store a value which has already been checked with
+    /// `StatsParamMeta::parse()`. Unknown names are ignored.
+    fn store_param(&mut self, name: &str, value: Value) -> serde_json::Result<()>;
+
+    /// Parse and store `value` as a "set" request would and return the
+    /// stored value.
+    fn set_param(&mut self, name: &str, value: &str) -> Result<Value> {
+        let meta = Self::params_meta()
+            .remove(name)
+            .ok_or_else(|| anyhow!("unknown param {:?}", name).context(StatsErrno(libc::ENOENT)))?;
+        let value = meta.parse(value)?;
+        self.store_param(name, value.clone())?;
+        Ok(value)
+    }
+
+    /// Current values of all parameters.
+    fn params(&self) -> BTreeMap<String, Value> {
+        Self::params_meta()
+            .into_keys()
+            .filter_map(|name| self.get_param(&name).map(|v| (name, v)))
+            .collect()
+    }
+}
+
+struct StatsParamAttrs {
+    desc: Option<String>,
+    min: Option<StatsParamBound>,
+    max: Option<StatsParamBound>,
+}
+
+impl Parse for StatsParamAttrs {
+    fn parse(input: &ParseBuffer) -> syn::Result<Self> {
+        let mut attrs = Self {
+            desc: None,
+            min: None,
+            max: None,
+        };
+
+        while !input.is_empty() {
+            let ident = input.parse::<Ident>()?;
+            input.parse::<Token!(=)>()?;
+            match ident.to_string().as_str() {
+                "desc" => attrs.desc = Some(input.parse::<LitStr>()?.value()),
+                "min" => attrs.min = Some(parse_number(input)?),
+                "max" => attrs.max = Some(parse_number(input)?),
+                _ => Err(Error::new(
+                    ident.span(),
+                    "scx_stats: Unknown param attribute",
+                ))?,
+            }
+            if !input.is_empty() {
+                input.parse::<Token!(,)>()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+fn parse_number(input: &ParseBuffer) -> syn::Result<StatsParamBound> {
+    let neg = input.parse::<Option<Token!(-)>>()?.is_some();
+    let lit = input.parse::<Lit>()?;
+    Ok(match &lit {
+        Lit::Int(v) if neg => format!("-{}", v.base10_digits())
+            .parse::<i64>()
+            .map_err(|e| Error::new(lit.span(), e))?
+            .into(),
+        Lit::Int(v) => v.base10_parse::<u64>()?.into(),
+        Lit::Float(v) => {
+            let v = v.base10_parse::<f64>()?;
+            (if neg { -v } else { v }).into()
+        }
+        _ => Err(Error::new(lit.span(), "scx_stats: Expected a number"))?,
+    })
+}
+
+/// Parsed struct for the `Params` derive macro.
+#[derive(Clone, Debug)]
+pub struct StatsParamsAux {
+    pub params: BTreeMap<String, StatsParamMeta>,
+    pub ident: Ident,
+}
+
+impl StatsParamsAux {
+    /// Range of the types narrower than the param kinds, values beyond it
+    /// can't be stored.
+    fn type_range(ty: &Type) -> Option<(StatsParamBound, StatsParamBound)> {
+        let ident = match ty {
+            Type::Path(path) => path.path.get_ident()?.to_string(),
+            _ => return None,
+        };
+        Some(match ident.as_str() {
+            "i8" => ((i8::MIN as i64).into(), (i8::MAX as i64).into()),
+            "i16" => ((i16::MIN as i64).into(), (i16::MAX as i64).into()),
+            "i32" => ((i32::MIN as i64).into(), (i32::MAX as i64).into()),
+            "u8" => (0u64.into(), (u8::MAX as u64).into()),
+            "u16" => (0u64.into(), (u16::MAX as u64).into()),
+            "u32" => (0u64.into(), (u32::MAX as u64).into()),
+            "f32" => ((f32::MIN as f64).into(), (f32::MAX as f64).into()),
+            _ => return None,
+        })
+    }
+
+    fn field_meta(ty: &Type, attrs: &[Attribute]) -> syn::Result<StatsParamMeta> {
+        let range = Self::type_range(ty);
+        let mut meta = StatsParamMeta::new(StatsParamKind::new(ty)?)
+            .set_range(range.map(|r| r.0), range.map(|r| r.1));
+
+        for attr in attrs {
+            if attr.path().is_ident("param") {
+                let pattrs = attr.parse_args::<StatsParamAttrs>()?;
+                meta.desc = pattrs.desc.or(meta.desc);
+                meta.min = pattrs.min.or(meta.min);
+                meta.max = pattrs.max.or(meta.max);
+            }
+        }
+
+        if (meta.min.is_some() || meta.max.is_some())
+            && matches!(meta.kind, StatsParamKind::Bool | StatsParamKind::String)
+        {
+            Err(Error::new(
+                ty.span(),
+                "scx_stats: Range on a non-numeric param",
+            ))?;
+        }
+        let is_float =
+            |bound: &Option<StatsParamBound>| matches!(bound, Some(StatsParamBound::Float(_)));
+        if (is_float(&meta.min) || is_float(&meta.max))
+            && matches!(meta.kind, StatsParamKind::I64 | StatsParamKind::U64)
+        {
+            Err(Error::new(
+                ty.span(),
+                "scx_stats: Float range on an integer param",
+            ))?;
+        }
+        if let (Some((min, max)), Some(pmin), Some(pmax)) = (range, meta.min, meta.max) {
+            if pmin < min || pmax > max {
+                Err(Error::new(
+                    ty.span(),
+                    "scx_stats: Range exceeds the param type",
+                ))?;
+            }
+        }
+        Ok(meta)
+    }
+}
+
+impl Parse for StatsParamsAux {
+    fn parse(input: &ParseBuffer) -> syn::Result<Self> {
+        let item_struct: ItemStruct = input.parse()?;
+        let mut params = BTreeMap::new();
+
+        if let Fields::Named(named_fields) = &item_struct.fields {
+            for field in named_fields.named.iter() {
+                params.insert(
+                    field.ident.as_ref().unwrap().to_string(),
+                    Self::field_meta(&field.ty, &field.attrs)?,
+                );
+            }
+        }
+
+        Ok(Self {
+            params,
+            ident: item_struct.ident,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errno(e: anyhow::Error) -> i32 {
+        e.downcast_ref::<StatsErrno>().unwrap().0
+    }
+
+    #[test]
+    fn test_parse() {
+        let slice = StatsParamMeta::new(StatsParamKind::U64).set_range(Some(100u64), Some(100_000));
+        assert_eq!(slice.parse("5000").unwrap(), Value::from(5000u64));
+        assert_eq!(errno(slice.parse("50").unwrap_err()), libc::ERANGE);
+        assert_eq!(errno(slice.parse("-1").unwrap_err()), libc::EINVAL);
+        assert_eq!(errno(slice.parse("1.5").unwrap_err()), libc::EINVAL);
+
+        let boost = StatsParamMeta::new(StatsParamKind::Float).set_range(Some(-1.0), None);
+        assert_eq!(boost.parse("-0.5").unwrap(), Value::from(-0.5));
+        assert_eq!(errno(boost.parse("nan").unwrap_err()), libc::EINVAL);
+
+        // integers are compared exactly, even beyond the precision of f64
+        let big = StatsParamMeta::new(StatsParamKind::U64).set_range(None, Some(u64::MAX - 1));
+        assert_eq!(
+            errno(big.parse(&u64::MAX.to_string()).unwrap_err()),
+            libc::ERANGE
+        );
+        let big = StatsParamMeta::new(StatsParamKind::I64).set_range(Some(i64::MIN + 1), None);
+        assert_eq!(
+            errno(big.parse(&i64::MIN.to_string()).unwrap_err()),
+            libc::ERANGE
+        );
+        let signed = StatsParamMeta {
+            min: Some(StatsParamBound::I64(-5)),
+            max: Some(StatsParamBound::U64(u64::MAX)),
+            ..StatsParamMeta::new(StatsParamKind::I64)
+        };
+        assert_eq!(
+            signed.parse(&i64::MAX.to_string()).unwrap(),
+            Value::from(i64::MAX)
+        );
+        assert_eq!(errno(signed.parse("-6").unwrap_err()), libc::ERANGE);
+
+        let flag = StatsParamMeta::new(StatsParamKind::Bool);
+        assert_eq!(flag.parse("true").unwrap(), Value::from(true));
+        assert_eq!(errno(flag.parse("1").unwrap_err()), libc::EINVAL);
+    }
+
+    #[test]
+    fn test_derive_parse() {
+        let aux: StatsParamsAux = syn::parse_str(
+            r#"
+            struct Tunables {
+                #[param(desc = "slice in usecs", min = 100, max = 100_000)]
+                slice_us: u64,
+                #[param(min = -1.5)]
+                boost: f64,
+                local_kthreads: bool,
+                #[param(max = 64)]
+                nr_layers: u8,
+                nice: i16,
+                ratio: f32,
+            }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(aux.ident, "Tunables");
+        assert_eq!(
+            aux.params["slice_us"],
+            StatsParamMeta::new(StatsParamKind::U64)
+                .set_range(Some(100u64), Some(100_000))
+                .set_desc("slice in usecs")
+        );
+        assert_eq!(aux.params["boost"].min, Some(StatsParamBound::Float(-1.5)));
+        assert_eq!(aux.params["local_kthreads"].kind, StatsParamKind::Bool);
+        assert_eq!(aux.params["local_kthreads"].min, None);
+
+        // narrower types are limited to their range unless a range is given
+        let nr_layers = &aux.params["nr_layers"];
+        assert_eq!(
+            (nr_layers.min, nr_layers.max),
+            (Some(0u64.into()), Some(64u64.into()))
+        );
+        let nice = StatsParamMeta::new(StatsParamKind::I64).set_range(Some(-32768i64), Some(32767));
+        assert_eq!(aux.params["nice"], nice);
+        // the bounds are equal after a round-trip through JSON as well
+        let json = serde_json::to_string(&nice).unwrap();
+        assert_eq!(serde_json::from_str::<StatsParamMeta>(&json).unwrap(), nice);
+        assert_eq!(
+            errno(aux.params["nice"].parse("40000").unwrap_err()),
+            libc::ERANGE
+        );
+        assert_eq!(aux.params["ratio"].max, Some((f32::MAX as f64).into()));
+        assert!(
+            syn::parse_str::<StatsParamsAux>("struct A { #[param(max = 256)] a: u8 }").is_err()
+        );
+
+        assert!(
+            syn::parse_str::<StatsParamsAux>("struct A { #[param(min = 1)] a: bool }").is_err()
+        );
+        assert!(
+            syn::parse_str::<StatsParamsAux>("struct A { #[param(min = 0.5)] a: u64 }").is_err()
+        );
+        assert!(syn::parse_str::<StatsParamsAux>("struct A { a: Vec<u64> }").is_err());
+    }
+}
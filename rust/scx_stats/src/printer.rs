@@ -0,0 +1,376 @@
+//! Generic output of statistics based on the `stats_meta` of the server,
+//! which doesn't require the definitions of the statistics structs.
+use crate::{StatsData, StatsKind, StatsMeta};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// One row per field with its value and optionally its description.
+    #[default]
+    Table,
+    /// One JSON object per sample.
+    JsonLines,
+    /// One row per sample with the fields as columns.
+    Csv,
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" | "jsonl" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("unknown format {:?}, expected table, json or csv", s),
+        }
+    }
+}
+
+/// A single value of the flattened statistics, e.g. "doms_dict[0].events".
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsRow<'a> {
+    pub name: String,
+    pub value: &'a Value,
+    pub desc: Option<&'a str>,
+}
+
+/// Prints samples of the top-level statistics in a `StatsFormat`.
+pub struct StatsPrinter {
+    metas: BTreeMap<String, StatsMeta>,
+    top: String,
+    format: StatsFormat,
+    desc: bool,
+    csv_header: Vec<String>,
+}
+
+impl StatsPrinter {
+    pub fn new(metas: BTreeMap<String, StatsMeta>, format: StatsFormat) -> Result<Self> {
+        let top = metas
+            .values()
+            .find(|meta| meta.attrs.top.is_some())
+            .map(|meta| meta.name.clone())
+            .ok_or_else(|| anyhow!("top-level stats metadata missing"))?;
+
+        Ok(Self {
+            metas,
+            top,
+            format,
+            desc: false,
+            csv_header: vec![],
+        })
+    }
+
+    /// Add the field descriptions to the table format.
+    pub fn set_descriptions(mut self, desc: bool) -> Self {
+        self.desc = desc;
+        self
+    }
+
+    pub fn metas(&self) -> &BTreeMap<String, StatsMeta> {
+        &self.metas
+    }
+
+    /// Flatten `stats` into one row per value. Nested structs are joined
+    /// with ".", array indices and dict keys are put into brackets.
+    pub fn flatten<'a>(&'a self, stats: &'a Value) -> Vec<StatsRow<'a>> {
+        let mut rows = vec![];
+        self.flatten_struct(&self.metas[&self.top], stats, "", &mut rows);
+        rows
+    }
+
+    fn flatten_struct<'a>(
+        &'a self,
+        meta: &'a StatsMeta,
+        value: &'a Value,
+        prefix: &str,
+        rows: &mut Vec<StatsRow<'a>>,
+    ) {
+        for (fname, field) in meta.fields.iter() {
+            let name = match prefix.is_empty() {
+                true => fname.clone(),
+                false => format!("{}.{}", prefix, fname),
+            };
+            let desc = field.attrs.desc.as_deref();
+            let value = value.get(fname).unwrap_or(&Value::Null);
+
+            match &field.data {
+                StatsData::Datum(kind) => self.flatten_datum(kind, value, name, desc, rows),
+                StatsData::Array(kind) => {
+                    for (idx, elem) in value.as_array().into_iter().flatten().enumerate() {
+                        self.flatten_datum(kind, elem, format!("{}[{}]", name, idx), desc, rows);
+                    }
+                }
+                StatsData::Dict { key: _, datum } => {
+                    for (key, elem) in value.as_object().into_iter().flatten() {
+                        self.flatten_datum(datum, elem, format!("{}[{}]", name, key), desc, rows);
+                    }
+                }
+            }
+        }
+    }
+
+    fn flatten_datum<'a>(
+        &'a self,
+        kind: &StatsKind,
+        value: &'a Value,
+        name: String,
+        desc: Option<&'a str>,
+        rows: &mut Vec<StatsRow<'a>>,
+    ) {
+        match kind {
+            StatsKind::Struct(sname) if self.metas.contains_key(sname) => {
+                self.flatten_struct(&self.metas[sname], value, &name, rows)
+            }
+            _ => rows.push(StatsRow { name, value, desc }),
+        }
+    }
+
+    /// Print a sample of the top-level statistics.
+    pub fn print<W: Write>(&mut self, w: &mut W, stats: &Value) -> Result<()> {
+        match self.format {
+            StatsFormat::Table => self.print_table(w, stats),
+            StatsFormat::JsonLines => Ok(writeln!(w, "{}", serde_json::to_string(stats)?)?),
+            StatsFormat::Csv => self.print_csv(w, stats),
+        }
+    }
+
+    fn print_table<W: Write>(&self, w: &mut W, stats: &Value) -> Result<()> {
+        let rows = self.flatten(stats);
+        let values: Vec<String> = rows.iter().map(|row| format_value(row.value)).collect();
+        let nwidth = rows.iter().map(|row| row.name.len()).max().unwrap_or(0);
+        let vwidth = values.iter().map(|v| v.len()).max().unwrap_or(0);
+
+        for (row, value) in rows.iter().zip(values.iter()) {
+            match (self.desc, row.desc) {
+                (true, Some(desc)) => writeln!(
+                    w,
+                    "{:nw$} {:>vw$}  {}",
+                    row.name,
+                    value,
+                    desc,
+                    nw = nwidth,
+                    vw = vwidth
+                )?,
+                _ => writeln!(
+                    w,
+                    "{:nw$} {:>vw$}",
+                    row.name,
+                    value,
+                    nw = nwidth,
+                    vw = vwidth
+                )?,
+            }
+        }
+        writeln!(w)?;
+        Ok(())
+    }
+
+    fn print_csv<W: Write>(&mut self, w: &mut W, stats: &Value) -> Result<()> {
+        let (header, values): (Vec<String>, Vec<String>) = self
+            .flatten(stats)
+            .into_iter()
+            .map(|row| (row.name, csv_escape(&format_value(row.value))))
+            .unzip();
+
+        // Dict keys can come and go, repeat the header when they do.
+        if header != self.csv_header {
+            let names: Vec<String> = header.iter().map(|name| csv_escape(name)).collect();
+            writeln!(w, "{}", names.join(","))?;
+            self.csv_header = header;
+        }
+
+        writeln!(w, "{}", values.join(","))?;
+        Ok(())
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "".into(),
+        v => v.to_string(),
+    }
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordHeader {
+    stats_meta: BTreeMap<String, StatsMeta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordSample {
+    ts: f64,
+    stats: Value,
+}
+
+/// Records samples into a JSON lines file which can be replayed with
+/// `StatsReplay`. The first line holds the metadata, each following line
+/// a sample with its timestamp in seconds since the epoch.
+pub struct StatsRecorder<W: Write> {
+    out: W,
+}
+
+impl<W: Write> StatsRecorder<W> {
+    pub fn new(mut out: W, metas: &BTreeMap<String, StatsMeta>) -> Result<Self> {
+        let header = RecordHeader {
+            stats_meta: metas.clone(),
+        };
+        writeln!(out, "{}", serde_json::to_string(&header)?)?;
+        Ok(Self { out })
+    }
+
+    pub fn record(&mut self, stats: &Value) -> Result<()> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.record_at(ts, stats)
+    }
+
+    pub fn record_at(&mut self, ts: f64, stats: &Value) -> Result<()> {
+        let sample = RecordSample {
+            ts,
+            stats: stats.clone(),
+        };
+        writeln!(self.out, "{}", serde_json::to_string(&sample)?)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads back a recording made by `StatsRecorder`. Iterating yields the
+/// timestamps and samples.
+pub struct StatsReplay<R: BufRead> {
+    metas: BTreeMap<String, StatsMeta>,
+    reader: R,
+    line_nr: usize,
+}
+
+impl<R: BufRead> StatsReplay<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let header: RecordHeader =
+            serde_json::from_str(&line).context("invalid stats recording header")?;
+
+        Ok(Self {
+            metas: header.stats_meta,
+            reader,
+            line_nr: 1,
+        })
+    }
+
+    pub fn metas(&self) -> &BTreeMap<String, StatsMeta> {
+        &self.metas
+    }
+}
+
+impl<R: BufRead> Iterator for StatsReplay<R> {
+    type Item = Result<(f64, Value)>;
+
+    fn next(&mut self) -> Option<Result<(f64, Value)>> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+        self.line_nr += 1;
+
+        Some(
+            serde_json::from_str::<RecordSample>(&line)
+                .map(|sample| (sample.ts, sample.stats))
+                .with_context(|| format!("invalid stats sample on line {}", self.line_nr)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metas() -> BTreeMap<String, StatsMeta> {
+        serde_json::from_value(json!({
+            "ClusterStats": {
+                "name": "ClusterStats",
+                "top": "true",
+                "fields": {
+                    "name": {"datum": "string"},
+                    "at": {"datum": "u64", "desc": "timestamp"},
+                    "doms": {"dict": {"key": "u64", "datum": {"struct": "DomainStats"}}},
+                },
+            },
+            "DomainStats": {
+                "name": "DomainStats",
+                "fields": {
+                    "load": {"datum": "float", "desc": "load"},
+                    "util": {"array": "float"},
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_print() {
+        let stats = json!({
+            "name": "a, b",
+            "at": 42,
+            "doms": {"0": {"load": 1.5, "util": [0.25, 0.5]}},
+        });
+
+        let mut printer = StatsPrinter::new(metas(), StatsFormat::Table)
+            .unwrap()
+            .set_descriptions(true);
+        let mut out = vec![];
+        printer.print(&mut out, &stats).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "at                42  timestamp\n\
+             doms[0].load     1.5  load\n\
+             doms[0].util[0] 0.25\n\
+             doms[0].util[1]  0.5\n\
+             name            a, b\n\n"
+        );
+
+        let mut printer = StatsPrinter::new(metas(), StatsFormat::Csv).unwrap();
+        let mut out = vec![];
+        printer.print(&mut out, &stats).unwrap();
+        printer.print(&mut out, &stats).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "at,doms[0].load,doms[0].util[0],doms[0].util[1],name\n\
+             42,1.5,0.25,0.5,\"a, b\"\n\
+             42,1.5,0.25,0.5,\"a, b\"\n"
+        );
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let mut out = vec![];
+        let mut recorder = StatsRecorder::new(&mut out, &metas()).unwrap();
+        recorder.record_at(1.5, &json!({"at": 1})).unwrap();
+        recorder.record_at(2.5, &json!({"at": 2})).unwrap();
+
+        let replay = StatsReplay::new(out.as_slice()).unwrap();
+        assert_eq!(replay.metas().len(), 2);
+        let samples: Vec<(f64, Value)> = replay.map(|s| s.unwrap()).collect();
+        assert_eq!(
+            samples,
+            vec![(1.5, json!({"at": 1})), (2.5, json!({"at": 2}))]
+        );
+    }
+}
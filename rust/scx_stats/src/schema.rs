@@ -0,0 +1,332 @@
+use crate::{Meta, StatsData, StatsKind, StatsMeta};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Combine the hash of a struct's own layout with the hashes of the structs
+/// nested in its fields, in field order. Used by the `Stats` derive macro.
+pub fn schema_hash_combine(own: u64, nested: &[u64]) -> u64 {
+    nested.iter().fold(own, |h, n| fnv1a(h, &n.to_le_bytes()))
+}
+
+impl StatsMeta {
+    /// Hash of the struct's own layout, i.e. its name and the names and
+    /// types of its fields. Descriptions and user attributes don't affect
+    /// the hash. The hash is stable across builds and Rust versions.
+    pub fn schema_hash(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, self.name.as_bytes());
+        for (fname, field) in self.fields.iter() {
+            hash = fnv1a(hash, b"\0");
+            hash = fnv1a(hash, fname.as_bytes());
+            hash = fnv1a(hash, b":");
+            hash = fnv1a(hash, field.data.to_string().as_bytes());
+        }
+        hash
+    }
+
+    fn nested_structs(&self) -> impl Iterator<Item = &str> {
+        self.fields.values().filter_map(|field| match &field.data {
+            StatsData::Datum(StatsKind::Struct(name))
+            | StatsData::Array(StatsKind::Struct(name))
+            | StatsData::Dict {
+                key: _,
+                datum: StatsKind::Struct(name),
+            } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+fn full_schema_hash(
+    metas: &BTreeMap<String, StatsMeta>,
+    name: &str,
+    nesting: &mut BTreeSet<String>,
+) -> Result<u64> {
+    let meta = metas
+        .get(name)
+        .ok_or_else(|| anyhow!("unknown stats meta name {}", name))?;
+    if !nesting.insert(name.into()) {
+        bail!("loop in stats meta detected, {} already nested", name);
+    }
+
+    let mut nested = vec![];
+    for inner in meta.nested_structs() {
+        nested.push(full_schema_hash(metas, inner, nesting)?);
+    }
+
+    nesting.remove(name);
+    Ok(schema_hash_combine(meta.schema_hash(), &nested))
+}
+
+/// Response of the "stats_schema" request. The hashes are formatted as 16
+/// hex digits to avoid precision loss in JSON consumers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatsSchema {
+    /// Changes whenever the layout of any of the structs changes.
+    pub hash: String,
+    /// Per-struct hashes which cover the struct and all structs nested in
+    /// it. These match `Meta::schema_hash()` of the derived structs.
+    pub structs: BTreeMap<String, String>,
+}
+
+impl StatsSchema {
+    pub fn new(metas: &BTreeMap<String, StatsMeta>) -> Result<Self> {
+        let mut structs = BTreeMap::new();
+        let mut hash = FNV_OFFSET_BASIS;
+        for name in metas.keys() {
+            let struct_hash = full_schema_hash(metas, name, &mut BTreeSet::new())?;
+            hash = fnv1a(hash, name.as_bytes());
+            hash = fnv1a(hash, &struct_hash.to_le_bytes());
+            structs.insert(name.clone(), format!("{:016x}", struct_hash));
+        }
+
+        Ok(Self {
+            hash: format!("{:016x}", hash),
+            structs,
+        })
+    }
+
+    /// Whether the server's layout of `T` matches the one compiled in.
+    pub fn matches<T: Meta>(&self) -> bool {
+        self.structs.get(&T::meta().name) == Some(&format!("{:016x}", T::schema_hash()))
+    }
+}
+
+/// A difference between two sets of stats metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatsSchemaChange {
+    /// A struct or field was added, as "Struct" or "Struct.field".
+    Added(String),
+    /// A struct or field was removed.
+    Removed(String),
+    /// The type of a field changed.
+    Changed {
+        field: String,
+        old: StatsData,
+        new: StatsData,
+    },
+    /// Another struct became the top-level one.
+    TopChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl StatsSchemaChange {
+    /// Additions are compatible with existing consumers, anything else
+    /// breaks them.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self, Self::Added(_))
+    }
+}
+
+impl std::fmt::Display for StatsSchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Added(name) => write!(f, "added {}", name),
+            Self::Removed(name) => write!(f, "removed {}", name),
+            Self::Changed { field, old, new } => write!(f, "changed {}: {} -> {}", field, old, new),
+            Self::TopChanged { old, new } => write!(f, "changed top: {:?} -> {:?}", old, new),
+        }
+    }
+}
+
+fn top_name(metas: &BTreeMap<String, StatsMeta>) -> Option<String> {
+    metas
+        .values()
+        .find(|meta| meta.attrs.top.is_some())
+        .map(|meta| meta.name.clone())
+}
+
+/// Compare two sets of stats metadata, e.g. as returned by "stats_meta"
+/// from two versions of a scheduler.
+pub fn diff_stats_meta(
+    old: &BTreeMap<String, StatsMeta>,
+    new: &BTreeMap<String, StatsMeta>,
+) -> Vec<StatsSchemaChange> {
+    let mut changes = vec![];
+
+    let (old_top, new_top) = (top_name(old), top_name(new));
+    if old_top != new_top {
+        changes.push(StatsSchemaChange::TopChanged {
+            old: old_top,
+            new: new_top,
+        });
+    }
+
+    for (name, old_meta) in old.iter() {
+        let new_meta = match new.get(name) {
+            Some(v) => v,
+            None => {
+                changes.push(StatsSchemaChange::Removed(name.clone()));
+                continue;
+            }
+        };
+
+        for (fname, old_field) in old_meta.fields.iter() {
+            match new_meta.fields.get(fname) {
+                Some(new_field) if new_field.data != old_field.data => {
+                    changes.push(StatsSchemaChange::Changed {
+                        field: format!("{}.{}", name, fname),
+                        old: old_field.data.clone(),
+                        new: new_field.data.clone(),
+                    })
+                }
+                Some(_) => {}
+                None => changes.push(StatsSchemaChange::Removed(format!("{}.{}", name, fname))),
+            }
+        }
+        for fname in new_meta.fields.keys() {
+            if !old_meta.fields.contains_key(fname) {
+                changes.push(StatsSchemaChange::Added(format!("{}.{}", name, fname)));
+            }
+        }
+    }
+
+    for name in new.keys() {
+        if !old.contains_key(name) {
+            changes.push(StatsSchemaChange::Added(name.clone()));
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scx_stats_derive::Stats;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Stats)]
+    #[stat(desc = "domain statistics")]
+    struct DomainStats {
+        #[stat(desc = "a gauge number")]
+        load: f64,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Stats)]
+    #[stat(top)]
+    struct ClusterStats {
+        #[stat(desc = "timestamp")]
+        at: u64,
+        doms: BTreeMap<u64, DomainStats>,
+    }
+
+    fn metas(at: &str, extra: bool) -> BTreeMap<String, StatsMeta> {
+        let mut metas: BTreeMap<String, StatsMeta> = serde_json::from_value(json!({
+            "ClusterStats": {
+                "name": "ClusterStats",
+                "top": "true",
+                "fields": {
+                    "at": {"datum": at, "desc": "timestamp"},
+                    "doms": {"dict": {"key": "u64", "datum": {"struct": "DomainStats"}}},
+                },
+            },
+            "DomainStats": {
+                "name": "DomainStats",
+                "fields": {
+                    "load": {"datum": "float"},
+                },
+            },
+        }))
+        .unwrap();
+        if extra {
+            let dom = metas.get_mut("DomainStats").unwrap();
+            let mut field = dom.fields["load"].clone();
+            field.attrs.desc = Some("utilization".into());
+            dom.fields.insert("util".into(), field);
+        }
+        metas
+    }
+
+    #[test]
+    fn test_schema_hash() {
+        let schema = StatsSchema::new(&metas("u64", false)).unwrap();
+        assert_eq!(schema, StatsSchema::new(&metas("u64", false)).unwrap());
+
+        // descriptions don't matter
+        let mut described = metas("u64", false);
+        described.get_mut("DomainStats").unwrap().attrs.desc = Some("domain".into());
+        assert_eq!(schema, StatsSchema::new(&described).unwrap());
+
+        // nested changes propagate to the containing struct
+        let extended = StatsSchema::new(&metas("u64", true)).unwrap();
+        assert_ne!(schema.hash, extended.hash);
+        assert_ne!(
+            schema.structs["ClusterStats"],
+            extended.structs["ClusterStats"]
+        );
+        assert_ne!(
+            schema.structs["DomainStats"],
+            extended.structs["DomainStats"]
+        );
+    }
+
+    #[test]
+    fn test_derive_schema_hash() {
+        // the hash computed at compile time matches the one of the metadata
+        let metas = BTreeMap::from([
+            ("ClusterStats".to_string(), ClusterStats::meta()),
+            ("DomainStats".to_string(), DomainStats::meta()),
+        ]);
+        let schema = StatsSchema::new(&metas).unwrap();
+        assert_eq!(
+            format!("{:016x}", ClusterStats::schema_hash()),
+            schema.structs["ClusterStats"]
+        );
+        assert_eq!(
+            format!("{:016x}", DomainStats::schema_hash()),
+            schema.structs["DomainStats"]
+        );
+        assert!(schema.matches::<ClusterStats>());
+
+        // and the one of the same layout described by hand
+        let (derived, described) = (metas, self::metas("u64", false));
+        assert_eq!(schema, StatsSchema::new(&described).unwrap());
+        assert!(diff_stats_meta(&derived, &described).is_empty());
+    }
+
+    #[test]
+    fn test_diff_stats_meta() {
+        let (old, new) = (metas("u64", false), metas("float", true));
+        assert!(diff_stats_meta(&old, &old).is_empty());
+
+        let changes = diff_stats_meta(&old, &new);
+        assert_eq!(
+            changes,
+            vec![
+                StatsSchemaChange::Changed {
+                    field: "ClusterStats.at".into(),
+                    old: StatsData::Datum(StatsKind::U64),
+                    new: StatsData::Datum(StatsKind::Float),
+                },
+                StatsSchemaChange::Added("DomainStats.util".into()),
+            ]
+        );
+        assert!(changes[0].is_breaking());
+        assert!(!changes[1].is_breaking());
+        assert_eq!(
+            changes[0].to_string(),
+            "changed ClusterStats.at: u64 -> float"
+        );
+
+        let changes = diff_stats_meta(&new, &old);
+        assert_eq!(
+            changes[1],
+            StatsSchemaChange::Removed("DomainStats.util".into())
+        );
+        assert!(changes[1].is_breaking());
+    }
+}
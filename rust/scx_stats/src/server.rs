@@ -1,5 +1,9 @@
+use crate::transport::{StatsListener, StatsStream};
 use crate::StatsClient;
-use crate::{Meta, StatsData, StatsKind, StatsMeta};
+use crate::StatsEncoding;
+use crate::StatsSchema;
+use crate::{Meta, StatsData, StatsKind, StatsMeta, StatsParamMeta};
+use crate::{StatsAccess, StatsListenAddr, StatsPeer};
 use anyhow::{anyhow, bail, Context, Result};
 use crossbeam::channel::{unbounded, Receiver, RecvError, Select, Sender};
 use log::{debug, error, warn};
@@ -7,11 +11,12 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{BTreeMap, BTreeSet};
 use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread::spawn;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
 
 pub trait StatsReader<Req, Res>:
     FnMut(&BTreeMap<String, String>, (&Sender<Req>, &Receiver<Res>)) -> Result<Value>
@@ -71,6 +76,36 @@ pub struct StatsOps<Req, Res> {
     pub close: Option<Box<dyn StatsCloser<Req, Res>>>,
 }
 
+pub trait StatsParamGetter<Req, Res>:
+    FnMut(&str, (&Sender<Req>, &Receiver<Res>)) -> Result<Value> + Send
+{
+}
+impl<Req, Res, T: FnMut(&str, (&Sender<Req>, &Receiver<Res>)) -> Result<Value> + Send>
+    StatsParamGetter<Req, Res> for T
+{
+}
+
+pub trait StatsParamSetter<Req, Res>:
+    FnMut(&str, Value, (&Sender<Req>, &Receiver<Res>)) -> Result<Value> + Send
+{
+}
+impl<Req, Res, T: FnMut(&str, Value, (&Sender<Req>, &Receiver<Res>)) -> Result<Value> + Send>
+    StatsParamSetter<Req, Res> for T
+{
+}
+
+/// Handlers of the "get_param" and "set" requests. Both are called with
+/// the parameter name and can use the channels to reach the scheduler,
+/// e.g. to update a BPF global variable. `set` is called with a value
+/// which has been checked against the `StatsParamMeta` and returns the
+/// value in effect afterwards.
+pub struct StatsParamOps<Req, Res> {
+    pub get: Box<dyn StatsParamGetter<Req, Res>>,
+    pub set: Box<dyn StatsParamSetter<Req, Res>>,
+}
+
+type StatsParamEntry<Req, Res> = (StatsParamMeta, Arc<Mutex<StatsParamOps<Req, Res>>>);
+
 struct StatsOpenOps<Req, Res> {
     map: BTreeMap<
         String,
@@ -122,6 +157,24 @@ pub struct StatsResponse {
     pub args: BTreeMap<String, Value>,
 }
 
+struct SubscribeArgs {
+    intv: Duration,
+    fields: Option<BTreeSet<String>>,
+}
+
+struct ResponseWriter {
+    stream: StatsStream,
+    encoding: StatsEncoding,
+}
+
+impl ResponseWriter {
+    fn write(&mut self, resp: &StatsResponse) -> Result<()> {
+        let buf = self.encoding.encode(resp)?;
+        self.stream.write_all(&buf)?;
+        Ok(())
+    }
+}
+
 pub struct StatsErrno(pub i32);
 
 impl std::fmt::Display for StatsErrno {
@@ -174,6 +227,9 @@ where
     top: Option<String>,
     meta: BTreeMap<String, StatsMeta>,
     ops: BTreeMap<String, Arc<Mutex<StatsOps<Req, Res>>>>,
+    params: BTreeMap<String, StatsParamEntry<Req, Res>>,
+    access: BTreeMap<String, StatsAccess>,
+    default_access: StatsAccess,
 }
 
 impl<Req, Res> StatsServerData<Req, Res>
@@ -186,6 +242,9 @@ where
             top: None,
             meta: BTreeMap::new(),
             ops: BTreeMap::new(),
+            params: BTreeMap::new(),
+            access: BTreeMap::new(),
+            default_access: StatsAccess::Any,
         }
     }
 
@@ -218,6 +277,55 @@ where
         self.add_ops(name, ops)
     }
 
+    /// Add tunable parameters, usually `Params::params_meta()` of a struct
+    /// with the `Params` derive macro, which are read and written with
+    /// `ops`.
+    pub fn add_params(
+        mut self,
+        params: BTreeMap<String, StatsParamMeta>,
+        ops: StatsParamOps<Req, Res>,
+    ) -> Self {
+        let ops = Arc::new(Mutex::new(ops));
+        for (name, meta) in params.into_iter() {
+            self.params.insert(name, (meta, ops.clone()));
+        }
+        self
+    }
+
+    /// Restrict who may issue requests for `name`, which is the target for
+    /// "stats" and "subscribe" requests and the request itself otherwise,
+    /// e.g. "stats_meta".
+    pub fn set_access(mut self, name: &str, access: StatsAccess) -> Self {
+        self.access.insert(name.to_string(), access);
+        self
+    }
+
+    /// Access for the names without `set_access()`, `StatsAccess::Any` by
+    /// default. "set" requests are limited to root unless configured with
+    /// `set_access()`.
+    pub fn set_default_access(mut self, access: StatsAccess) -> Self {
+        self.default_access = access;
+        self
+    }
+
+    fn check_access(&self, req: &StatsRequest, peer: Option<&StatsPeer>) -> Result<()> {
+        let name = match req.req.as_str() {
+            "stats" | "subscribe" => req.args.get("target").map_or("top", |v| v.as_str()),
+            v => v,
+        };
+        let access = match (self.access.get(name), name) {
+            (Some(v), _) => v,
+            (None, "set") => &StatsAccess::Root,
+            (None, _) => &self.default_access,
+        };
+
+        if !access.allows(peer) {
+            Err(anyhow!("{:?} not permitted for {:?}", name, peer)
+                .context(StatsErrno(libc::EACCES)))?;
+        }
+        Ok(())
+    }
+
     fn visit_meta_inner(
         &self,
         name: &str,
@@ -347,7 +455,7 @@ where
     Req: Send + 'static,
     Res: Send + 'static,
 {
-    listener: UnixListener,
+    listeners: Vec<StatsListener>,
     data: Arc<Mutex<StatsServerData<Req, Res>>>,
     inner_ch: ChannelPair<Req, Res>,
     exit: Arc<AtomicBool>,
@@ -359,13 +467,13 @@ where
     Res: Send + 'static,
 {
     fn new(
-        listener: UnixListener,
+        listeners: Vec<StatsListener>,
         data: Arc<Mutex<StatsServerData<Req, Res>>>,
         inner_ch: ChannelPair<Req, Res>,
         exit: Arc<AtomicBool>,
     ) -> Self {
         Self {
-            listener,
+            listeners,
             data,
             inner_ch,
             exit,
@@ -384,53 +492,229 @@ where
         })
     }
 
+    fn build_err_resp(e: anyhow::Error) -> Result<StatsResponse> {
+        let errno = match e.downcast_ref::<StatsErrno>() {
+            Some(e) if e.0 != 0 => e.0,
+            _ => libc::EINVAL,
+        };
+        Self::build_resp(errno, &format!("{:?}", &e))
+    }
+
+    fn read_stats(
+        req: &StatsRequest,
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        ch: &ChannelPair<Req, Res>,
+        open_ops: &mut StatsOpenOps<Req, Res>,
+    ) -> Result<Value> {
+        let target = match req.args.get("target") {
+            Some(v) => v,
+            None => "top",
+        };
+
+        let ops = match data.lock().unwrap().ops.get(target) {
+            Some(v) => v.clone(),
+            None => {
+                Err(anyhow!("unknown stat target {:?}", req).context(StatsErrno(libc::EINVAL)))?
+            }
+        };
+
+        if !open_ops.map.contains_key(target) {
+            let read = (ops.lock().unwrap().open)((&ch.req, &ch.res))?;
+            open_ops
+                .map
+                .insert(target.into(), (ops.clone(), read, ch.clone()));
+        }
+
+        let read = &mut open_ops.map.get_mut(target).unwrap().1;
+
+        read(&req.args, (&ch.req, &ch.res))
+    }
+
+    fn param_ops(
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        name: &str,
+    ) -> Result<StatsParamEntry<Req, Res>> {
+        match data.lock().unwrap().params.get(name) {
+            Some((meta, ops)) => Ok((meta.clone(), ops.clone())),
+            None => Err(anyhow!("unknown param {:?}", name).context(StatsErrno(libc::ENOENT)))?,
+        }
+    }
+
+    /// Values of the parameter in the "name" argument or all parameters.
+    fn get_params(
+        req: &StatsRequest,
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        ch: &ChannelPair<Req, Res>,
+    ) -> Result<BTreeMap<String, Value>> {
+        let names: Vec<String> = match req.args.get("name") {
+            Some(v) => vec![v.clone()],
+            None => data.lock().unwrap().params.keys().cloned().collect(),
+        };
+
+        let mut values = BTreeMap::new();
+        for name in names.into_iter() {
+            let (_, ops) = Self::param_ops(data, &name)?;
+            let value = (ops.lock().unwrap().get)(&name, (&ch.req, &ch.res))?;
+            values.insert(name, value);
+        }
+        Ok(values)
+    }
+
+    fn set_param(
+        req: &StatsRequest,
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        ch: &ChannelPair<Req, Res>,
+    ) -> Result<BTreeMap<String, Value>> {
+        let (name, value) = match (req.args.get("name"), req.args.get("value")) {
+            (Some(name), Some(value)) => (name, value),
+            _ => Err(anyhow!("\"name\" and \"value\" required").context(StatsErrno(libc::EINVAL)))?,
+        };
+
+        let (meta, ops) = Self::param_ops(data, name)?;
+        let value = meta.parse(value)?;
+        let value = (ops.lock().unwrap().set)(name, value, (&ch.req, &ch.res))?;
+        Ok([(name.clone(), value)].into_iter().collect())
+    }
+
     fn handle_request(
-        line: String,
+        req: &StatsRequest,
         data: &Arc<Mutex<StatsServerData<Req, Res>>>,
         ch: &ChannelPair<Req, Res>,
         open_ops: &mut StatsOpenOps<Req, Res>,
     ) -> Result<StatsResponse> {
-        let req: StatsRequest = serde_json::from_str(&line)?;
-
         match req.req.as_str() {
-            "stats" => {
-                let target = match req.args.get("target") {
-                    Some(v) => v,
-                    None => "top",
-                };
-
-                let ops =
-                    match data.lock().unwrap().ops.get(target) {
-                        Some(v) => v.clone(),
-                        None => Err(anyhow!("unknown stat target {:?}", req)
-                            .context(StatsErrno(libc::EINVAL)))?,
-                    };
-
-                if !open_ops.map.contains_key(target) {
-                    let read = (ops.lock().unwrap().open)((&ch.req, &ch.res))?;
-                    open_ops
-                        .map
-                        .insert(target.into(), (ops.clone(), read, ch.clone()));
-                }
+            "stats" => Self::build_resp(0, &Self::read_stats(req, data, ch, open_ops)?),
+            "stats_meta" => Ok(Self::build_resp(0, &data.lock().unwrap().meta)?),
+            "stats_schema" => Ok(Self::build_resp(
+                0,
+                &StatsSchema::new(&data.lock().unwrap().meta)?,
+            )?),
+            "params_meta" => {
+                let params: BTreeMap<String, StatsParamMeta> = data
+                    .lock()
+                    .unwrap()
+                    .params
+                    .iter()
+                    .map(|(name, (meta, _))| (name.clone(), meta.clone()))
+                    .collect();
+                Self::build_resp(0, &params)
+            }
+            "get_param" => Self::build_resp(0, &Self::get_params(req, data, ch)?),
+            "set" => Self::build_resp(0, &Self::set_param(req, data, ch)?),
+            req => Err(anyhow!("unknown command {:?}", req).context(StatsErrno(libc::EINVAL)))?,
+        }
+    }
 
-                let read = &mut open_ops.map.get_mut(target).unwrap().1;
+    fn parse_subscription(req: &StatsRequest) -> Result<SubscribeArgs> {
+        let intv = match req.args.get("interval_ms") {
+            Some(v) => Duration::from_millis(v.parse().map_err(|e| {
+                anyhow!("invalid interval_ms {:?} ({})", v, e).context(StatsErrno(libc::EINVAL))
+            })?),
+            None => Duration::from_secs(1),
+        };
+        if intv.is_zero() {
+            Err(anyhow!("interval_ms must be positive").context(StatsErrno(libc::EINVAL)))?;
+        }
+        let fields = req.args.get("fields").map(|v| {
+            v.split(',')
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect()
+        });
+        Ok(SubscribeArgs { intv, fields })
+    }
 
-                let resp = read(&req.args, (&ch.req, &ch.res))?;
+    fn filter_fields(mut stats: Value, fields: &Option<BTreeSet<String>>) -> Value {
+        if let (Some(fields), Some(map)) = (fields, stats.as_object_mut()) {
+            map.retain(|k, _| fields.contains(k));
+        }
+        stats
+    }
 
-                Self::build_resp(0, &resp)
+    /// Push samples every interval until the client goes away. The
+    /// connection is dedicated to the subscription from here on.
+    fn push_samples(
+        writer: &mut ResponseWriter,
+        req: &StatsRequest,
+        sub: SubscribeArgs,
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        ch: &ChannelPair<Req, Res>,
+        open_ops: &mut StatsOpenOps<Req, Res>,
+        exit: &Arc<AtomicBool>,
+    ) -> Result<()> {
+        let mut next = Instant::now();
+
+        while !exit.load(Ordering::Relaxed) {
+            let (resp, last) = match Self::read_stats(req, data, ch, open_ops) {
+                Ok(v) => (
+                    Self::build_resp(0, &Self::filter_fields(v, &sub.fields))?,
+                    false,
+                ),
+                Err(e) => (Self::build_err_resp(e)?, true),
+            };
+
+            if let Err(e) = writer.write(&resp) {
+                match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+                    Some(std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset) => {
+                        debug!("subscriber disconnected");
+                        return Ok(());
+                    }
+                    _ => Err(e)?,
+                }
+            }
+            if last {
+                break;
+            }
+
+            // Keep the cadence regardless of how long reading took.
+            next += sub.intv;
+            let now = Instant::now();
+            match next.checked_duration_since(now) {
+                Some(dur) => sleep(dur),
+                None => next = now,
             }
-            "stats_meta" => Ok(Self::build_resp(0, &data.lock().unwrap().meta)?),
-            req => Err(anyhow!("unknown command {:?}", req).context(StatsErrno(libc::EINVAL)))?,
         }
+
+        Ok(())
+    }
+
+    fn check_access(
+        data: &Arc<Mutex<StatsServerData<Req, Res>>>,
+        req: &StatsRequest,
+        peer: &Option<StatsPeer>,
+    ) -> Result<()> {
+        data.lock().unwrap().check_access(req, peer.as_ref())
+    }
+
+    /// Pick the first of the encodings requested by the client which is
+    /// supported. JSON is used if there is none.
+    fn negotiate_encoding(req: &StatsRequest) -> Result<(StatsResponse, StatsEncoding)> {
+        let encoding: StatsEncoding = req
+            .args
+            .get("encodings")
+            .and_then(|v| v.split(',').find_map(|name| name.parse().ok()))
+            .unwrap_or_default();
+
+        let supported: Vec<&str> = StatsEncoding::ALL.iter().map(|enc| enc.name()).collect();
+        let resp = Self::build_resp(
+            0,
+            &serde_json::json!({ "encoding": encoding.name(), "encodings": supported }),
+        )?;
+        Ok((resp, encoding))
     }
 
     fn serve(
-        mut stream: UnixStream,
+        stream: StatsStream,
         data: Arc<Mutex<StatsServerData<Req, Res>>>,
         inner_ch: ChannelPair<Req, Res>,
         exit: Arc<AtomicBool>,
     ) -> Result<()> {
+        let peer = stream.peer()?;
         let mut stream_reader = BufReader::new(stream.try_clone()?);
+        let mut writer = ResponseWriter {
+            stream,
+            encoding: StatsEncoding::Json,
+        };
         let mut open_ops = StatsOpenOps::new();
 
         loop {
@@ -444,19 +728,41 @@ where
                 return Ok(());
             }
 
-            let resp = match Self::handle_request(line, &data, &inner_ch, &mut open_ops) {
-                Ok(v) => v,
-                Err(e) => {
-                    let errno = match e.downcast_ref::<StatsErrno>() {
-                        Some(e) if e.0 != 0 => e.0,
-                        _ => libc::EINVAL,
-                    };
-                    Self::build_resp(errno, &format!("{:?}", &e))?
+            let resp = match serde_json::from_str::<StatsRequest>(&line) {
+                Ok(req) if req.req == "hello" => {
+                    // Reply in the current encoding and switch afterwards.
+                    let (resp, encoding) = Self::negotiate_encoding(&req)?;
+                    writer.write(&resp)?;
+                    debug!("using {} encoding", encoding);
+                    writer.encoding = encoding;
+                    continue;
                 }
+                Ok(req) if req.req == "subscribe" => match Self::check_access(&data, &req, &peer)
+                    .and_then(|_| Self::parse_subscription(&req))
+                {
+                    Ok(sub) => {
+                        return Self::push_samples(
+                            &mut writer,
+                            &req,
+                            sub,
+                            &data,
+                            &inner_ch,
+                            &mut open_ops,
+                            &exit,
+                        )
+                    }
+                    Err(e) => Self::build_err_resp(e)?,
+                },
+                Ok(req) => match Self::check_access(&data, &req, &peer)
+                    .and_then(|_| Self::handle_request(&req, &data, &inner_ch, &mut open_ops))
+                {
+                    Ok(v) => v,
+                    Err(e) => Self::build_err_resp(e)?,
+                },
+                Err(e) => Self::build_err_resp(e.into())?,
             };
 
-            let output = serde_json::to_string(&resp)? + "\n";
-            stream.write_all(output.as_bytes())?;
+            writer.write(&resp)?;
         }
     }
 
@@ -541,21 +847,22 @@ where
         }
     }
 
-    fn listen(self) {
-        let inner_ch_copy = self.inner_ch.clone();
-        let (add_req, add_res) = unbounded::<ChannelPair<Res, Req>>();
-
-        spawn(move || Self::proxy(inner_ch_copy, add_res));
-
-        for stream in self.listener.incoming() {
-            if self.exit.load(Ordering::Relaxed) {
+    fn accept_loop(
+        listener: StatsListener,
+        data: Arc<Mutex<StatsServerData<Req, Res>>>,
+        add_req: Sender<ChannelPair<Res, Req>>,
+        exit: Arc<AtomicBool>,
+    ) {
+        loop {
+            let stream = listener.accept();
+            if exit.load(Ordering::Relaxed) {
                 debug!("listener exiting");
                 break;
             }
             match stream {
                 Ok(stream) => {
-                    let data = self.data.clone();
-                    let exit = self.exit.clone();
+                    let data = data.clone();
+                    let exit = exit.clone();
 
                     let (req_pair, res_pair) = ChannelPair::<Req, Res>::bidi();
                     match add_req.send(res_pair) {
@@ -573,6 +880,20 @@ where
             }
         }
     }
+
+    fn listen(self) {
+        let inner_ch_copy = self.inner_ch.clone();
+        let (add_req, add_res) = unbounded::<ChannelPair<Res, Req>>();
+
+        spawn(move || Self::proxy(inner_ch_copy, add_res));
+
+        for listener in self.listeners.into_iter() {
+            let data = self.data.clone();
+            let add_req = add_req.clone();
+            let exit = self.exit.clone();
+            spawn(move || Self::accept_loop(listener, data, add_req, exit));
+        }
+    }
 }
 
 pub struct StatsServer<Req, Res>
@@ -584,6 +905,10 @@ where
     sched_path: PathBuf,
     stats_path: PathBuf,
     path: Option<PathBuf>,
+    socket_mode: Option<u32>,
+    socket_owner: (Option<u32>, Option<u32>),
+    extra_addrs: Vec<StatsListenAddr>,
+    listen_addrs: Vec<StatsListenAddr>,
 
     data: Arc<Mutex<StatsServerData<Req, Res>>>,
 
@@ -605,6 +930,10 @@ where
             sched_path: PathBuf::from("root"),
             stats_path: PathBuf::from("stats"),
             path: None,
+            socket_mode: None,
+            socket_owner: (None, None),
+            extra_addrs: vec![],
+            listen_addrs: vec![],
             data: Arc::new(Mutex::new(data)),
             outer_ch: och,
             inner_ch: Some(ich),
@@ -632,6 +961,27 @@ where
         self
     }
 
+    /// Permission bits of the socket, e.g. 0o660 together with
+    /// `set_socket_owner()` to limit connections to a group.
+    pub fn set_socket_mode(mut self, mode: u32) -> Self {
+        self.socket_mode = Some(mode);
+        self
+    }
+
+    /// Owner and group of the socket. None leaves the respective id as is.
+    pub fn set_socket_owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.socket_owner = (uid, gid);
+        self
+    }
+
+    /// Listen on an additional abstract UNIX domain socket or a loopback
+    /// TCP port. Use `StatsServerData::set_access()` to restrict the
+    /// requests, TCP peers can only issue requests with `StatsAccess::Any`.
+    pub fn add_listener(mut self, addr: StatsListenAddr) -> Self {
+        self.extra_addrs.push(addr);
+        self
+    }
+
     pub fn launch(mut self) -> Result<Self> {
         self.data.lock().unwrap().verify_meta()?;
 
@@ -651,23 +1001,72 @@ where
             }
         }
 
-        let listener =
-            UnixListener::bind(path).with_context(|| format!("creating UNIX socket {:?}", path))?;
+        let addr = StatsListenAddr::Unix(path.to_path_buf());
+        let mut listeners = vec![self.bind_path(path)?];
+        self.listen_addrs.push(addr);
+
+        for addr in self.extra_addrs.iter() {
+            let listener = StatsListener::bind(addr)?;
+            self.listen_addrs.push(listener.local_addr(addr));
+            listeners.push(listener);
+        }
 
         let inner = StatsServerInner::new(
-            listener,
+            listeners,
             self.data.clone(),
             self.inner_ch.take().unwrap(),
             self.exit.clone(),
         );
 
-        spawn(move || inner.listen());
+        inner.listen();
         Ok(self)
     }
 
+    /// Bind the UNIX domain socket at `path`. With a socket mode or owner,
+    /// it's bound in a private directory and only moved into place after
+    /// they are set, so that it's never accessible with the permissions
+    /// from the umask.
+    fn bind_path(&self, path: &Path) -> Result<StatsListener> {
+        if self.socket_mode.is_none() && self.socket_owner == (None, None) {
+            return StatsListener::bind(&StatsListenAddr::Unix(path.to_path_buf()));
+        }
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_dir = path.with_file_name(format!(".{}.{}", file_name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&tmp_dir)
+            .with_context(|| format!("creating {:?}", &tmp_dir))?;
+
+        let tmp_path = tmp_dir.join(path.file_name().unwrap_or_default());
+        let res = (|| {
+            let listener = StatsListener::bind(&StatsListenAddr::Unix(tmp_path.clone()))?;
+            if let Some(mode) = self.socket_mode {
+                std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(mode))
+                    .with_context(|| format!("changing mode of {:?}", path))?;
+            }
+            if self.socket_owner != (None, None) {
+                std::os::unix::fs::chown(&tmp_path, self.socket_owner.0, self.socket_owner.1)
+                    .with_context(|| format!("changing owner of {:?}", path))?;
+            }
+            std::fs::rename(&tmp_path, path)
+                .with_context(|| format!("moving the socket to {:?}", path))?;
+            Ok(listener)
+        })();
+
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        res
+    }
+
     pub fn channels(&self) -> (Sender<Res>, Receiver<Req>) {
         (self.outer_ch.req.clone(), self.outer_ch.res.clone())
     }
+
+    /// Addresses the server listens on, with the actual port for TCP port 0.
+    pub fn listen_addrs(&self) -> &[StatsListenAddr] {
+        &self.listen_addrs
+    }
 }
 
 impl<Req, Res> std::ops::Drop for StatsServer<Req, Res>
@@ -677,8 +1076,8 @@ where
 {
     fn drop(&mut self) {
         self.exit.store(true, Ordering::Relaxed);
-        if let Some(path) = self.path.as_ref() {
-            let _ = StatsClient::new().set_path(path).connect();
+        for addr in self.listen_addrs.iter() {
+            let _ = StatsClient::new().set_addr(addr.clone()).connect();
         }
     }
 }
@@ -695,3 +1094,28 @@ where
         Ok(serde_json::to_value(self)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_mode() {
+        let dir = std::env::temp_dir().join(format!("scx_stats-mode-{}", std::process::id()));
+        let path = dir.join("stats");
+        let server = StatsServer::<(), ()>::new(StatsServerData::new())
+            .set_path(&path)
+            .set_socket_mode(0o660)
+            .launch()
+            .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        // only the socket is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        StatsClient::new().set_path(&path).connect().unwrap();
+
+        drop(server);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,7 +8,7 @@ use syn::{
     PathArguments, Token, Type, TypePath,
 };
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StatsKind {
     #[serde(rename = "i64")]
     I64,
@@ -62,7 +62,7 @@ impl std::fmt::Display for StatsKind {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StatsData {
     #[serde(rename = "datum")]
     Datum(StatsKind),
@@ -364,4 +364,12 @@ impl Parse for StatsMetaAux {
 
 pub trait Meta {
     fn meta() -> StatsMeta;
+
+    /// Stable hash of the layout of the struct including the structs
+    /// nested in it, see `StatsSchema`. The default implementation only
+    /// covers the struct itself. The `Stats` derive macro covers the
+    /// nested structs too.
+    fn schema_hash() -> u64 {
+        Self::meta().schema_hash()
+    }
 }
@@ -0,0 +1,331 @@
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr as UnixSocketAddr, UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Address of a stats socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatsListenAddr {
+    /// UNIX domain socket on the filesystem, "unix:PATH".
+    Unix(PathBuf),
+    /// Linux abstract UNIX domain socket, "abstract:NAME" or "@NAME".
+    Abstract(String),
+    /// TCP socket, "HOST:PORT". Stats can only be served on loopback
+    /// addresses.
+    Tcp(SocketAddr),
+}
+
+impl FromStr for StatsListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        if let Some(name) = s.strip_prefix("abstract:").or_else(|| s.strip_prefix('@')) {
+            return Ok(Self::Abstract(name.to_string()));
+        }
+        Ok(Self::Tcp(s.parse().with_context(|| {
+            format!("invalid stats socket address {:?}", s)
+        })?))
+    }
+}
+
+impl std::fmt::Display for StatsListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Abstract(name) => write!(f, "abstract:{}", name),
+            Self::Tcp(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+/// Credentials of the process on the other end of a UNIX domain socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatsPeer {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups.
+    pub groups: Vec<u32>,
+}
+
+/// Who may issue a request. TCP peers don't have credentials and are only
+/// allowed by `Any`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StatsAccess {
+    /// Everyone who can connect to the socket.
+    #[default]
+    Any,
+    /// Only root.
+    Root,
+    /// Root and the listed users and members of the listed groups,
+    /// including supplementary ones.
+    Users { uids: Vec<u32>, gids: Vec<u32> },
+    /// Nobody.
+    Deny,
+}
+
+impl StatsAccess {
+    pub fn allows(&self, peer: Option<&StatsPeer>) -> bool {
+        match (self, peer) {
+            (Self::Any, _) => true,
+            (Self::Deny, _) | (_, None) => false,
+            (_, Some(peer)) if peer.uid == 0 => true,
+            (Self::Root, Some(_)) => false,
+            (Self::Users { uids, gids }, Some(peer)) => {
+                uids.contains(&peer.uid)
+                    || gids.contains(&peer.gid)
+                    || peer.groups.iter().any(|gid| gids.contains(gid))
+            }
+        }
+    }
+}
+
+pub(crate) enum StatsStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl StatsStream {
+    pub(crate) fn connect(addr: &StatsListenAddr) -> Result<Self> {
+        Ok(match addr {
+            StatsListenAddr::Unix(path) => Self::Unix(UnixStream::connect(path)?),
+            StatsListenAddr::Abstract(name) => Self::Unix(UnixStream::connect_addr(
+                &UnixSocketAddr::from_abstract_name(name)?,
+            )?),
+            StatsListenAddr::Tcp(addr) => Self::Tcp(TcpStream::connect(addr)?),
+        })
+    }
+
+    /// Set the read and write timeouts of the stream.
+    pub(crate) fn set_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Self::Unix(s) => {
+                s.set_read_timeout(timeout)?;
+                s.set_write_timeout(timeout)
+            }
+            Self::Tcp(s) => {
+                s.set_read_timeout(timeout)?;
+                s.set_write_timeout(timeout)
+            }
+        }
+    }
+
+    pub(crate) fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(match self {
+            Self::Unix(s) => Self::Unix(s.try_clone()?),
+            Self::Tcp(s) => Self::Tcp(s.try_clone()?),
+        })
+    }
+
+    /// SO_PEERCRED and SO_PEERGROUPS of a UNIX domain socket, None for TCP.
+    pub(crate) fn peer(&self) -> Result<Option<StatsPeer>> {
+        let stream = match self {
+            Self::Unix(s) => s,
+            Self::Tcp(_) => return Ok(None),
+        };
+
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret < 0 {
+            Err(std::io::Error::last_os_error()).context("reading SO_PEERCRED")?;
+        }
+
+        Ok(Some(StatsPeer {
+            pid: cred.pid,
+            uid: cred.uid,
+            gid: cred.gid,
+            groups: Self::peer_groups(stream)?,
+        }))
+    }
+
+    fn peer_groups(stream: &UnixStream) -> Result<Vec<u32>> {
+        const GID_SIZE: usize = std::mem::size_of::<libc::gid_t>();
+        let mut groups: Vec<libc::gid_t> = vec![0; 16];
+
+        loop {
+            let mut len = (groups.len() * GID_SIZE) as libc::socklen_t;
+            let ret = unsafe {
+                libc::getsockopt(
+                    stream.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_PEERGROUPS,
+                    groups.as_mut_ptr() as *mut libc::c_void,
+                    &mut len,
+                )
+            };
+            if ret == 0 {
+                groups.truncate(len as usize / GID_SIZE);
+                return Ok(groups);
+            }
+
+            // The kernel reports the required size if the buffer is too small.
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) && len as usize > groups.len() * GID_SIZE {
+                groups.resize(len as usize / GID_SIZE, 0);
+                continue;
+            }
+            Err(err).context("reading SO_PEERGROUPS")?;
+        }
+    }
+}
+
+impl Read for StatsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(s) => s.read(buf),
+            Self::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for StatsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(s) => s.write(buf),
+            Self::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Unix(s) => s.flush(),
+            Self::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+pub(crate) enum StatsListener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl StatsListener {
+    /// Listen on an abstract or TCP address. Sockets on the filesystem are
+    /// set up by `StatsServer::launch()` itself.
+    pub(crate) fn bind(addr: &StatsListenAddr) -> Result<Self> {
+        Ok(match addr {
+            StatsListenAddr::Unix(path) => Self::Unix(
+                UnixListener::bind(path)
+                    .with_context(|| format!("creating UNIX socket {:?}", path))?,
+            ),
+            StatsListenAddr::Abstract(name) => Self::Unix(
+                UnixListener::bind_addr(&UnixSocketAddr::from_abstract_name(name)?)
+                    .with_context(|| format!("creating abstract UNIX socket {:?}", name))?,
+            ),
+            StatsListenAddr::Tcp(sockaddr) => {
+                if !sockaddr.ip().is_loopback() {
+                    bail!("stats can only be served on loopback, not {}", sockaddr);
+                }
+                Self::Tcp(
+                    TcpListener::bind(sockaddr)
+                        .with_context(|| format!("binding to {}", sockaddr))?,
+                )
+            }
+        })
+    }
+
+    /// The address to connect to, e.g. with the actual port for port 0.
+    pub(crate) fn local_addr(&self, addr: &StatsListenAddr) -> StatsListenAddr {
+        match self {
+            Self::Tcp(l) => l.local_addr().map_or(addr.clone(), StatsListenAddr::Tcp),
+            Self::Unix(_) => addr.clone(),
+        }
+    }
+
+    pub(crate) fn accept(&self) -> std::io::Result<StatsStream> {
+        Ok(match self {
+            Self::Unix(l) => StatsStream::Unix(l.accept()?.0),
+            Self::Tcp(l) => StatsStream::Tcp(l.accept()?.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access() {
+        let user = StatsPeer {
+            pid: 100,
+            uid: 1000,
+            gid: 1000,
+            groups: vec![],
+        };
+        let root = StatsPeer {
+            pid: 1,
+            uid: 0,
+            gid: 0,
+            groups: vec![],
+        };
+        let member = StatsPeer {
+            pid: 200,
+            uid: 1001,
+            gid: 1001,
+            groups: vec![10, 1000],
+        };
+        let monitoring = StatsAccess::Users {
+            uids: vec![],
+            gids: vec![1000],
+        };
+
+        assert!(StatsAccess::Any.allows(None));
+        assert!(!StatsAccess::Root.allows(Some(&user)));
+        assert!(StatsAccess::Root.allows(Some(&root)));
+        assert!(monitoring.allows(Some(&user)));
+        assert!(monitoring.allows(Some(&root)));
+        assert!(monitoring.allows(Some(&member)));
+        assert!(!monitoring.allows(Some(&StatsPeer {
+            groups: vec![10],
+            ..member.clone()
+        })));
+        assert!(!monitoring.allows(None));
+        assert!(!StatsAccess::Deny.allows(Some(&root)));
+
+        assert_eq!(
+            "@scx_stats".parse::<StatsListenAddr>().unwrap(),
+            StatsListenAddr::Abstract("scx_stats".into())
+        );
+        assert_eq!(
+            "unix:/run/scx/stats".parse::<StatsListenAddr>().unwrap(),
+            StatsListenAddr::Unix("/run/scx/stats".into())
+        );
+        assert!(StatsListener::bind(&"0.0.0.0:0".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_peer() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let peer = StatsStream::Unix(a).peer().unwrap().unwrap();
+        assert_eq!(peer.pid, std::process::id() as i32);
+        assert_eq!(peer.uid, unsafe { libc::geteuid() });
+
+        let mut groups = vec![0; unsafe { libc::getgroups(0, std::ptr::null_mut()) } as usize];
+        unsafe { libc::getgroups(groups.len() as i32, groups.as_mut_ptr()) };
+        let mut peer_groups = peer.groups.clone();
+        groups.sort();
+        peer_groups.sort();
+        assert_eq!(peer_groups, groups);
+    }
+}
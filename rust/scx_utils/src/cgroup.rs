@@ -0,0 +1,653 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! # Cgroup v2 Discovery and Monitoring
+//!
+//! Helpers to enumerate the cgroup v2 hierarchy, read the CPU related
+//! interface files and follow cgroups being created and removed:
+//!
+//!```no_run
+//!     use scx_utils::cgroup::read_cgroups;
+//!     use scx_utils::cgroup::CgroupEvent;
+//!     use scx_utils::cgroup::CgroupWatcher;
+//!     use std::time::Duration;
+//!
+//!     for cgrp in read_cgroups().unwrap().values() {
+//!         println!("{} {:?} weight={:?}", cgrp.id, cgrp.path, cgrp.cpu_weight());
+//!     }
+//!
+//!     let mut watcher = CgroupWatcher::new().unwrap();
+//!     loop {
+//!         for event in watcher.wait(Duration::from_secs(1)).unwrap() {
+//!             match event {
+//!                 CgroupEvent::Created(cgrp) => println!("created {:?}", cgrp.path),
+//!                 CgroupEvent::Removed(cgrp) => println!("removed {:?}", cgrp.path),
+//!             }
+//!         }
+//!     }
+//!```
+//!
+//! A cgroup is identified by the inode number of its directory, which is
+//! the ID BPF programs see in cgrp->kn->id and bpf_get_current_cgroup_id().
+//! Paths are relative to the cgroupfs root, CGROUP_ROOT under ROOT_PREFIX,
+//! with the root cgroup at "".
+
+use crate::compat::ROOT_PREFIX;
+use crate::Cpumask;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use log::warn;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The cgroupfs root under ROOT_PREFIX.
+pub fn cgroup_root() -> PathBuf {
+    PathBuf::from(format!("{}{}", *ROOT_PREFIX, CGROUP_ROOT))
+}
+
+/// Bandwidth limit from cpu.max. `quota_us` is None if unlimited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuMax {
+    pub quota_us: Option<u64>,
+    pub period_us: u64,
+}
+
+impl CpuMax {
+    /// The number of CPUs worth of bandwidth, None if unlimited.
+    pub fn cpus(&self) -> Option<f64> {
+        self.quota_us
+            .map(|quota| quota as f64 / self.period_us as f64)
+    }
+}
+
+/// Parse the content of cpu.max, e.g. "max 100000" or "50000 100000".
+pub fn parse_cpu_max(content: &str) -> Result<CpuMax> {
+    let mut fields = content.split_whitespace();
+    let (Some(quota), Some(period), None) = (fields.next(), fields.next(), fields.next()) else {
+        bail!("Invalid cpu.max {:?}", content.trim());
+    };
+    let quota_us = match quota {
+        "max" => None,
+        v => Some(
+            v.parse::<u64>()
+                .with_context(|| format!("Invalid cpu.max quota {:?}", v))?,
+        ),
+    };
+    let period_us = period
+        .parse::<u64>()
+        .with_context(|| format!("Invalid cpu.max period {:?}", period))?;
+    Ok(CpuMax {
+        quota_us,
+        period_us,
+    })
+}
+
+/// Parse the content of cpu.weight, which is in [1, 10000].
+pub fn parse_cpu_weight(content: &str) -> Result<u32> {
+    let weight = content
+        .trim()
+        .parse::<u32>()
+        .with_context(|| format!("Invalid cpu.weight {:?}", content.trim()))?;
+    if !(1..=10000).contains(&weight) {
+        bail!("cpu.weight {} out of range", weight);
+    }
+    Ok(weight)
+}
+
+/// Parse the content of a cpuset cpulist file. An empty file is an empty
+/// Cpumask. The Cpumask is widened beyond *NR_CPU_IDS if needed to hold
+/// all the CPUs.
+pub fn parse_cpuset(content: &str) -> Result<Cpumask> {
+    match content.trim() {
+        "" => Ok(Cpumask::new()),
+        cpulist => Cpumask::from_cpulist(cpulist),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cgroup {
+    /// Inode number of the cgroup directory, also its BPF cgroup ID.
+    pub id: u64,
+    /// Path relative to the cgroupfs root, "" for the root cgroup.
+    pub path: PathBuf,
+    /// Depth in the hierarchy, 0 for the root cgroup.
+    pub level: usize,
+    /// Absolute path of the cgroup directory.
+    fs_path: PathBuf,
+    /// Device of the cgroupfs, to avoid walking into other mounts.
+    dev: u64,
+}
+
+impl Cgroup {
+    fn from_dir(root: &Path, path: &Path) -> Result<Self> {
+        let fs_path = root.join(path);
+        let meta = std::fs::metadata(&fs_path)
+            .with_context(|| format!("Failed to stat {}", fs_path.display()))?;
+        if !meta.is_dir() {
+            bail!("{} is not a cgroup", fs_path.display());
+        }
+        Ok(Self {
+            id: meta.ino(),
+            path: path.to_path_buf(),
+            level: path.components().count(),
+            fs_path,
+            dev: meta.dev(),
+        })
+    }
+
+    /// Look up a cgroup by its path relative to the cgroupfs root.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        Self::from_dir(&cgroup_root(), path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Absolute path of the cgroup directory.
+    pub fn fs_path(&self) -> &Path {
+        &self.fs_path
+    }
+
+    fn read(&self, file: &str) -> Result<String> {
+        let path = self.fs_path.join(file);
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    /// The CPUs the cgroup's tasks can run on, from cpuset.cpus.effective.
+    pub fn cpuset_effective(&self) -> Result<Cpumask> {
+        parse_cpuset(&self.read("cpuset.cpus.effective")?)
+    }
+
+    /// The cgroup's cpu.weight. Fails if the cpu controller isn't enabled
+    /// for the cgroup.
+    pub fn cpu_weight(&self) -> Result<u32> {
+        parse_cpu_weight(&self.read("cpu.weight")?)
+    }
+
+    /// The cgroup's cpu.max. Fails if the cpu controller isn't enabled for
+    /// the cgroup.
+    pub fn cpu_max(&self) -> Result<CpuMax> {
+        parse_cpu_max(&self.read("cpu.max")?)
+    }
+}
+
+/// Resolve a cgroup path, relative to the cgroupfs root, to the cgroup ID
+/// BPF programs see.
+pub fn cgroup_id<P: AsRef<Path>>(path: P) -> Result<u64> {
+    Ok(Cgroup::from_path(path)?.id)
+}
+
+/// Paths of the child cgroups of `cgrp`, empty if it was removed. Other
+/// filesystems mounted below, e.g. cgroup v1 hierarchies in hybrid setups,
+/// are skipped.
+fn child_paths(cgrp: &Cgroup) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(&cgrp.fs_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", cgrp.fs_path.display()))
+        }
+    };
+    let mut children: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .metadata()
+                .is_ok_and(|meta| meta.is_dir() && meta.dev() == cgrp.dev)
+        })
+        .map(|entry| cgrp.path.join(entry.file_name()))
+        .collect();
+    children.sort();
+    Ok(children)
+}
+
+/// Enumerate the cgroups at and below `path` in `root`, parents first.
+/// Cgroups removed while walking are skipped.
+fn scan_cgroups(root: &Path, path: &Path) -> Result<Vec<Cgroup>> {
+    let mut cgroups = vec![Cgroup::from_dir(root, path)?];
+    let mut idx = 0;
+    while idx < cgroups.len() {
+        let children = child_paths(&cgroups[idx])?;
+        idx += 1;
+        cgroups.extend(
+            children
+                .iter()
+                .filter_map(|child| Cgroup::from_dir(root, child).ok()),
+        );
+    }
+    Ok(cgroups)
+}
+
+/// Enumerate the cgroup hierarchy, indexed by cgroup ID.
+pub fn read_cgroups() -> Result<BTreeMap<u64, Cgroup>> {
+    Ok(scan_cgroups(&cgroup_root(), Path::new(""))?
+        .into_iter()
+        .map(|cgrp| (cgrp.id, cgrp))
+        .collect())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CgroupEvent {
+    Created(Cgroup),
+    Removed(Cgroup),
+}
+
+/// Follows cgroups being created and removed through inotify. Each cgroup
+/// directory is watched for subdirectories being created and removed.
+pub struct CgroupWatcher {
+    root: PathBuf,
+    fd: OwnedFd,
+    /// Watch descriptors and the paths of the watched cgroups.
+    wds: HashMap<i32, PathBuf>,
+    cgroups: BTreeMap<PathBuf, Cgroup>,
+    /// Events were lost or cgroups couldn't be followed.
+    needs_rescan: bool,
+}
+
+const INOTIFY_MASK: u32 =
+    libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+
+impl CgroupWatcher {
+    /// Watch the whole cgroup hierarchy. Every cgroup takes an inotify
+    /// watch which counts against /proc/sys/fs/inotify/max_user_watches.
+    /// Creating the watcher fails if the existing cgroups exceed the limit.
+    /// Cgroups created later which can't be watched are logged and retried
+    /// on the following read_events() calls, e.g. after the limit is raised.
+    pub fn new() -> Result<Self> {
+        Self::with_root(cgroup_root())
+    }
+
+    fn with_root(root: PathBuf) -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            bail!(
+                "Failed to initialize inotify ({})",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        let mut watcher = Self {
+            root,
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            wds: HashMap::new(),
+            cgroups: BTreeMap::new(),
+            needs_rescan: false,
+        };
+        watcher.add_subtree(Path::new(""), &mut vec![])?;
+        Ok(watcher)
+    }
+
+    /// The currently known cgroups, indexed by path.
+    pub fn cgroups(&self) -> &BTreeMap<PathBuf, Cgroup> {
+        &self.cgroups
+    }
+
+    fn add_watch(&mut self, path: &Path) -> Result<()> {
+        let fs_path = self.root.join(path);
+        let cpath = CString::new(fs_path.as_os_str().as_bytes())?;
+        let wd =
+            unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), cpath.as_ptr(), INOTIFY_MASK) };
+        if wd < 0 {
+            bail!(
+                "Failed to watch {} ({})",
+                fs_path.display(),
+                std::io::Error::last_os_error()
+            );
+        }
+        self.wds.insert(wd, path.to_path_buf());
+        Ok(())
+    }
+
+    /// Start tracking the cgroups at and below `path`, parents first. Each
+    /// directory is watched before it's read so that no child is missed.
+    /// The cgroups which weren't known yet are appended to `added`, also
+    /// the ones tracked before an error.
+    fn add_subtree(&mut self, path: &Path, added: &mut Vec<Cgroup>) -> Result<()> {
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            if self.cgroups.contains_key(&path) {
+                continue;
+            }
+            // Cgroups may be removed at any point, skip them if so.
+            if let Err(e) = self.add_watch(&path) {
+                match self.root.join(&path).exists() {
+                    true => return Err(e),
+                    false => continue,
+                }
+            }
+            let Ok(cgrp) = Cgroup::from_dir(&self.root, &path) else {
+                continue;
+            };
+            pending.extend(child_paths(&cgrp)?.into_iter().rev());
+            self.cgroups.insert(path, cgrp.clone());
+            added.push(cgrp);
+        }
+        Ok(())
+    }
+
+    /// Stop tracking the cgroups at and below `path`. Returns the removed
+    /// cgroups, children first.
+    fn remove_subtree(&mut self, path: &Path) -> Vec<Cgroup> {
+        let paths: Vec<PathBuf> = self
+            .cgroups
+            .range(path.to_path_buf()..)
+            .map(|(p, _)| p)
+            .take_while(|p| p.starts_with(path))
+            .cloned()
+            .collect();
+        self.wds.retain(|_, p| !p.starts_with(path));
+        paths
+            .iter()
+            .rev()
+            .filter_map(|p| self.cgroups.remove(p))
+            .collect()
+    }
+
+    /// Rescan the whole hierarchy after events were lost. The changes are
+    /// appended to `events`. Cgroups which can't be followed are skipped
+    /// and the first error is returned after the others were added.
+    fn rescan(&mut self, events: &mut Vec<CgroupEvent>) -> Result<()> {
+        let known: Vec<PathBuf> = self.cgroups.keys().cloned().collect();
+        for path in known.iter().rev() {
+            let stale = match Cgroup::from_dir(&self.root, path) {
+                Ok(cgrp) => cgrp.id != self.cgroups[path].id,
+                Err(_) => true,
+            };
+            if stale && self.cgroups.contains_key(path) {
+                events.extend(
+                    self.remove_subtree(path)
+                        .into_iter()
+                        .map(CgroupEvent::Removed),
+                );
+            }
+        }
+        let mut res = Ok(());
+        for cgrp in scan_cgroups(&self.root, Path::new(""))? {
+            if !self.cgroups.contains_key(&cgrp.path) {
+                let mut added = vec![];
+                if let Err(e) = self.add_subtree(&cgrp.path, &mut added) {
+                    res = res.and(Err(e));
+                }
+                events.extend(added.into_iter().map(CgroupEvent::Created));
+            }
+        }
+        res
+    }
+
+    /// Read the pending events without blocking. Cgroups which can't be
+    /// followed don't stop the processing of the other events. They're
+    /// logged and the hierarchy is rescanned.
+    pub fn read_events(&mut self) -> Result<Vec<CgroupEvent>> {
+        let mut events = vec![];
+        let mut buf = [0u8; 16384];
+        loop {
+            let len = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if len < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    break;
+                }
+                bail!("Failed to read inotify events ({})", err);
+            }
+
+            let len = len as usize;
+            let hdr_len = std::mem::size_of::<libc::inotify_event>();
+            let mut off = 0;
+            while off + hdr_len <= len {
+                let ev: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf[off..].as_ptr() as *const _) };
+                let name = &buf[off + hdr_len..off + hdr_len + ev.len as usize];
+                let name = OsStr::from_bytes(name.split(|&b| b == 0).next().unwrap_or(&[]));
+                off += hdr_len + ev.len as usize;
+
+                if ev.mask & libc::IN_Q_OVERFLOW != 0 {
+                    self.needs_rescan = true;
+                    continue;
+                }
+                if ev.mask & libc::IN_IGNORED != 0 {
+                    self.wds.remove(&ev.wd);
+                    continue;
+                }
+                if ev.mask & libc::IN_ISDIR == 0 {
+                    continue;
+                }
+                let Some(parent) = self.wds.get(&ev.wd) else {
+                    continue;
+                };
+                let path = parent.join(name);
+
+                if ev.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    let mut added = vec![];
+                    if let Err(e) = self.add_subtree(&path, &mut added) {
+                        warn!("Failed to follow cgroup {} ({:#})", path.display(), &e);
+                        self.needs_rescan = true;
+                    }
+                    events.extend(added.into_iter().map(CgroupEvent::Created));
+                } else if ev.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                    let removed = self.remove_subtree(&path);
+                    events.extend(removed.into_iter().map(CgroupEvent::Removed));
+                }
+            }
+        }
+
+        if self.needs_rescan {
+            match self.rescan(&mut events) {
+                Ok(()) => self.needs_rescan = false,
+                Err(e) => warn!("Failed to rescan cgroups, retrying ({:#})", &e),
+            }
+        }
+        Ok(events)
+    }
+
+    /// Wait up to `timeout` for cgroups to be created or removed. Returns
+    /// an empty Vec on timeout.
+    pub fn wait(&mut self, timeout: Duration) -> Result<Vec<CgroupEvent>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let events = self.read_events()?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(events);
+            }
+            let mut pfd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ms = (deadline - now).as_millis().clamp(1, i32::MAX as u128) as i32;
+            unsafe { libc::poll(&mut pfd, 1, ms) };
+        }
+    }
+}
+
+/// The inotify file descriptor becomes readable when events are pending,
+/// which allows adding the watcher to an event loop.
+impl AsFd for CgroupWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn fake_cgroupfs() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for (path, cpus, weight, max) in [
+            ("", "0-3", None, None),
+            ("system.slice", "0-1", Some("100"), Some("max 100000")),
+            ("workload.slice", "2-3", Some("400"), Some("150000 100000")),
+            ("workload.slice/job", "3", Some("50"), Some("max 100000")),
+        ] {
+            let dir = root.join(path);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cpuset.cpus.effective"), format!("{}\n", cpus)).unwrap();
+            if let Some(weight) = weight {
+                fs::write(dir.join("cpu.weight"), format!("{}\n", weight)).unwrap();
+            }
+            if let Some(max) = max {
+                fs::write(dir.join("cpu.max"), format!("{}\n", max)).unwrap();
+            }
+        }
+        dir
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_cpu_max("max 100000\n").unwrap(),
+            CpuMax {
+                quota_us: None,
+                period_us: 100000
+            }
+        );
+        assert_eq!(parse_cpu_max("150000 100000").unwrap().cpus(), Some(1.5));
+        assert!(parse_cpu_max("max").is_err());
+        assert!(parse_cpu_max("1 2 3").is_err());
+        assert_eq!(parse_cpu_weight("100\n").unwrap(), 100);
+        assert!(parse_cpu_weight("0").is_err());
+        assert!(parse_cpu_weight("10001").is_err());
+        assert!(parse_cpuset("\n").unwrap().is_empty());
+        assert_eq!(parse_cpuset("0,2\n").unwrap().to_cpulist(), "0,2");
+        let nr = *crate::NR_CPU_IDS;
+        let mask = parse_cpuset(&format!("{}\n", nr + 4)).unwrap();
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![nr + 4]);
+    }
+
+    #[test]
+    fn test_scan() {
+        let dir = fake_cgroupfs();
+        let root = dir.path();
+        let cgroups = scan_cgroups(root, Path::new("")).unwrap();
+        let paths: Vec<&Path> = cgroups.iter().map(|cgrp| cgrp.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            ["", "system.slice", "workload.slice", "workload.slice/job"].map(Path::new)
+        );
+
+        let job = &cgroups[3];
+        assert_eq!(job.level, 2);
+        assert_eq!(
+            job.id,
+            fs::metadata(root.join("workload.slice/job")).unwrap().ino()
+        );
+        assert_eq!(job.cpuset_effective().unwrap().to_cpulist(), "3");
+        assert_eq!(job.cpu_weight().unwrap(), 50);
+        assert_eq!(job.cpu_max().unwrap().quota_us, None);
+        assert_eq!(cgroups[2].cpu_max().unwrap().cpus(), Some(1.5));
+        assert!(cgroups[0].cpu_weight().is_err());
+    }
+
+    #[test]
+    fn test_watcher() {
+        let dir = fake_cgroupfs();
+        let root = dir.path();
+        let mut watcher = CgroupWatcher::with_root(root.to_path_buf()).unwrap();
+        assert_eq!(watcher.cgroups().len(), 4);
+        assert!(watcher.read_events().unwrap().is_empty());
+
+        fs::create_dir_all(root.join("workload.slice/job2/sub")).unwrap();
+        let events = watcher.wait(Duration::from_secs(1)).unwrap();
+        let created: Vec<&Path> = events
+            .iter()
+            .map(|ev| match ev {
+                CgroupEvent::Created(cgrp) => cgrp.path.as_path(),
+                CgroupEvent::Removed(_) => panic!("unexpected {:?}", ev),
+            })
+            .collect();
+        assert_eq!(
+            created,
+            ["workload.slice/job2", "workload.slice/job2/sub"].map(Path::new)
+        );
+        let sub = watcher.cgroups()[Path::new("workload.slice/job2/sub")].clone();
+        assert_eq!(
+            sub.id,
+            fs::metadata(root.join("workload.slice/job2/sub"))
+                .unwrap()
+                .ino()
+        );
+
+        fs::remove_dir(root.join("workload.slice/job2/sub")).unwrap();
+        fs::remove_dir(root.join("workload.slice/job2")).unwrap();
+        let mut events = watcher.wait(Duration::from_secs(1)).unwrap();
+        if events.len() < 2 {
+            events.extend(watcher.wait(Duration::from_secs(1)).unwrap());
+        }
+        let removed: Vec<&Path> = events
+            .iter()
+            .map(|ev| match ev {
+                CgroupEvent::Removed(cgrp) => cgrp.path.as_path(),
+                CgroupEvent::Created(_) => panic!("unexpected {:?}", ev),
+            })
+            .collect();
+        assert_eq!(
+            removed,
+            ["workload.slice/job2/sub", "workload.slice/job2"].map(Path::new)
+        );
+        assert_eq!(watcher.cgroups().len(), 4);
+        assert!(watcher.wait(Duration::from_millis(10)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watcher_failure() {
+        let dir = fake_cgroupfs();
+        let root = dir.path();
+        let mut watcher = CgroupWatcher::with_root(root.to_path_buf()).unwrap();
+
+        // "a" is replaced with a file before its creation is processed, so
+        // it can't be watched. The events after it are still processed.
+        fs::create_dir(root.join("a")).unwrap();
+        fs::create_dir(root.join("b")).unwrap();
+        fs::remove_dir(root.join("a")).unwrap();
+        fs::write(root.join("a"), "").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let events = watcher.read_events().unwrap();
+        assert_eq!(
+            events,
+            vec![CgroupEvent::Created(
+                watcher.cgroups()[Path::new("b")].clone()
+            )]
+        );
+        assert!(!watcher.cgroups().contains_key(Path::new("a")));
+        assert!(!watcher.needs_rescan);
+
+        fs::remove_file(root.join("a")).unwrap();
+        fs::create_dir(root.join("a")).unwrap();
+        let events = watcher.wait(Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            events,
+            vec![CgroupEvent::Created(
+                watcher.cgroups()[Path::new("a")].clone()
+            )]
+        );
+    }
+}
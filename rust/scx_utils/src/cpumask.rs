@@ -54,36 +54,115 @@
 //!     info!("{:#?}", mask); // 32:<11111111111111111111111111111111>
 //!     assert!(mask.test_cpu(0));
 //!```
+//!
+//! Cpumasks are displayed as cpulists, which stay short on large machines.
+//! The hexadecimal form is available through the `{:x}` and `{:X}` formats:
+//!
+//!```
+//!     use scx_utils::Cpumask;
+//!     let mask = Cpumask::from_cpulist("0-3,8").unwrap();
+//!     assert_eq!(format!("{}", mask), "0-3,8");
+//!     assert!(format!("{:x}", mask).ends_with("10f"));
+//!```
+//!
+//! They can be converted to and from the `cpu_set_t` used by
+//! sched_setaffinity(2), either directly or through `nix::sched::CpuSet`:
+//!
+//!```no_run
+//!     use nix::sched::sched_setaffinity;
+//!     use nix::sched::CpuSet;
+//!     use nix::unistd::Pid;
+//!     use scx_utils::Cpumask;
+//!     let mask = Cpumask::from_cpulist("0-3").unwrap();
+//!     let cpuset = CpuSet::try_from(&mask).unwrap();
+//!     sched_setaffinity(Pid::from_raw(0), &cpuset).unwrap();
+//!```
 
 use crate::NR_CPU_IDS;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bitvec::prelude::*;
+use nix::sched::CpuSet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sscanf::sscanf;
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::ops::BitAndAssign;
 use std::ops::BitOrAssign;
 use std::ops::BitXorAssign;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+use std::ops::RangeInclusive;
 
-#[derive(Debug, Eq, Clone, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
 pub struct Cpumask {
     mask: BitVec<u64, Lsb0>,
+    /// Number of CPUs the Cpumask holds, *NR_CPU_IDS unless it was widened
+    /// explicitly. The bits beyond are padding and always clear.
+    nr_cpus: usize,
 }
 
 impl Cpumask {
     fn check_cpu(&self, cpu: usize) -> Result<()> {
-        if cpu >= *NR_CPU_IDS {
-            bail!("Invalid CPU {} passed, max {}", cpu, *NR_CPU_IDS);
+        if cpu >= self.nr_cpus {
+            bail!("Invalid CPU {} passed, max {}", cpu, self.nr_cpus);
         }
 
         Ok(())
     }
 
+    /// Build an empty Cpumask which can hold at least `nr_cpus` CPUs, e.g. to
+    /// describe CPUs beyond *NR_CPU_IDS which aren't possible on this
+    /// machine.
+    pub fn with_width(nr_cpus: usize) -> Cpumask {
+        let nr_cpus = nr_cpus.max(*NR_CPU_IDS);
+        Cpumask {
+            mask: bitvec![u64, Lsb0; 0; nr_cpus],
+            nr_cpus,
+        }
+    }
+
+    /// Extend the Cpumask with clear bits so that it holds at least `nr_cpus`
+    /// CPUs.
+    fn grow(&mut self, nr_cpus: usize) {
+        if self.mask.len() < nr_cpus {
+            self.mask.resize(nr_cpus, false);
+        }
+        self.nr_cpus = self.nr_cpus.max(nr_cpus);
+    }
+
+    /// Convert `range` to a half-open range, checking that it fits in the
+    /// Cpumask.
+    fn check_range(&self, range: impl RangeBounds<usize>) -> Result<(usize, usize)> {
+        let start = match range.start_bound() {
+            Bound::Included(&v) => v,
+            Bound::Excluded(&v) => v + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&v) => v + 1,
+            Bound::Excluded(&v) => v,
+            Bound::Unbounded => self.nr_cpus,
+        };
+        if start > end || end > self.nr_cpus {
+            bail!(
+                "Invalid CPU range {}..{} passed, max {}",
+                start,
+                end,
+                self.nr_cpus
+            );
+        }
+        Ok((start, end))
+    }
+
     /// Build a new empty Cpumask object.
     pub fn new() -> Cpumask {
         Cpumask {
             mask: bitvec![u64, Lsb0; 0; *NR_CPU_IDS],
+            nr_cpus: *NR_CPU_IDS,
         }
     }
 
@@ -91,12 +170,14 @@ impl Cpumask {
     pub fn from_str(cpumask: &str) -> Result<Cpumask> {
         match cpumask {
             "none" => {
-                let mask = bitvec![u64, Lsb0; 0; *NR_CPU_IDS];
-                return Ok(Self { mask });
+                return Ok(Self::new());
             }
             "all" => {
                 let mask = bitvec![u64, Lsb0; 1; *NR_CPU_IDS];
-                return Ok(Self { mask });
+                return Ok(Self {
+                    mask,
+                    nr_cpus: *NR_CPU_IDS,
+                });
             }
             _ => {}
         }
@@ -135,26 +216,40 @@ impl Cpumask {
             }
         }
 
-        Ok(Self { mask })
+        Ok(Self {
+            mask,
+            nr_cpus: *NR_CPU_IDS,
+        })
     }
 
     pub fn from_cpulist(cpulist: &str) -> Result<Cpumask> {
-        let mut mask = Cpumask::new();
-        for cpu_id in read_cpulist(cpulist)? {
-            let _ = mask.set_cpu(cpu_id);
+        let cpus = read_cpulist(cpulist)?;
+        let mut mask = Cpumask::with_width(cpus.iter().max().map_or(0, |max| max + 1));
+        for cpu in cpus {
+            mask.mask.set(cpu, true);
         }
 
         Ok(mask)
     }
 
+    /// Build a Cpumask from raw u64's, e.g. as read from BPF. The padding of
+    /// the last u64 isn't part of the Cpumask, it holds *NR_CPU_IDS CPUs or
+    /// up to the highest CPU set if that's beyond.
     pub fn from_vec(vec: Vec<u64>) -> Self {
-        Self {
-            mask: BitVec::from_vec(vec),
-        }
+        let mask = BitVec::from_vec(vec);
+        let nr_cpus = mask
+            .len()
+            .min(*NR_CPU_IDS)
+            .max(mask.last_one().map_or(0, |cpu| cpu + 1));
+        Self { mask, nr_cpus }
     }
 
+    /// Build a Cpumask which holds as many CPUs as `bitvec` is long.
     pub fn from_bitvec(bitvec: BitVec<u64, Lsb0>) -> Self {
-        Self { mask: bitvec }
+        Self {
+            nr_cpus: bitvec.len(),
+            mask: bitvec,
+        }
     }
 
     /// Return a slice of u64's whose bits reflect the Cpumask.
@@ -174,7 +269,7 @@ impl Cpumask {
 
     /// Set all bits in the Cpumask to 1
     pub fn set_all(&mut self) {
-        self.mask.fill(true);
+        self.mask[..self.nr_cpus].fill(true);
     }
 
     /// Set all bits in the Cpumask to 0
@@ -182,6 +277,22 @@ impl Cpumask {
         self.mask.fill(false);
     }
 
+    /// Set the CPUs in `range`. Returns an error if the range exceeds the
+    /// size of the Cpumask.
+    pub fn set_range(&mut self, range: impl RangeBounds<usize>) -> Result<()> {
+        let (start, end) = self.check_range(range)?;
+        self.mask[start..end].fill(true);
+        Ok(())
+    }
+
+    /// Clear the CPUs in `range`. Returns an error if the range exceeds the
+    /// size of the Cpumask.
+    pub fn clear_range(&mut self, range: impl RangeBounds<usize>) -> Result<()> {
+        let (start, end) = self.check_range(range)?;
+        self.mask[start..end].fill(false);
+        Ok(())
+    }
+
     /// Set a bit in the Cpumask. Returns an error if the specified CPU exceeds
     /// the size of the Cpumask.
     pub fn set_cpu(&mut self, cpu: usize) -> Result<()> {
@@ -219,42 +330,69 @@ impl Cpumask {
 
     /// Return true if the Cpumask has all bits set, false otherwise.
     pub fn is_full(&self) -> bool {
-        self.mask.count_ones() == *NR_CPU_IDS
+        self.mask.count_ones() == self.nr_cpus
     }
 
-    /// The total size of the cpumask.
+    /// The total size of the cpumask, *NR_CPU_IDS unless it was widened.
     pub fn len(&self) -> usize {
-        *NR_CPU_IDS
+        self.nr_cpus
+    }
+
+    /// Return true if all CPUs set in the Cpumask are also set in `other`.
+    pub fn is_subset(&self, other: &Cpumask) -> bool {
+        self.mask.iter_ones().all(|cpu| other.test_cpu(cpu))
+    }
+
+    /// Return true if the Cpumask and `other` have any CPU in common.
+    pub fn intersects(&self, other: &Cpumask) -> bool {
+        self.mask.iter_ones().any(|cpu| other.test_cpu(cpu))
     }
 
     /// Create a Cpumask that is the negation of the current Cpumask.
     pub fn not(&self) -> Cpumask {
         let mut new = self.clone();
         new.mask = !new.mask;
+        new.mask[self.nr_cpus..].fill(false);
         new
     }
 
     /// Create a Cpumask that is the AND of the current Cpumask and another.
     pub fn and(&self, other: &Cpumask) -> Cpumask {
         let mut new = self.clone();
-        new.mask &= other.mask.clone();
+        new &= other;
         new
     }
 
     /// Create a Cpumask that is the OR of the current Cpumask and another.
     pub fn or(&self, other: &Cpumask) -> Cpumask {
         let mut new = self.clone();
-        new.mask |= other.mask.clone();
+        new |= other;
         new
     }
 
     /// Create a Cpumask that is the XOR of the current Cpumask and another.
     pub fn xor(&self, other: &Cpumask) -> Cpumask {
         let mut new = self.clone();
-        new.mask ^= other.mask.clone();
+        new ^= other;
         new
     }
 
+    /// Create a Cpumask with the CPUs of the current Cpumask which aren't
+    /// set in another.
+    pub fn andnot(&self, other: &Cpumask) -> Cpumask {
+        let mut new = self.clone();
+        new.andnot_assign(other);
+        new
+    }
+
+    /// Clear the CPUs which are set in `other`.
+    pub fn andnot_assign(&mut self, other: &Cpumask) {
+        let len = self.mask.len().min(other.mask.len());
+        for cpu in other.mask[..len].iter_ones() {
+            self.mask.set(cpu, false);
+        }
+    }
+
     /// Iterate over each element of a Cpumask, and return the indices with bits
     /// set.
     ///
@@ -276,6 +414,65 @@ impl Cpumask {
         }
     }
 
+    /// Iterate over the ranges of consecutive CPUs set in the Cpumask.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use scx_utils::Cpumask;
+    /// let mask = Cpumask::from_cpulist("0-3,8,10-11").unwrap();
+    /// let ranges: Vec<_> = mask.iter_ranges().collect();
+    /// assert_eq!(ranges, vec![0..=3, 8..=8, 10..=11]);
+    /// ```
+    pub fn iter_ranges(&self) -> CpumaskRangeIterator<'_> {
+        CpumaskRangeIterator {
+            mask: self,
+            index: 0,
+        }
+    }
+
+    /// Format the Cpumask as a cpulist, e.g. "0-3,8,10-11".
+    pub fn to_cpulist(&self) -> String {
+        self.iter_ranges()
+            .map(|range| match range.start() == range.end() {
+                true => range.start().to_string(),
+                false => format!("{}-{}", range.start(), range.end()),
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Convert the Cpumask to a cpu_set_t for sched_setaffinity(2). Returns
+    /// an error if a CPU doesn't fit in a cpu_set_t.
+    pub fn to_cpu_set(&self) -> Result<libc::cpu_set_t> {
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for cpu in self.iter() {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                bail!(
+                    "CPU {} doesn't fit in cpu_set_t (max {})",
+                    cpu,
+                    libc::CPU_SETSIZE
+                );
+            }
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        Ok(set)
+    }
+
+    /// Build a Cpumask from a cpu_set_t, e.g. as filled by
+    /// sched_getaffinity(2). The Cpumask is widened beyond *NR_CPU_IDS if
+    /// needed to hold all the CPUs.
+    pub fn from_cpu_set(set: &libc::cpu_set_t) -> Cpumask {
+        let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, set) })
+            .collect();
+        let mut mask = Self::with_width(cpus.last().map_or(0, |cpu| cpu + 1));
+        for cpu in cpus {
+            mask.mask.set(cpu, true);
+        }
+        mask
+    }
+
     /// Write out a CPU mask to a raw memory pointer. We normally use this as part of updating
     /// the CPU masks on the BPF side.
     ///
@@ -306,11 +503,17 @@ impl Cpumask {
             .flat_map(|x| [*x as u32, (x >> 32) as u32])
             .collect();
 
-        // Throw out possible stray from u64 -> u32.
-        masks.truncate((*NR_CPU_IDS).div_ceil(32));
-
-        // Print the highest 32bit. Trim digits beyond *NR_CPU_IDS.
-        let width = match (*NR_CPU_IDS).div_ceil(4) % 8 {
+        // Throw out possible stray from u64 -> u32. Masks wider than
+        // *NR_CPU_IDS are printed up to their highest CPU.
+        let nr_cpus = self
+            .mask
+            .last_one()
+            .map_or(0, |cpu| cpu + 1)
+            .max(*NR_CPU_IDS);
+        masks.truncate(nr_cpus.div_ceil(32));
+
+        // Print the highest 32bit. Trim digits beyond nr_cpus.
+        let width = match nr_cpus.div_ceil(4) % 8 {
             0 => 8,
             v => v,
         };
@@ -363,7 +566,7 @@ impl Iterator for CpumaskIterator<'_> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.index < *NR_CPU_IDS {
+        while self.index < self.mask.nr_cpus {
             let index = self.index;
             self.index += 1;
             let bit_val = self.mask.test_cpu(index);
@@ -376,9 +579,61 @@ impl Iterator for CpumaskIterator<'_> {
     }
 }
 
+pub struct CpumaskRangeIterator<'a> {
+    mask: &'a Cpumask,
+    index: usize,
+}
+
+impl Iterator for CpumaskRangeIterator<'_> {
+    type Item = RangeInclusive<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bits = &self.mask.mask[..self.mask.nr_cpus];
+        if self.index >= bits.len() {
+            return None;
+        }
+        let first = self.index + bits[self.index..].first_one()?;
+        let end = bits[first..].first_zero().map_or(bits.len(), |n| first + n);
+        self.index = end;
+        Some(first..=end - 1)
+    }
+}
+
+/// Cpumasks are compared and hashed by the CPUs set in them, regardless of
+/// their width.
+impl PartialEq for Cpumask {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Cpumask {}
+
+impl Hash for Cpumask {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for cpu in self.iter() {
+            cpu.hash(state);
+        }
+    }
+}
+
+impl PartialOrd for Cpumask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cpumask {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+/// Cpumasks are displayed as cpulists, e.g. "0-3,8,10-11". Use `{:x}` for
+/// the hexadecimal form.
 impl fmt::Display for Cpumask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.fmt_with(f, 'x')
+        f.write_str(&self.to_cpulist())
     }
 }
 
@@ -394,20 +649,282 @@ impl fmt::UpperHex for Cpumask {
     }
 }
 
+/// The in-place operations accept Cpumasks of different widths. CPUs beyond
+/// the width of a Cpumask are treated as clear and the result is widened as
+/// needed to hold all its CPUs.
 impl BitAndAssign<&Self> for Cpumask {
     fn bitand_assign(&mut self, rhs: &Self) {
-        self.mask &= &rhs.mask;
+        let len = self.mask.len().min(rhs.mask.len());
+        self.mask[..len] &= &rhs.mask[..len];
+        self.mask[len..].fill(false);
     }
 }
 
 impl BitOrAssign<&Self> for Cpumask {
     fn bitor_assign(&mut self, rhs: &Self) {
-        self.mask |= &rhs.mask;
+        self.grow(rhs.nr_cpus);
+        let len = self.mask.len().min(rhs.mask.len());
+        self.mask[..len] |= &rhs.mask[..len];
     }
 }
 
 impl BitXorAssign<&Self> for Cpumask {
     fn bitxor_assign(&mut self, rhs: &Self) {
-        self.mask ^= &rhs.mask;
+        self.grow(rhs.nr_cpus);
+        let len = self.mask.len().min(rhs.mask.len());
+        self.mask[..len] ^= &rhs.mask[..len];
+    }
+}
+
+/// Fails if the Cpumask has CPUs beyond what a CpuSet can hold.
+impl TryFrom<&Cpumask> for CpuSet {
+    type Error = anyhow::Error;
+
+    fn try_from(mask: &Cpumask) -> Result<Self> {
+        let mut set = CpuSet::new();
+        for cpu in mask.iter() {
+            set.set(cpu)
+                .with_context(|| format!("CPU {} doesn't fit in CpuSet", cpu))?;
+        }
+        Ok(set)
+    }
+}
+
+impl From<&CpuSet> for Cpumask {
+    fn from(set: &CpuSet) -> Self {
+        let cpus: Vec<usize> = (0..CpuSet::count())
+            .filter(|&cpu| set.is_set(cpu).unwrap_or(false))
+            .collect();
+        let mut mask = Cpumask::with_width(cpus.last().map_or(0, |cpu| cpu + 1));
+        for cpu in cpus {
+            mask.mask.set(cpu, true);
+        }
+        mask
+    }
+}
+
+/// Cpumasks are serialized as cpulists. Deserialized Cpumasks are sized to
+/// hold all their CPUs, which may exceed *NR_CPU_IDS if they come from a
+/// larger machine.
+impl Serialize for Cpumask {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_cpulist())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cpumask {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cpulist = String::deserialize(deserializer)?;
+        match cpulist.trim() {
+            "" => Ok(Self::new()),
+            v => Self::from_cpulist(v).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(cpus: &[usize]) -> Cpumask {
+        let mut mask = Cpumask::with_width(*NR_CPU_IDS + 200);
+        for &cpu in cpus {
+            mask.set_cpu(cpu).unwrap();
+        }
+        mask
+    }
+
+    #[test]
+    fn test_display_and_ranges() {
+        let nr = *NR_CPU_IDS;
+        let mask = wide(&[0, 1, 2, 5, nr + 10, nr + 11, nr + 199]);
+        let cpulist = format!("0-2,5,{}-{},{}", nr + 10, nr + 11, nr + 199);
+        assert_eq!(format!("{}", mask), cpulist);
+        assert_eq!(
+            mask.iter_ranges().collect::<Vec<_>>(),
+            vec![0..=2, 5..=5, nr + 10..=nr + 11, nr + 199..=nr + 199]
+        );
+        assert_eq!(Cpumask::from_bitvec(mask.as_raw_bitvec().clone()), mask);
+        assert_eq!(format!("{}", Cpumask::new()), "");
+
+        let mut full = wide(&[]);
+        full.set_all();
+        assert_eq!(full.to_string(), format!("0-{}", nr + 199));
+
+        // Hex output covers CPUs beyond *NR_CPU_IDS.
+        let hex = format!("{:x}", wide(&[nr + 199]));
+        let lead = format!("{:x}", 1 << ((nr + 199) % 4));
+        assert!(hex.starts_with(&lead));
+        assert_eq!(hex.replace(',', "").len(), (nr + 200).div_ceil(4));
+    }
+
+    #[test]
+    fn test_range_ops() {
+        let nr = *NR_CPU_IDS;
+        let mut mask = wide(&[]);
+        mask.set_range(nr - 1..nr + 3).unwrap();
+        mask.clear_range(nr..=nr).unwrap();
+        assert_eq!(
+            mask.iter().collect::<Vec<_>>(),
+            vec![nr - 1, nr + 1, nr + 2]
+        );
+        assert!(mask.set_range(0..nr + 201).is_err());
+        assert!(mask.clear_range(..).is_ok());
+        assert!(mask.is_empty());
+    }
+
+    #[test]
+    fn test_predicates_and_ops() {
+        let nr = *NR_CPU_IDS;
+        let small = Cpumask::from_cpulist("0").unwrap();
+        let big = wide(&[0, nr + 50]);
+
+        assert!(small.is_subset(&big));
+        assert!(!big.is_subset(&small));
+        assert!(small.intersects(&big));
+        assert!(!wide(&[nr + 1]).intersects(&big));
+        assert!(Cpumask::new().is_subset(&small));
+        assert!(!Cpumask::new().intersects(&small));
+
+        // Narrower Cpumasks are widened and CPUs beyond a width are clear.
+        let mut mask = small.clone();
+        mask |= &big;
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![0, nr + 50]);
+        mask ^= &wide(&[0, nr + 100]);
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![nr + 50, nr + 100]);
+        let mut mask = big.clone();
+        mask &= &small;
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![0]);
+
+        assert_eq!(big.and(&small), mask);
+        assert_eq!(big.andnot(&small).iter().collect::<Vec<_>>(), vec![nr + 50]);
+        assert_eq!(small.andnot(&big), Cpumask::new());
+    }
+
+    #[test]
+    fn test_raw_width() {
+        let nr = *NR_CPU_IDS;
+
+        // The padding of raw u64's isn't iterated over.
+        let mask = Cpumask::from_vec(vec![1]);
+        assert_eq!(
+            mask.not().iter().collect::<Vec<_>>(),
+            (1..nr.min(64)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            mask.not().iter_ranges().last(),
+            (nr > 1).then(|| 1..=nr.min(64) - 1)
+        );
+
+        // CPUs set beyond *NR_CPU_IDS are kept.
+        let raw = wide(&[0, nr + 10]).as_raw_slice().to_vec();
+        let mask = Cpumask::from_vec(raw);
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![0, nr + 10]);
+        assert_eq!(mask.not().iter().last(), Some(nr + 9));
+    }
+
+    #[test]
+    fn test_check_width() {
+        let nr = *NR_CPU_IDS;
+
+        // The padding of raw u64's doesn't hold CPUs.
+        let mut mask = Cpumask::from_vec(vec![0]);
+        assert!(mask.set_cpu(nr + 3).is_err());
+        assert!(mask.set_range(..nr + 3).is_err());
+        assert!(mask.set_range(..nr).is_ok());
+        assert_eq!(mask.weight(), nr);
+
+        // Explicitly wide masks do.
+        let mut mask = wide(&[]);
+        assert!(mask.set_cpu(nr + 3).is_ok());
+        assert!(mask.set_range(nr..nr + 5).is_ok());
+        assert!(mask.set_cpu(nr + 200).is_err());
+    }
+
+    #[test]
+    fn test_width() {
+        let nr = *NR_CPU_IDS;
+
+        let mut mask = Cpumask::new();
+        assert_eq!(mask.len(), nr);
+        mask.set_all();
+        assert!(mask.is_full());
+
+        // Cpulists hold CPUs beyond *NR_CPU_IDS.
+        let mut mask = Cpumask::from_cpulist(&format!("0,{}", nr + 7)).unwrap();
+        assert_eq!(mask.len(), nr + 8);
+        assert_eq!(mask.iter().collect::<Vec<_>>(), vec![0, nr + 7]);
+        assert!(mask.test_cpu(nr + 7));
+        assert_eq!(
+            serde_json::to_string(&mask).unwrap(),
+            format!("\"0,{}\"", nr + 7)
+        );
+        assert!(!mask.is_full());
+        mask.set_all();
+        assert!(mask.is_full());
+        assert_eq!(mask.weight(), nr + 8);
+        assert_eq!(
+            serde_json::from_str::<Cpumask>(&serde_json::to_string(&mask).unwrap()).unwrap(),
+            mask
+        );
+
+        // Raw u64's hold *NR_CPU_IDS CPUs, the padding isn't set.
+        let mut mask = Cpumask::from_vec(vec![0]);
+        assert_eq!(mask.len(), nr);
+        mask.set_all();
+        assert!(mask.is_full());
+        assert_eq!(mask.weight(), nr);
+
+        assert_eq!(Cpumask::with_width(nr + 3).len(), nr + 3);
+        assert_eq!(Cpumask::with_width(0).len(), nr);
+    }
+
+    #[test]
+    fn test_cmp_width() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+
+        let hash = |mask: &Cpumask| {
+            let mut hasher = DefaultHasher::new();
+            mask.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // Masks of different widths holding the same CPUs are equal.
+        let mask = Cpumask::from_cpulist("0").unwrap();
+        let wide_mask = wide(&[0]);
+        assert_ne!(mask.len(), wide_mask.len());
+        assert_eq!(mask, wide_mask);
+        assert_eq!(mask.cmp(&wide_mask), Ordering::Equal);
+        assert_eq!(hash(&mask), hash(&wide_mask));
+        assert!(HashSet::from([mask.clone()]).contains(&wide_mask));
+        assert_eq!(Cpumask::new(), wide(&[]));
+
+        let nr = *NR_CPU_IDS;
+        let wide_mask = wide(&[0, nr + 1]);
+        assert_ne!(mask, wide_mask);
+        assert!(mask < wide_mask);
+        assert!(wide(&[1]) > wide_mask);
+    }
+
+    #[test]
+    fn test_cpu_set() {
+        let nr = *NR_CPU_IDS;
+        let mask = wide(&[1, nr + 5]);
+
+        let set = mask.to_cpu_set().unwrap();
+        assert!(unsafe { libc::CPU_ISSET(nr + 5, &set) });
+        assert_eq!(
+            Cpumask::from_cpu_set(&set).iter().collect::<Vec<_>>(),
+            vec![1, nr + 5]
+        );
+
+        let cpuset = CpuSet::try_from(&mask).unwrap();
+        assert!(cpuset.is_set(nr + 5).unwrap());
+        assert_eq!(Cpumask::from(&cpuset).to_cpulist(), mask.to_cpulist());
+
+        let too_wide = Cpumask::from_bitvec(bitvec![u64, Lsb0; 1; CpuSet::count() + 1]);
+        assert!(too_wide.to_cpu_set().is_err());
+        assert!(CpuSet::try_from(&too_wide).is_err());
     }
 }
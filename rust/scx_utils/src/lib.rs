@@ -47,13 +47,19 @@ pub use builder::Builder;
 mod user_exit_info;
 pub use user_exit_info::ScxConsts;
 pub use user_exit_info::ScxExitKind;
+pub use user_exit_info::UeiDump;
+pub use user_exit_info::UeiDumpCpu;
 pub use user_exit_info::UeiDumpPtr;
+pub use user_exit_info::UeiDumpTask;
+pub use user_exit_info::UeiRecord;
+pub use user_exit_info::UeiRecorder;
 pub use user_exit_info::UserExitInfo;
 pub use user_exit_info::SCX_ECODE_ACT_RESTART;
 pub use user_exit_info::SCX_ECODE_RSN_HOTPLUG;
 pub use user_exit_info::UEI_DUMP_PTR_MUTEX;
 
 pub mod build_id;
+pub mod cgroup;
 pub mod compat;
 pub use compat::ROOT_PREFIX;
 
@@ -72,6 +78,13 @@ pub use topology::Topology;
 pub use topology::NR_CPUS_POSSIBLE;
 pub use topology::NR_CPU_IDS;
 
+mod topology_builder;
+pub use topology_builder::TopologyBuilder;
+
+mod topology_watcher;
+pub use topology_watcher::TopologyDiff;
+pub use topology_watcher::TopologyWatcher;
+
 mod energy_model;
 pub use energy_model::EnergyModel;
 pub use energy_model::PerfDomain;
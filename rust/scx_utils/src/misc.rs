@@ -1,7 +1,7 @@
 use anyhow::Result;
 use anyhow::{anyhow, bail};
 use libc;
-use log::{info, warn};
+use log::{debug, info, warn};
 use scx_stats::prelude::*;
 use serde::Deserialize;
 use std::path::Path;
@@ -18,6 +18,7 @@ where
     T: for<'a> Deserialize<'a>,
 {
     let mut retry_cnt: u32 = 0;
+    let mut subscribe = true;
 
     const RETRYABLE_ERRORS: [std::io::ErrorKind; 2] = [
         std::io::ErrorKind::NotFound,
@@ -41,6 +42,39 @@ where
         };
         retry_cnt = 0;
 
+        // Let the server push the samples if it supports subscriptions and
+        // fall back to polling over a new connection otherwise.
+        if subscribe {
+            match client.subscribe::<T>(stats_args.to_owned(), intv, &[]) {
+                Ok(samples) => {
+                    for stats in samples {
+                        match stats {
+                            Ok(v) => output(v)?,
+                            Err(e) => {
+                                info!("Stats subscription ended ({})", e);
+                                sleep(Duration::from_secs(1));
+                                break;
+                            }
+                        }
+                        if should_exit() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => match e.downcast_ref::<StatsErrno>() {
+                    Some(_) => {
+                        debug!("Stats server doesn't support subscriptions ({})", e);
+                        subscribe = false;
+                    }
+                    None => {
+                        info!("Connection to stats_server failed ({})", e);
+                        sleep(Duration::from_secs(1));
+                    }
+                },
+            }
+            continue;
+        }
+
         while !should_exit() {
             let stats = match client.request::<T>("stats", stats_args.to_owned()) {
                 Ok(v) => v,
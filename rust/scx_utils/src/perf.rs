@@ -2,8 +2,20 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+use crate::compat::tracefs_mount;
+use crate::compat::ROOT_PREFIX;
+use crate::misc::read_from_file;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
 use libc::pid_t;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
 use std::os::raw::{c_int, c_ulong};
+use std::path::Path;
 
 /// The `perf_event_open` system call.
 ///
@@ -55,8 +67,575 @@ pub mod ioctls {
         unsafe { libc::ioctl(fd, perf::bindings::ENABLE as libc::Ioctl, arg) }
     }
 
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn disable(fd: c_int, arg: c_uint) -> c_int {
+        unsafe { libc::ioctl(fd, perf::bindings::DISABLE as libc::Ioctl, arg) }
+    }
+
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn reset(fd: c_int, arg: c_uint) -> c_int {
         unsafe { libc::ioctl(fd, perf::bindings::RESET as libc::Ioctl, arg) }
     }
 }
+
+// Defined with macros and thus not in the bindings.
+const PERF_FLAG_PID_CGROUP: c_ulong = 1 << 2;
+const PERF_FLAG_FD_CLOEXEC: c_ulong = 1 << 3;
+
+/// Type and config of a perf event. Usually created from the event's name
+/// with `PerfEventSpec::from_name()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PerfEventSpec {
+    pub name: String,
+    pub type_: u32,
+    pub config: u64,
+    pub config1: u64,
+    pub config2: u64,
+    /// Don't count in the kernel and hypervisor. Required for unprivileged
+    /// users with the default perf_event_paranoid.
+    pub exclude_kernel: bool,
+}
+
+const HW_EVENTS: &[(&str, u32)] = &[
+    ("cycles", bindings::PERF_COUNT_HW_CPU_CYCLES),
+    ("cpu-cycles", bindings::PERF_COUNT_HW_CPU_CYCLES),
+    ("instructions", bindings::PERF_COUNT_HW_INSTRUCTIONS),
+    ("cache-references", bindings::PERF_COUNT_HW_CACHE_REFERENCES),
+    ("cache-misses", bindings::PERF_COUNT_HW_CACHE_MISSES),
+    ("branches", bindings::PERF_COUNT_HW_BRANCH_INSTRUCTIONS),
+    (
+        "branch-instructions",
+        bindings::PERF_COUNT_HW_BRANCH_INSTRUCTIONS,
+    ),
+    ("branch-misses", bindings::PERF_COUNT_HW_BRANCH_MISSES),
+    ("bus-cycles", bindings::PERF_COUNT_HW_BUS_CYCLES),
+    (
+        "stalled-cycles-frontend",
+        bindings::PERF_COUNT_HW_STALLED_CYCLES_FRONTEND,
+    ),
+    (
+        "stalled-cycles-backend",
+        bindings::PERF_COUNT_HW_STALLED_CYCLES_BACKEND,
+    ),
+    ("ref-cycles", bindings::PERF_COUNT_HW_REF_CPU_CYCLES),
+];
+
+const SW_EVENTS: &[(&str, u32)] = &[
+    ("cpu-clock", bindings::PERF_COUNT_SW_CPU_CLOCK),
+    ("task-clock", bindings::PERF_COUNT_SW_TASK_CLOCK),
+    ("page-faults", bindings::PERF_COUNT_SW_PAGE_FAULTS),
+    ("faults", bindings::PERF_COUNT_SW_PAGE_FAULTS),
+    ("context-switches", bindings::PERF_COUNT_SW_CONTEXT_SWITCHES),
+    ("cs", bindings::PERF_COUNT_SW_CONTEXT_SWITCHES),
+    ("cpu-migrations", bindings::PERF_COUNT_SW_CPU_MIGRATIONS),
+    ("migrations", bindings::PERF_COUNT_SW_CPU_MIGRATIONS),
+    ("minor-faults", bindings::PERF_COUNT_SW_PAGE_FAULTS_MIN),
+    ("major-faults", bindings::PERF_COUNT_SW_PAGE_FAULTS_MAJ),
+];
+
+fn parse_u64(val: &str) -> Result<u64> {
+    let parsed = match val.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => val.parse::<u64>(),
+    };
+    parsed.with_context(|| format!("Invalid value {:?}", val))
+}
+
+/// A PMU format term as in /sys/bus/event_source/devices/PMU/format/TERM,
+/// e.g. "config:0-7,32-35". Returns the config index and bit ranges.
+fn parse_pmu_format(format: &str) -> Result<(usize, Vec<(u32, u32)>)> {
+    let (field, ranges) = format
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid PMU format {:?}", format))?;
+    let idx = match field {
+        "config" => 0,
+        "config1" => 1,
+        "config2" => 2,
+        _ => bail!("Unsupported PMU format field {:?}", field),
+    };
+
+    let mut bits = vec![];
+    for range in ranges.split(',') {
+        let (lo, hi) = match range.split_once('-') {
+            Some((lo, hi)) => (lo.parse::<u32>()?, hi.parse::<u32>()?),
+            None => (range.parse::<u32>()?, range.parse::<u32>()?),
+        };
+        if lo > hi || hi > 63 {
+            bail!("Invalid PMU format range {:?}", range);
+        }
+        bits.push((lo, hi));
+    }
+    Ok((idx, bits))
+}
+
+/// Scatter `val` into the bit ranges of `config` starting from the lowest
+/// bits of `val`. The previous value of the bit ranges is replaced.
+fn apply_pmu_format(config: &mut u64, bits: &[(u32, u32)], mut val: u64) -> Result<()> {
+    for &(lo, hi) in bits {
+        let width = hi - lo + 1;
+        let mask = if width == 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        *config &= !(mask << lo);
+        *config |= (val & mask) << lo;
+        val = val.checked_shr(width).unwrap_or(0);
+    }
+    if val != 0 {
+        bail!("Value doesn't fit in the PMU format");
+    }
+    Ok(())
+}
+
+/// Type and configs of the event described by `terms` on the PMU at
+/// `pmu_path`. `terms` are "TERM=VAL" or "TERM" pairs separated by commas.
+/// A term without a value is either an event alias under events/ or a
+/// flag which is set to 1. Aliases are expanded in place and terms are
+/// applied in order, so later terms override earlier ones, e.g.
+/// "mem-loads,ldlat=5" overrides the ldlat of the mem-loads alias.
+fn pmu_event_configs(pmu_path: &Path, terms: &str) -> Result<(u32, [u64; 3])> {
+    let type_ = read_from_file(&pmu_path.join("type"))?;
+    let mut configs = [0u64; 3];
+
+    let mut expanded = vec![];
+    for term in terms.split(',').map(|t| t.trim()) {
+        let alias_path = pmu_path.join("events").join(term);
+        if !term.is_empty() && !term.contains('=') && alias_path.exists() {
+            let alias = std::fs::read_to_string(&alias_path)?;
+            expanded.extend(alias.trim().split(',').map(|t| t.trim().to_string()));
+        } else {
+            expanded.push(term.to_string());
+        }
+    }
+
+    for term in expanded {
+        if term.is_empty() {
+            continue;
+        }
+        let (name, val) = match term.split_once('=') {
+            Some((name, val)) => (name.to_string(), parse_u64(val)?),
+            None => (term.clone(), 1),
+        };
+
+        let format_path = pmu_path.join("format").join(&name);
+        let format = std::fs::read_to_string(&format_path)
+            .with_context(|| format!("Unknown PMU term {:?}", &name))?;
+        let (idx, bits) = parse_pmu_format(&format)?;
+        apply_pmu_format(&mut configs[idx], &bits, val)
+            .with_context(|| format!("Invalid PMU term {:?}", &term))?;
+    }
+    Ok((type_, configs))
+}
+
+impl PerfEventSpec {
+    /// Look up an event by name. Supported are:
+    ///
+    /// - Generic hardware and software events, e.g. "instructions" and
+    ///   "context-switches".
+    /// - PMU events, e.g. "cpu/event=0x3c,umask=0x0/" or
+    ///   "cpu/cache-misses/", configured according to the PMU's formats
+    ///   and event aliases in /sys/bus/event_source/devices/PMU.
+    /// - Raw events, e.g. "r003c".
+    /// - Tracepoints, e.g. "sched:sched_switch".
+    pub fn from_name(name: &str) -> Result<Self> {
+        let mut spec = Self {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        if let Some((_, config)) = HW_EVENTS.iter().find(|(n, _)| *n == name) {
+            spec.type_ = bindings::PERF_TYPE_HARDWARE;
+            spec.config = *config as u64;
+        } else if let Some((_, config)) = SW_EVENTS.iter().find(|(n, _)| *n == name) {
+            spec.type_ = bindings::PERF_TYPE_SOFTWARE;
+            spec.config = *config as u64;
+        } else if let Some((pmu, terms)) =
+            name.strip_suffix('/').and_then(|name| name.split_once('/'))
+        {
+            let pmu_path = format!("{}/sys/bus/event_source/devices/{}", *ROOT_PREFIX, pmu);
+            let (type_, configs) = pmu_event_configs(Path::new(&pmu_path), terms)
+                .with_context(|| format!("Failed to resolve perf event {:?}", name))?;
+            spec.type_ = type_;
+            [spec.config, spec.config1, spec.config2] = configs;
+        } else if let Some((subsys, event)) = name.split_once(':') {
+            let id_path = tracefs_mount()?
+                .join("events")
+                .join(subsys)
+                .join(event)
+                .join("id");
+            spec.type_ = bindings::PERF_TYPE_TRACEPOINT;
+            spec.config = read_from_file(&id_path)
+                .with_context(|| format!("Unknown tracepoint {:?}", name))?;
+        } else if let Some(raw) = name.strip_prefix('r') {
+            spec.type_ = bindings::PERF_TYPE_RAW;
+            spec.config = u64::from_str_radix(raw, 16)
+                .with_context(|| format!("Unknown perf event {:?}", name))?;
+        } else {
+            bail!("Unknown perf event {:?}", name);
+        }
+        Ok(spec)
+    }
+
+    pub fn set_exclude_kernel(mut self, exclude: bool) -> Self {
+        self.exclude_kernel = exclude;
+        self
+    }
+}
+
+/// What a counter counts.
+#[derive(Clone, Copy, Debug)]
+pub enum PerfScope<'a> {
+    /// All tasks on a CPU.
+    Cpu(usize),
+    /// Tasks in a cgroup, given as its path in cgroupfs, on a CPU.
+    Cgroup(&'a Path, usize),
+    /// A task on any CPU.
+    Task(pid_t),
+}
+
+/// A counter reading. The times are in nsecs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCount {
+    pub value: u64,
+    /// Time the counter was enabled.
+    pub time_enabled: u64,
+    /// Time the counter was actually counting. Less than time_enabled if
+    /// the counter was multiplexed with others.
+    pub time_running: u64,
+}
+
+impl PerfCount {
+    /// The value extrapolated to the whole enabled time.
+    pub fn scaled(&self) -> u64 {
+        if self.time_running == 0 {
+            0
+        } else if self.time_running >= self.time_enabled {
+            self.value
+        } else {
+            (self.value as u128 * self.time_enabled as u128 / self.time_running as u128) as u64
+        }
+    }
+
+    /// The counts since `prev`, an earlier reading of the same counter.
+    pub fn delta(&self, prev: &PerfCount) -> PerfCount {
+        PerfCount {
+            value: self.value.saturating_sub(prev.value),
+            time_enabled: self.time_enabled.saturating_sub(prev.time_enabled),
+            time_running: self.time_running.saturating_sub(prev.time_running),
+        }
+    }
+}
+
+fn open_event(
+    spec: &PerfEventSpec,
+    scope: PerfScope,
+    group: Option<&File>,
+    read_format: u64,
+    disabled: bool,
+) -> Result<File> {
+    let mut attrs = bindings::perf_event_attr {
+        size: std::mem::size_of::<bindings::perf_event_attr>() as u32,
+        type_: spec.type_,
+        config: spec.config,
+        read_format,
+        ..Default::default()
+    };
+    attrs.__bindgen_anon_3.config1 = spec.config1;
+    attrs.__bindgen_anon_4.config2 = spec.config2;
+    attrs.set_disabled(disabled.into());
+    attrs.set_exclude_kernel(spec.exclude_kernel.into());
+    attrs.set_exclude_hv(spec.exclude_kernel.into());
+
+    // The cgroup is specified with an fd of its directory which only needs
+    // to stay open during perf_event_open().
+    let cgroup_dir;
+    let (pid, cpu, flags) = match scope {
+        PerfScope::Cpu(cpu) => (-1, cpu as c_int, 0),
+        PerfScope::Cgroup(path, cpu) => {
+            cgroup_dir = File::open(path)
+                .with_context(|| format!("Failed to open cgroup {}", path.display()))?;
+            (cgroup_dir.as_raw_fd(), cpu as c_int, PERF_FLAG_PID_CGROUP)
+        }
+        PerfScope::Task(pid) => (pid, -1, 0),
+    };
+    let group_fd = group.map_or(-1, |f| f.as_raw_fd());
+
+    let fd =
+        unsafe { perf_event_open(&mut attrs, pid, cpu, group_fd, flags | PERF_FLAG_FD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| {
+            format!("Failed to open perf event {:?} for {:?}", &spec.name, scope)
+        });
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn read_u64s(file: &File, nr: usize) -> Result<Vec<u64>> {
+    let mut buf = vec![0u8; nr * 8];
+    (&*file)
+        .read_exact(&mut buf)
+        .context("Failed to read perf counter")?;
+    Ok(buf
+        .chunks_exact(8)
+        .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+        .collect())
+}
+
+fn ioctl(file: &File, op: unsafe fn(c_int, u32) -> c_int, arg: u32) -> Result<()> {
+    if unsafe { op(file.as_raw_fd(), arg) } < 0 {
+        bail!("perf ioctl failed ({})", std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+const READ_FORMAT_TIMES: u64 =
+    (bindings::PERF_FORMAT_TOTAL_TIME_ENABLED | bindings::PERF_FORMAT_TOTAL_TIME_RUNNING) as u64;
+
+/// A counter of a single event. It starts counting when opened and is
+/// closed when dropped.
+///
+///```no_run
+///     use scx_utils::perf::{PerfCounter, PerfEventSpec, PerfScope};
+///     let spec = PerfEventSpec::from_name("cache-misses").unwrap();
+///     let counter = PerfCounter::open(&spec, PerfScope::Cpu(0)).unwrap();
+///     let before = counter.read().unwrap();
+///     // ...
+///     let misses = counter.read().unwrap().delta(&before).scaled();
+///```
+#[derive(Debug)]
+pub struct PerfCounter {
+    spec: PerfEventSpec,
+    file: File,
+}
+
+impl PerfCounter {
+    pub fn open(spec: &PerfEventSpec, scope: PerfScope) -> Result<Self> {
+        Ok(Self {
+            spec: spec.clone(),
+            file: open_event(spec, scope, None, READ_FORMAT_TIMES, false)?,
+        })
+    }
+
+    pub fn spec(&self) -> &PerfEventSpec {
+        &self.spec
+    }
+
+    pub fn read(&self) -> Result<PerfCount> {
+        let vals = read_u64s(&self.file, 3)?;
+        Ok(PerfCount {
+            value: vals[0],
+            time_enabled: vals[1],
+            time_running: vals[2],
+        })
+    }
+
+    pub fn enable(&self) -> Result<()> {
+        ioctl(&self.file, ioctls::enable, 0)
+    }
+
+    pub fn disable(&self) -> Result<()> {
+        ioctl(&self.file, ioctls::disable, 0)
+    }
+
+    /// Zero the value. The times keep running.
+    pub fn reset(&self) -> Result<()> {
+        ioctl(&self.file, ioctls::reset, 0)
+    }
+}
+
+/// Counters which are scheduled onto the PMU together and read at once,
+/// so that ratios such as IPC are computed from the same intervals. The
+/// first event leads the group. It starts counting when opened and is
+/// closed when dropped.
+///
+///```no_run
+///     use scx_utils::perf::{PerfEventSpec, PerfGroup, PerfScope};
+///     let specs = ["cycles", "instructions"].map(|n| PerfEventSpec::from_name(n).unwrap());
+///     let group = PerfGroup::open(&specs, PerfScope::Cpu(0)).unwrap();
+///     let counts = group.read().unwrap();
+///     let ipc = counts[1].scaled() as f64 / counts[0].scaled().max(1) as f64;
+///```
+#[derive(Debug)]
+pub struct PerfGroup {
+    specs: Vec<PerfEventSpec>,
+    /// Only held to keep the member counters open.
+    #[allow(dead_code)]
+    members: Vec<File>,
+    leader: File,
+}
+
+impl PerfGroup {
+    pub fn open(specs: &[PerfEventSpec], scope: PerfScope) -> Result<Self> {
+        let read_format = READ_FORMAT_TIMES | bindings::PERF_FORMAT_GROUP as u64;
+        let (first, rest) = specs
+            .split_first()
+            .ok_or_else(|| anyhow!("Empty perf group"))?;
+
+        let leader = open_event(first, scope, None, read_format, true)?;
+        let members = rest
+            .iter()
+            .map(|spec| open_event(spec, scope, Some(&leader), read_format, false))
+            .collect::<Result<Vec<_>>>()?;
+
+        let group = Self {
+            specs: specs.to_vec(),
+            members,
+            leader,
+        };
+        group.enable()?;
+        Ok(group)
+    }
+
+    pub fn specs(&self) -> &[PerfEventSpec] {
+        &self.specs
+    }
+
+    /// Read all counters in the order of the specs. They share the times.
+    pub fn read(&self) -> Result<Vec<PerfCount>> {
+        let nr = self.specs.len();
+        let vals = read_u64s(&self.leader, 3 + nr)?;
+        if vals[0] as usize != nr {
+            bail!("perf group read {} counters, expected {}", vals[0], nr);
+        }
+        Ok(vals[3..]
+            .iter()
+            .map(|&value| PerfCount {
+                value,
+                time_enabled: vals[1],
+                time_running: vals[2],
+            })
+            .collect())
+    }
+
+    pub fn enable(&self) -> Result<()> {
+        ioctl(&self.leader, ioctls::enable, bindings::PERF_IOC_FLAG_GROUP)
+    }
+
+    pub fn disable(&self) -> Result<()> {
+        ioctl(&self.leader, ioctls::disable, bindings::PERF_IOC_FLAG_GROUP)
+    }
+
+    /// Zero the values. The times keep running.
+    pub fn reset(&self) -> Result<()> {
+        ioctl(&self.leader, ioctls::reset, bindings::PERF_IOC_FLAG_GROUP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pmu_format() {
+        let (idx, bits) = parse_pmu_format("config1:0-7,32-35\n").unwrap();
+        assert_eq!((idx, bits.clone()), (1, vec![(0, 7), (32, 35)]));
+
+        let mut config = 0;
+        apply_pmu_format(&mut config, &bits, 0xabc).unwrap();
+        assert_eq!(config, 0xa_0000_00bc);
+        assert!(apply_pmu_format(&mut config, &bits, 0x1000).is_err());
+
+        let (_, bits) = parse_pmu_format("config:21").unwrap();
+        let mut config = 0;
+        apply_pmu_format(&mut config, &bits, 1).unwrap();
+        assert_eq!(config, 1 << 21);
+        assert!(parse_pmu_format("config3:0-7").is_err());
+    }
+
+    #[test]
+    fn test_pmu_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let pmu = dir.path();
+        std::fs::create_dir_all(pmu.join("format")).unwrap();
+        std::fs::create_dir_all(pmu.join("events")).unwrap();
+        for (name, val) in [
+            ("type", "4"),
+            ("format/event", "config:0-7,32-35"),
+            ("format/umask", "config:8-15"),
+            ("format/edge", "config:18"),
+            ("format/ldlat", "config1:0-15"),
+            ("events/mem-loads", "event=0x1cd,umask=0x1,ldlat=3"),
+        ] {
+            std::fs::write(pmu.join(name), format!("{}\n", val)).unwrap();
+        }
+
+        let (type_, configs) = pmu_event_configs(pmu, "event=0x3c,umask=0x2,edge").unwrap();
+        assert_eq!(type_, 4);
+        assert_eq!(configs, [0x4023c, 0, 0]);
+
+        let (_, configs) = pmu_event_configs(pmu, "mem-loads").unwrap();
+        assert_eq!(configs, [0x1_0000_01cd, 3, 0]);
+
+        // Explicit terms override the ones of the alias.
+        let (_, configs) = pmu_event_configs(pmu, "mem-loads,ldlat=5").unwrap();
+        assert_eq!(configs, [0x1_0000_01cd, 5, 0]);
+        let (_, configs) = pmu_event_configs(pmu, "umask=0x4,mem-loads,umask=0x2").unwrap();
+        assert_eq!(configs, [0x1_0000_02cd, 3, 0]);
+        let (_, configs) = pmu_event_configs(pmu, "event=0x3c,event=0x2e").unwrap();
+        assert_eq!(configs, [0x2e, 0, 0]);
+
+        assert!(pmu_event_configs(pmu, "foo=1").is_err());
+        assert!(pmu_event_configs(pmu, "umask=0x100").is_err());
+    }
+
+    #[test]
+    fn test_scaling() {
+        let prev = PerfCount {
+            value: 100,
+            time_enabled: 1000,
+            time_running: 1000,
+        };
+        let cur = PerfCount {
+            value: 400,
+            time_enabled: 3000,
+            time_running: 2000,
+        };
+        let delta = cur.delta(&prev);
+        assert_eq!(delta.value, 300);
+        assert_eq!(delta.scaled(), 600);
+        assert_eq!(PerfCount::default().scaled(), 0);
+    }
+
+    #[test]
+    fn test_generic_events() {
+        let spec = PerfEventSpec::from_name("instructions").unwrap();
+        assert_eq!(spec.type_, bindings::PERF_TYPE_HARDWARE);
+        assert_eq!(spec.config, bindings::PERF_COUNT_HW_INSTRUCTIONS as u64);
+
+        let spec = PerfEventSpec::from_name("r01c2").unwrap();
+        assert_eq!((spec.type_, spec.config), (bindings::PERF_TYPE_RAW, 0x1c2));
+        assert!(PerfEventSpec::from_name("no-such-event").is_err());
+    }
+
+    #[test]
+    fn test_task_counter() {
+        let spec = PerfEventSpec::from_name("task-clock")
+            .unwrap()
+            .set_exclude_kernel(true);
+        // perf may be unavailable or restricted by perf_event_paranoid
+        let counter = match PerfCounter::open(&spec, PerfScope::Task(0)) {
+            Ok(counter) => counter,
+            Err(err) => match err
+                .downcast_ref::<std::io::Error>()
+                .and_then(|e| e.raw_os_error())
+            {
+                Some(libc::EACCES | libc::EPERM | libc::ENOSYS | libc::ENOENT) => {
+                    eprintln!("Skipping test_task_counter: {:#}", err);
+                    return;
+                }
+                _ => panic!("{:#}", err),
+            },
+        };
+        let prev = counter.read().unwrap();
+        let mut sum = 0u64;
+        for i in 0..1_000_000u64 {
+            sum = std::hint::black_box(sum.wrapping_add(i));
+        }
+        let delta = counter.read().unwrap().delta(&prev);
+        assert!(delta.value > 0);
+        assert!(delta.time_enabled > 0);
+        assert!(delta.scaled() >= delta.value);
+    }
+}
@@ -11,6 +11,264 @@
 //! and
 //! [ravg_impl.bpf.h](https://github.com/sched-ext/scx/blob/main/scheds/include/common/ravg_impl.bpf.h)
 //! for details.
+//!
+//! A scheduler's generated struct ravg_data can implement RavgData with
+//! impl_ravg_data!() to read and update it with the same integer math as
+//! the BPF side:
+//!
+//!```ignore
+//!     use scx_utils::ravg::RavgData;
+//!     scx_utils::impl_ravg_data!(bpf_intf::ravg_data);
+//!
+//!     let load = task_ctx.dcyc_rd.ravg_read(now, half_life);
+//!```
+
+/// Input values are clamped to RAVG_VAL_BITS bits.
+pub const RAVG_VAL_BITS: u32 = 44;
+/// Fixed point fraction bits, 1 << RAVG_FRAC_BITS is 1.0.
+pub const RAVG_FRAC_BITS: u32 = 20;
+
+/// Pre-computed decayed full-period values as in ravg_impl.bpf.h.
+/// `[n] = [n - 1] + ravg_decay(1 << RAVG_FRAC_BITS, n + 1)`
+const RAVG_FULL_SUM: [u64; 20] = [
+    524288, 786432, 917504, 983040, 1015808, 1032192, 1040384, 1044480, 1046528, 1047552, 1048064,
+    1048320, 1048448, 1048512, 1048544, 1048560, 1048568, 1048572, 1048574,
+    1048575,
+    // the same from here on
+];
+
+/// Add saturating at u64::MAX. Equivalent to C `ravg_add()`.
+pub fn ravg_add(sum: u64, addend: u64) -> u64 {
+    sum.saturating_add(addend)
+}
+
+/// Halve `v` `shift` times. Equivalent to C `ravg_decay()`.
+pub fn ravg_decay(v: u64, shift: u32) -> u64 {
+    v.checked_shr(shift).unwrap_or(0)
+}
+
+/// `dur` as a fixed point fraction of `half_life`, rounded up and capped
+/// at 1.0. Equivalent to C `ravg_normalize_dur()`.
+pub fn ravg_normalize_dur(dur: u32, half_life: u32) -> u32 {
+    if dur < half_life {
+        ((dur as u64) << RAVG_FRAC_BITS).div_ceil(half_life as u64) as u32
+    } else {
+        1 << RAVG_FRAC_BITS
+    }
+}
+
+/// `(a * b) >> rshift` without overflowing the interim product as long as
+/// the result fits in u64. Equivalent to C `u64_x_u32_rshift()`.
+pub fn u64_x_u32_rshift(a: u64, b: u32, rshift: u32) -> u64 {
+    let mask32 = u32::MAX as u64;
+    let mut al = a & mask32;
+    let mut ah = (a & (mask32 << 32)) >> 32;
+
+    al *= b as u64;
+    ah *= b as u64;
+
+    al = al.checked_shr(rshift).unwrap_or(0);
+    if rshift <= 32 {
+        ah <<= 32 - rshift;
+    } else {
+        ah = ah.checked_shr(rshift - 32).unwrap_or(0);
+    }
+    al.wrapping_add(ah)
+}
+
+/// Rust copy of C struct ravg_data. The methods are equivalent to the ones
+/// in ravg_impl.bpf.h, including the wrapping and truncation of the u64
+/// and u32 arithmetic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ravg {
+    /// Current value.
+    pub val: u64,
+    /// Timestamp of `val`.
+    pub val_at: u64,
+    /// Running average as of the latest completed period.
+    pub old: u64,
+    /// Accumulated value of the current period.
+    pub cur: u64,
+}
+
+impl Ravg {
+    /// The value is changing to `new_val` at `now`. Accumulate
+    /// accordingly. Equivalent to C `ravg_accumulate()`.
+    pub fn accumulate(&mut self, new_val: u64, now: u64, half_life: u32) {
+        let now = now.max(self.val_at);
+        let hl = half_life as u64;
+
+        let cur_seq = (now / hl) as u32;
+        let val_seq = (self.val_at / hl) as u32;
+        let seq_delta = cur_seq.wrapping_sub(val_seq);
+
+        // Decay ->old and fold ->cur into it.
+        if seq_delta > 0 {
+            self.old = ravg_decay(self.old, seq_delta);
+            self.old = ravg_add(self.old, ravg_decay(self.cur, seq_delta));
+            self.cur = 0;
+        }
+
+        if self.val != 0 {
+            if seq_delta > 0 {
+                // Fold the oldest period which may be partial.
+                let dur = ravg_normalize_dur((hl - self.val_at % hl) as u32, half_life);
+                self.old = ravg_add(
+                    self.old,
+                    self.val.wrapping_mul(ravg_decay(dur as u64, seq_delta)),
+                );
+
+                // Fold the full periods in the middle.
+                if seq_delta > 1 {
+                    let idx = ((seq_delta - 2) as usize).min(RAVG_FULL_SUM.len() - 1);
+                    self.old = ravg_add(self.old, self.val.wrapping_mul(RAVG_FULL_SUM[idx]));
+                }
+
+                // Accumulate the current period duration into ->cur.
+                let dur = ravg_normalize_dur((now % hl) as u32, half_life);
+                self.cur = self.cur.wrapping_add(self.val.wrapping_mul(dur as u64));
+            } else {
+                let dur = ravg_normalize_dur((now - self.val_at) as u32, half_life);
+                self.cur = self.cur.wrapping_add(self.val.wrapping_mul(dur as u64));
+            }
+        }
+
+        self.val = new_val.min((1 << RAVG_VAL_BITS) - 1);
+        self.val_at = now;
+    }
+
+    /// Transfer `xfer`'s contribution into or out of `self`. Equivalent to
+    /// C `ravg_transfer()`.
+    pub fn transfer(
+        &mut self,
+        new_val: u64,
+        xfer: &mut Ravg,
+        xfer_new_val: u64,
+        half_life: u32,
+        is_xfer_in: bool,
+    ) {
+        // Synchronize self and xfer.
+        let delta = self.val_at.wrapping_sub(xfer.val_at) as i64;
+        if delta < 0 {
+            self.accumulate(new_val, xfer.val_at, half_life);
+        } else if delta > 0 {
+            xfer.accumulate(xfer_new_val, self.val_at, half_life);
+        }
+
+        if is_xfer_in {
+            self.old = self.old.wrapping_add(xfer.old);
+            self.cur = self.cur.wrapping_add(xfer.cur);
+        } else {
+            self.old = self.old.saturating_sub(xfer.old);
+            self.cur = self.cur.saturating_sub(xfer.cur);
+        }
+    }
+
+    /// Multiply the tracked values by `mult` and shift right by `rshift`.
+    /// Equivalent to C `ravg_scale()`.
+    pub fn scale(&mut self, mult: u32, rshift: u32) {
+        self.val = u64_x_u32_rshift(self.val, mult, rshift);
+        self.old = u64_x_u32_rshift(self.old, mult, rshift);
+        self.cur = u64_x_u32_rshift(self.cur, mult, rshift);
+    }
+
+    /// Read the running average as of `now` as a fixed point number with
+    /// RAVG_FRAC_BITS fraction bits. Equivalent to C `ravg_read()`.
+    pub fn read(&self, now: u64, half_life: u32) -> u64 {
+        let now = now.max(self.val_at);
+        let elapsed = (now % half_life as u64) as u32;
+
+        let mut trd = *self;
+        trd.accumulate(0, now, half_life);
+
+        if elapsed != 0 {
+            let progress = ravg_normalize_dur(elapsed, half_life);
+            let old = u64_x_u32_rshift(
+                trd.old,
+                (1 << RAVG_FRAC_BITS) - progress / 2,
+                RAVG_FRAC_BITS,
+            );
+            old.wrapping_add(trd.cur / 2)
+        } else {
+            trd.old
+        }
+    }
+}
+
+/// Access to the fields of a struct ravg_data type. Implement with
+/// impl_ravg_data!() for bindgen and libbpf_cargo generated types.
+pub trait RavgData {
+    fn ravg(&self) -> Ravg;
+    fn set_ravg(&mut self, ravg: &Ravg);
+
+    /// See `Ravg::read()`.
+    fn ravg_read(&self, now: u64, half_life: u32) -> u64 {
+        self.ravg().read(now, half_life)
+    }
+
+    /// The running average in the input value's unit. This is the f64
+    /// approximation `ravg_read()` computes.
+    fn ravg_read_f64(&self, now: u64, half_life: u32) -> f64 {
+        let rd = self.ravg();
+        ravg_read(
+            rd.val,
+            rd.val_at,
+            rd.old,
+            rd.cur,
+            now,
+            half_life,
+            RAVG_FRAC_BITS,
+        )
+    }
+
+    /// See `Ravg::accumulate()`.
+    fn ravg_accumulate(&mut self, new_val: u64, now: u64, half_life: u32) {
+        let mut rd = self.ravg();
+        rd.accumulate(new_val, now, half_life);
+        self.set_ravg(&rd);
+    }
+
+    /// See `Ravg::scale()`.
+    fn ravg_scale(&mut self, mult: u32, rshift: u32) {
+        let mut rd = self.ravg();
+        rd.scale(mult, rshift);
+        self.set_ravg(&rd);
+    }
+}
+
+impl RavgData for Ravg {
+    fn ravg(&self) -> Ravg {
+        *self
+    }
+
+    fn set_ravg(&mut self, ravg: &Ravg) {
+        *self = *ravg;
+    }
+}
+
+/// Implement RavgData for a generated struct ravg_data type.
+#[macro_export]
+macro_rules! impl_ravg_data {
+    ($ty:ty) => {
+        impl $crate::ravg::RavgData for $ty {
+            fn ravg(&self) -> $crate::ravg::Ravg {
+                $crate::ravg::Ravg {
+                    val: self.val,
+                    val_at: self.val_at,
+                    old: self.old,
+                    cur: self.cur,
+                }
+            }
+
+            fn set_ravg(&mut self, ravg: &$crate::ravg::Ravg) {
+                self.val = ravg.val;
+                self.val_at = ravg.val_at;
+                self.old = ravg.old;
+                self.cur = ravg.cur;
+            }
+        }
+    };
+}
 
 /// Read the current running average
 ///
@@ -18,10 +276,9 @@
 /// `@val_at`, `@old`, `@cur`) given `@half_life` and `@frac_bits`. This is
 /// equivalent to C `ravg_read()`.
 ///
-/// There currently is no way to make `bindgen` and `libbpf_cargo` generated
-/// code to use a pre-existing type, so this function takes each field of
-/// struct ravg_data as a separate argument. This works but is ugly and
-/// error-prone. Something to improve in the future.
+/// This function takes each field of struct ravg_data as a separate
+/// argument. Prefer `RavgData::ravg_read_f64()` on types implemented with
+/// impl_ravg_data!().
 pub fn ravg_read(
     val: u64,
     val_at: u64,
@@ -100,3 +357,263 @@ pub fn ravg_read(
     //
     old * (1.0 - normalized_dur(now % half_life) / 2.0) + cur / 2.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Mimics a bindgen generated struct ravg_data.
+    #[derive(Default)]
+    #[allow(non_camel_case_types)]
+    struct ravg_data {
+        val: u64,
+        val_at: u64,
+        old: u64,
+        cur: u64,
+    }
+    impl_ravg_data!(ravg_data);
+
+    const ONE: u64 = 1 << RAVG_FRAC_BITS;
+
+    /// Accumulate `steps` of (value, duration) pairs from `start`.
+    fn run(steps: &[(u64, u64)], start: u64, half_life: u32) -> Ravg {
+        let mut rd = Ravg::default();
+        let mut now = start;
+        for &(val, dur) in steps {
+            rd.accumulate(val, now, half_life);
+            now += dur;
+        }
+        rd
+    }
+
+    #[test]
+    fn test_full_sum() {
+        let mut sum = 0;
+        for (i, &v) in RAVG_FULL_SUM.iter().enumerate() {
+            sum += ravg_decay(ONE, i as u32 + 1);
+            assert_eq!(v, sum);
+        }
+    }
+
+    #[test]
+    fn test_ravg_data_macro() {
+        let mut rd = ravg_data::default();
+        rd.ravg_accumulate(100, 1000, 100);
+        rd.ravg_accumulate(0, 1150, 100);
+        assert_eq!((rd.val, rd.val_at), (0, 1150));
+        assert_eq!(rd.ravg(), run(&[(100, 150), (0, 0)], 1000, 100));
+        assert_eq!(rd.ravg_read(1150, 100), rd.ravg().read(1150, 100));
+    }
+
+    /// Results of ravg_impl.bpf.h compiled natively for the same inputs.
+    #[test]
+    fn test_c_vectors() {
+        #[rustfmt::skip]
+        let vectors: [(u32, &[(u64, u64)], u64, u64, Ravg); 5] = [
+            (2200848, &[(33382118705667, 1099513294170), (7826130849894, 1099513334117)],
+             1099513386147, 264420930376111477,
+             Ravg { val: 7826130849894, val_at: 1099513334117, old: 0, cur: 334832076983350695 }),
+            (2885023, &[(4674395398691, 1099514506738)],
+             1099693181838, 4901454406203995926,
+             Ravg { val: 4674395398691, val_at: 1099514506738, old: 0, cur: 0 }),
+            // The second value is clamped to RAVG_VAL_BITS.
+            (15314677, &[(3694258362908, 1099512830567), (30296280746102, 1099512830637)],
+             1099513653003, 495290845447344972,
+             Ravg { val: 17592186044415, val_at: 1099512830637, old: 0, cur: 18471291814540 }),
+            // Non-zero old and cur are decayed and folded across several
+            // periods.
+            (1000000, &[(100, 500000), (200, 1700000), (300, 1900000), (50, 4300000),
+                        (0, 9100000), (700, 9200000)],
+             9600000, 192329915,
+             Ravg { val: 700, val_at: 9200000, old: 61296610, cur: 5242900 }),
+            // The gap exceeds the precomputed full period sums.
+            (1000000, &[(1024, 250000), (512, 1250000), (2048, 1500000), (0, 80000000)],
+             80500000, 1610611200,
+             Ravg { val: 0, val_at: 80000000, old: 2147481600, cur: 0 }),
+        ];
+
+        for (half_life, steps, now, read, expected) in vectors {
+            let mut rd = Ravg::default();
+            for &(val, at) in steps {
+                rd.accumulate(val, at, half_life);
+            }
+            assert_eq!(rd, expected);
+            assert_eq!(rd.read(now, half_life), read);
+        }
+
+        let mut rd = Ravg {
+            val: 7826130849894,
+            val_at: 0,
+            old: 0,
+            cur: 334832076983350695,
+        };
+        rd.scale(744604791, 18);
+        assert_eq!(
+            (rd.val, rd.old, rd.cur),
+            (22229669669433495, 0, 10287099841687786446)
+        );
+
+        // Transfer out of a base ahead of xfer, xfer is synchronized.
+        let hl = 1000000;
+        let (mut base, mut xfer) = (Ravg::default(), Ravg::default());
+        base.accumulate(300, 100000, hl);
+        base.accumulate(500, 2600000, hl);
+        xfer.accumulate(200, 1400000, hl);
+        xfer.accumulate(100, 2200000, hl);
+        base.transfer(600, &mut xfer, 150, hl, false);
+        assert_eq!(
+            base,
+            Ravg {
+                val: 500,
+                val_at: 2600000,
+                old: 165150500,
+                cur: 104857500
+            }
+        );
+        assert_eq!(
+            xfer,
+            Ravg {
+                val: 150,
+                val_at: 2600000,
+                old: 62914600,
+                cur: 83886300
+            }
+        );
+        assert_eq!(base.read(3000000, hl), 239861500);
+
+        // Transfer into a base behind xfer, base is synchronized.
+        let (mut base, mut xfer) = (Ravg::default(), Ravg::default());
+        base.accumulate(400, 300000, hl);
+        xfer.accumulate(250, 1100000, hl);
+        xfer.accumulate(350, 4700000, hl);
+        base.transfer(450, &mut xfer, 300, hl, true);
+        assert_eq!(
+            base,
+            Ravg {
+                val: 450,
+                val_at: 4700000,
+                old: 611450600,
+                cur: 477102600
+            }
+        );
+        assert_eq!(
+            xfer,
+            Ravg {
+                val: 350,
+                val_at: 4700000,
+                old: 226099000,
+                cur: 183501000
+            }
+        );
+        assert_eq!(base.read(5000000, hl), 615055300);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_u64_x_u32_rshift(a: u64, b: u32, rshift in 0u32..=64) {
+            let exact = (a as u128 * b as u128) >> rshift;
+            prop_assume!(exact <= u64::MAX as u128);
+            // The halves are shifted separately, which can lose a carry if
+            // the low half of the product is shifted out.
+            let res = u64_x_u32_rshift(a, b, rshift) as u128;
+            if rshift <= 32 {
+                prop_assert_eq!(res, exact);
+            } else {
+                prop_assert!(res == exact || res + 1 == exact);
+            }
+        }
+
+        #[test]
+        fn prop_normalize_dur(dur: u32, half_life in 1u32..) {
+            let norm = ravg_normalize_dur(dur, half_life);
+            prop_assert!(norm as u64 <= ONE);
+            if dur < half_life {
+                // Rounded up fraction of the half-life.
+                let exact = ((dur as u128) << RAVG_FRAC_BITS) as f64 / half_life as f64;
+                prop_assert!(norm as f64 >= exact && (norm as f64) < exact + 1.0);
+            }
+        }
+
+        /// Without input, the average halves every half-life and reads
+        /// the decayed old and cur at period boundaries.
+        #[test]
+        fn prop_decay(
+            steps in prop::collection::vec((0u64..1 << 32, 0u64..1 << 24), 1..16),
+            half_life in 1000u32..1 << 24,
+            periods in 1u64..80,
+        ) {
+            let mut rd = run(&steps, 1 << 40, half_life);
+            rd.accumulate(0, rd.val_at, half_life);
+            let hl = half_life as u64;
+            let now = (rd.val_at / hl + periods) * hl;
+
+            let expected = ravg_add(
+                ravg_decay(rd.old, periods as u32),
+                ravg_decay(rd.cur, periods as u32),
+            );
+            prop_assert_eq!(rd.read(now, half_life), expected);
+
+            let mut later = rd;
+            later.accumulate(0, now, half_life);
+            prop_assert_eq!(later.old, expected);
+            prop_assert_eq!(later.cur, 0);
+        }
+
+        /// A constant input converges to the input.
+        #[test]
+        fn prop_steady_state(val in 1u64..1 << 32, half_life in 1000u32..1 << 24, phase: u32) {
+            let hl = half_life as u64;
+            let start = (1 << 40) + phase as u64 % hl;
+            let mut rd = Ravg::default();
+            rd.accumulate(val, start, half_life);
+
+            let now = start + 40 * hl + phase as u64 % hl;
+            let avg = rd.read(now, half_life) as f64 / ONE as f64;
+            prop_assert!((avg - val as f64).abs() <= val as f64 / 1000.0);
+        }
+
+        /// The integer math matches the f64 approximation within rounding.
+        #[test]
+        fn prop_read_matches_f64(
+            steps in prop::collection::vec((0u64..1 << 32, 0u64..1 << 26), 1..16),
+            half_life in 1000u32..1 << 24,
+            tail in 0u64..1 << 26,
+        ) {
+            let rd = run(&steps, 1 << 40, half_life);
+            let now = rd.val_at + tail;
+            let fixed = rd.read(now, half_life) as f64 / ONE as f64;
+            let float = rd.ravg_read_f64(now, half_life);
+
+            let max_val = steps.iter().map(|s| s.0).max().unwrap() as f64;
+            prop_assert!(
+                (fixed - float).abs() <= max_val * 1e-4 + 1.0,
+                "fixed {} float {}", fixed, float
+            );
+        }
+
+        /// Transferring in and then out restores the base.
+        #[test]
+        fn prop_transfer(
+            base_steps in prop::collection::vec((0u64..1 << 32, 0u64..1 << 24), 1..8),
+            xfer_steps in prop::collection::vec((0u64..1 << 32, 0u64..1 << 24), 1..8),
+            half_life in 1000u32..1 << 24,
+        ) {
+            let mut base = run(&base_steps, 1 << 40, half_life);
+            let mut xfer = run(&xfer_steps, 1 << 40, half_life);
+
+            let (base_val, xfer_val) = (base.val, xfer.val);
+            base.transfer(base_val, &mut xfer, xfer_val, half_life, true);
+            prop_assert_eq!(base.val_at, xfer.val_at);
+            let synced = {
+                let mut b = base;
+                b.old -= xfer.old;
+                b.cur -= xfer.cur;
+                b
+            };
+
+            base.transfer(base_val, &mut xfer, xfer_val, half_life, false);
+            prop_assert_eq!(base, synced);
+        }
+    }
+}
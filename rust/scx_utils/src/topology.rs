@@ -61,13 +61,26 @@
 //!     let top = Topology::new().unwrap();
 //!```
 //!
+//! A Topology can also be saved and loaded, e.g. to run a scheduler's
+//! topology-dependent logic against the topology of another machine:
+//!
+//!```no_run
+//!     use scx_utils::Topology;
+//!     let top = Topology::new().unwrap();
+//!     std::fs::write("topo.json", serde_json::to_string(&top).unwrap()).unwrap();
+//!     let loaded = Topology::from_file("topo.json").unwrap();
+//!```
+//!
+//! `scx_topology` dumps the host topology in this format.
+//!
 //! Querying Topology
 //! -----------------
 //!
 //! With a created Topology, you can query the topological hierarchy using the
 //! set of accessor functions defined below. All objects in the topological
 //! hierarchy are entirely read-only. If the host topology were to change (due
-//! to e.g. hotplug), a new Topology object should be created.
+//! to e.g. hotplug), a new Topology object should be created. See
+//! `TopologyWatcher` for following the changes.
 
 use crate::compat::ROOT_PREFIX;
 use crate::cpumask::read_cpulist;
@@ -76,9 +89,12 @@ use crate::misc::read_file_usize_vec;
 use crate::misc::read_from_file;
 use crate::Cpumask;
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
 use glob::glob;
 use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
 use sscanf::sscanf;
 use std::collections::BTreeMap;
 use std::path::Path;
@@ -106,13 +122,13 @@ lazy_static::lazy_static! {
     pub static ref NR_CPUS_POSSIBLE: usize = libbpf_rs::num_possible_cpus().unwrap();
 }
 
-#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum CoreType {
     Big { turbo: bool },
     Little,
 }
 
-#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Cpu {
     pub id: usize,
     pub min_freq: usize,
@@ -140,7 +156,7 @@ pub struct Cpu {
     pub cluster_id: isize,
 }
 
-#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Core {
     /// Monotonically increasing unique id
     pub id: usize,
@@ -157,7 +173,7 @@ pub struct Core {
     pub node_id: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Llc {
     /// Monotonically increasing unique id
     pub id: usize,
@@ -171,10 +187,11 @@ pub struct Llc {
     pub node_id: usize,
 
     /// Skip indices to access lower level members easily.
+    #[serde(skip)]
     pub all_cpus: BTreeMap<usize, Arc<Cpu>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: usize,
     pub distance: Vec<usize>,
@@ -183,14 +200,20 @@ pub struct Node {
     pub span: Cpumask,
 
     /// Skip indices to access lower level members easily.
+    #[serde(skip)]
     pub all_cores: BTreeMap<usize, Arc<Core>>,
+    #[serde(skip)]
     pub all_cpus: BTreeMap<usize, Arc<Cpu>>,
 
     #[cfg(feature = "gpu-topology")]
+    #[serde(skip)]
     pub gpus: BTreeMap<GpuIndex, Gpu>,
 }
 
-#[derive(Debug)]
+/// Topology serializes into the hierarchy rooted at the nodes. The skip
+/// indices are rebuilt on deserialization. GPUs aren't included.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "TopologySnapshot")]
 pub struct Topology {
     pub nodes: BTreeMap<usize, Node>,
     /// Cpumask all CPUs in the system.
@@ -199,13 +222,33 @@ pub struct Topology {
     pub smt_enabled: bool,
 
     /// Skip indices to access lower level members easily.
+    #[serde(skip)]
     pub all_llcs: BTreeMap<usize, Arc<Llc>>,
+    #[serde(skip)]
     pub all_cores: BTreeMap<usize, Arc<Core>>,
+    #[serde(skip)]
     pub all_cpus: BTreeMap<usize, Arc<Cpu>>,
 }
 
+#[derive(Deserialize)]
+struct TopologySnapshot {
+    nodes: BTreeMap<usize, Node>,
+    span: Cpumask,
+    smt_enabled: bool,
+}
+
+impl TryFrom<TopologySnapshot> for Topology {
+    type Error = anyhow::Error;
+
+    fn try_from(snapshot: TopologySnapshot) -> Result<Self> {
+        let mut topo = Self::instantiate(snapshot.span, snapshot.nodes)?;
+        topo.smt_enabled = snapshot.smt_enabled;
+        Ok(topo)
+    }
+}
+
 impl Topology {
-    fn instantiate(span: Cpumask, mut nodes: BTreeMap<usize, Node>) -> Result<Self> {
+    pub(crate) fn instantiate(span: Cpumask, mut nodes: BTreeMap<usize, Node>) -> Result<Self> {
         // Build skip indices prefixed with all_ for easy lookups. As Arc
         // objects can only be modified while there's only one reference,
         // skip indices must be built from bottom to top.
@@ -269,7 +312,12 @@ impl Topology {
 
     /// Build a complete host Topology
     pub fn new() -> Result<Topology> {
-        let span = cpus_online()?;
+        Self::with_span(cpus_online()?)
+    }
+
+    /// Build a Topology of the CPUs in `span`, e.g. the online CPUs in the
+    /// scheduler's cpuset. `span` must not contain offline CPUs.
+    pub(crate) fn with_span(span: Cpumask) -> Result<Topology> {
         let mut topo_ctx = TopoCtx::new();
         // If the kernel is compiled with CONFIG_NUMA, then build a topology
         // from the NUMA hierarchy in sysfs. Otherwise, just make a single
@@ -291,6 +339,16 @@ impl Topology {
         Self::instantiate(span, nodes)
     }
 
+    /// Load a Topology saved as JSON, e.g. with `scx_topology`, instead
+    /// of reading the host's.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Topology> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open topology file {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to parse topology file {}", path.display()))
+    }
+
     /// Get a vec of all GPUs on the hosts.
     #[cfg(feature = "gpu-topology")]
     pub fn gpus(&self) -> BTreeMap<GpuIndex, &Gpu> {
@@ -364,7 +422,7 @@ impl TopoCtx {
     }
 }
 
-fn cpus_online() -> Result<Cpumask> {
+pub(crate) fn cpus_online() -> Result<Cpumask> {
     let path = format!("{}/sys/devices/system/cpu/online", *ROOT_PREFIX);
     let online = std::fs::read_to_string(path)?;
     Cpumask::from_cpulist(&online)
@@ -831,3 +889,22 @@ fn create_numa_nodes(
     }
     Ok(nodes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topology_serde() {
+        let topo = Topology::new().unwrap();
+        let json = serde_json::to_string(&topo).unwrap();
+        let loaded: Topology = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.span, topo.span);
+        assert_eq!(loaded.smt_enabled, topo.smt_enabled);
+        assert!(loaded.all_llcs.keys().eq(topo.all_llcs.keys()));
+        assert!(loaded.all_cores.keys().eq(topo.all_cores.keys()));
+        assert!(loaded.all_cpus.values().eq(topo.all_cpus.values()));
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+    }
+}
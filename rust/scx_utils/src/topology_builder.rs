@@ -0,0 +1,502 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! # Synthetic Topology
+//!
+//! TopologyBuilder builds a Topology of a given shape without reading
+//! sysfs, so that topology-dependent logic can be tested against machines
+//! other than the host:
+//!
+//!```
+//!     use scx_utils::TopologyBuilder;
+//!     // 2 sockets with 2 LLCs of 8 SMT2 cores each.
+//!     let topo = TopologyBuilder::new()
+//!         .set_nodes(2)
+//!         .set_llcs_per_node(2)
+//!         .set_cores_per_llc(8)
+//!         .set_smt(2)
+//!         .build()
+//!         .unwrap();
+//!     assert_eq!(topo.all_cpus.len(), 64);
+//!```
+//!
+//! CPUs are numbered like on x86 by default. The first hardware threads of
+//! all cores come first, followed by their SMT siblings. The Cpumasks are
+//! sized to hold all CPUs even if the shape has more CPUs than the host.
+
+use crate::Core;
+use crate::CoreType;
+use crate::Cpu;
+use crate::Cpumask;
+use crate::Llc;
+use crate::Node;
+use crate::Topology;
+use anyhow::bail;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Placement of a core in the synthetic topology.
+struct CoreSpec {
+    node_id: usize,
+    llc_id: usize,
+    /// Index of the core in its node, used as the kernel core id.
+    node_idx: usize,
+    cluster_id: isize,
+    core_type: CoreType,
+    nr_threads: usize,
+}
+
+type MapCpuFn = Box<dyn Fn(&mut Cpu)>;
+
+pub struct TopologyBuilder {
+    nr_nodes: usize,
+    llcs_per_node: usize,
+    cores_per_llc: usize,
+    smt: usize,
+    little_cores_per_llc: usize,
+    turbo_cores_per_llc: usize,
+    cores_per_cluster: Option<usize>,
+    distances: Option<Vec<Vec<usize>>>,
+    capacities: (usize, usize),
+    freqs: (usize, usize),
+    cache_size: usize,
+    siblings_adjacent: bool,
+    offline: Vec<usize>,
+    map_cpu: Option<MapCpuFn>,
+}
+
+impl TopologyBuilder {
+    /// A single node with one LLC of one core without SMT.
+    pub fn new() -> Self {
+        Self {
+            nr_nodes: 1,
+            llcs_per_node: 1,
+            cores_per_llc: 1,
+            smt: 1,
+            little_cores_per_llc: 0,
+            turbo_cores_per_llc: 0,
+            cores_per_cluster: None,
+            distances: None,
+            capacities: (1024, 512),
+            freqs: (0, 0),
+            cache_size: 0,
+            siblings_adjacent: false,
+            offline: vec![],
+            map_cpu: None,
+        }
+    }
+
+    pub fn set_nodes(mut self, nr_nodes: usize) -> Self {
+        self.nr_nodes = nr_nodes;
+        self
+    }
+
+    pub fn set_llcs_per_node(mut self, nr_llcs: usize) -> Self {
+        self.llcs_per_node = nr_llcs;
+        self
+    }
+
+    pub fn set_cores_per_llc(mut self, nr_cores: usize) -> Self {
+        self.cores_per_llc = nr_cores;
+        self
+    }
+
+    /// Number of hardware threads of the big cores. Little cores never
+    /// have SMT.
+    pub fn set_smt(mut self, nr_threads: usize) -> Self {
+        self.smt = nr_threads;
+        self
+    }
+
+    /// Make the last `nr_cores` cores of each LLC little cores, e.g. the
+    /// E-cores of Intel hybrid CPUs.
+    pub fn set_little_cores(mut self, nr_cores: usize) -> Self {
+        self.little_cores_per_llc = nr_cores;
+        self
+    }
+
+    /// Make the first `nr_cores` cores of each LLC turbo boosted big cores.
+    pub fn set_turbo_cores(mut self, nr_cores: usize) -> Self {
+        self.turbo_cores_per_llc = nr_cores;
+        self
+    }
+
+    /// Group every `nr_cores` consecutive cores of an LLC into a cluster
+    /// which shares the L2 cache. Each core is its own cluster by default.
+    pub fn set_cores_per_cluster(mut self, nr_cores: usize) -> Self {
+        self.cores_per_cluster = Some(nr_cores);
+        self
+    }
+
+    /// NUMA distances between the nodes as in
+    /// /sys/devices/system/node/nodeN/distance. By default, the local
+    /// distance is 10 and the remote one 20.
+    pub fn set_node_distances(mut self, distances: Vec<Vec<usize>>) -> Self {
+        self.distances = Some(distances);
+        self
+    }
+
+    /// Capacities of the big and little cores, scaled to 1024. The defaults
+    /// are 1024 and 512.
+    pub fn set_capacities(mut self, big: usize, little: usize) -> Self {
+        self.capacities = (big, little);
+        self
+    }
+
+    /// Minimum and maximum frequencies of all CPUs in kHz. The base
+    /// frequency is the maximum one.
+    pub fn set_freqs(mut self, min_freq: usize, max_freq: usize) -> Self {
+        self.freqs = (min_freq, max_freq);
+        self
+    }
+
+    /// Per-CPU cache size of all levels in bytes.
+    pub fn set_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Number the SMT siblings of a core consecutively, as on e.g. POWER
+    /// and most arm64 systems, instead of the x86 numbering.
+    pub fn set_siblings_adjacent(mut self, adjacent: bool) -> Self {
+        self.siblings_adjacent = adjacent;
+        self
+    }
+
+    /// CPUs which are offline and thus left out of the Topology as on the
+    /// host.
+    pub fn set_offline(mut self, cpus: &[usize]) -> Self {
+        self.offline = cpus.to_vec();
+        self
+    }
+
+    /// Adjust every CPU before it's added, e.g. to vary the frequencies.
+    /// The ID and ancestor ID fields must be left alone.
+    pub fn map_cpus(mut self, map_cpu: impl Fn(&mut Cpu) + 'static) -> Self {
+        self.map_cpu = Some(Box::new(map_cpu));
+        self
+    }
+
+    fn verify(&self) -> Result<()> {
+        if self.nr_nodes == 0 || self.llcs_per_node == 0 || self.cores_per_llc == 0 {
+            bail!("Nodes, LLCs per node and cores per LLC must be at least 1");
+        }
+        if self.smt == 0 {
+            bail!("SMT must be at least 1");
+        }
+        if self.little_cores_per_llc + self.turbo_cores_per_llc > self.cores_per_llc {
+            bail!(
+                "{} little and {} turbo cores don't fit into {} cores per LLC",
+                self.little_cores_per_llc,
+                self.turbo_cores_per_llc,
+                self.cores_per_llc
+            );
+        }
+        if self.cores_per_cluster == Some(0) {
+            bail!("Cores per cluster must be at least 1");
+        }
+        if let Some(distances) = &self.distances {
+            if distances.len() != self.nr_nodes
+                || distances.iter().any(|row| row.len() != self.nr_nodes)
+            {
+                bail!(
+                    "Node distances must be a {}x{} matrix",
+                    self.nr_nodes,
+                    self.nr_nodes
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn core_specs(&self) -> Vec<CoreSpec> {
+        let mut specs = vec![];
+        let mut nr_clusters = 0;
+
+        for node_id in 0..self.nr_nodes {
+            for llc_idx in 0..self.llcs_per_node {
+                let llc_id = node_id * self.llcs_per_node + llc_idx;
+                let first_little = self.cores_per_llc - self.little_cores_per_llc;
+
+                for core_idx in 0..self.cores_per_llc {
+                    let core_type = if core_idx >= first_little {
+                        CoreType::Little
+                    } else {
+                        CoreType::Big {
+                            turbo: core_idx < self.turbo_cores_per_llc,
+                        }
+                    };
+                    let cluster_id = match self.cores_per_cluster {
+                        Some(v) if core_idx % v == 0 => {
+                            nr_clusters += 1;
+                            nr_clusters - 1
+                        }
+                        Some(_) => nr_clusters - 1,
+                        None => specs.len() as isize,
+                    };
+                    let nr_threads = match core_type {
+                        CoreType::Little => 1,
+                        CoreType::Big { .. } => self.smt,
+                    };
+
+                    specs.push(CoreSpec {
+                        node_id,
+                        llc_id,
+                        node_idx: llc_idx * self.cores_per_llc + core_idx,
+                        cluster_id,
+                        core_type,
+                        nr_threads,
+                    });
+                }
+            }
+        }
+        specs
+    }
+
+    /// (core index, CPU ID) of all hardware threads in CPU ID order.
+    fn cpu_ids(&self, specs: &[CoreSpec]) -> Vec<(usize, usize)> {
+        let mut threads = vec![];
+        if self.siblings_adjacent {
+            for (core_idx, spec) in specs.iter().enumerate() {
+                threads.extend((0..spec.nr_threads).map(|_| core_idx));
+            }
+        } else {
+            for thread in 0..self.smt {
+                for (core_idx, spec) in specs.iter().enumerate() {
+                    if thread < spec.nr_threads {
+                        threads.push(core_idx);
+                    }
+                }
+            }
+        }
+        threads
+            .into_iter()
+            .enumerate()
+            .map(|(cpu_id, core_idx)| (core_idx, cpu_id))
+            .collect()
+    }
+
+    pub fn build(&self) -> Result<Topology> {
+        self.verify()?;
+
+        let specs = self.core_specs();
+        let cpu_ids = self.cpu_ids(&specs);
+        let nr_cpus = cpu_ids.len();
+        let new_mask = || Cpumask::with_width(nr_cpus);
+
+        let mut nodes = BTreeMap::new();
+        for node_id in 0..self.nr_nodes {
+            let distance = match &self.distances {
+                Some(v) => v[node_id].clone(),
+                None => (0..self.nr_nodes)
+                    .map(|to| if to == node_id { 10 } else { 20 })
+                    .collect(),
+            };
+            nodes.insert(
+                node_id,
+                Node {
+                    id: node_id,
+                    distance,
+                    llcs: BTreeMap::new(),
+                    span: new_mask(),
+                    all_cores: BTreeMap::new(),
+                    all_cpus: BTreeMap::new(),
+                    #[cfg(feature = "gpu-topology")]
+                    gpus: BTreeMap::new(),
+                },
+            );
+        }
+
+        let mut span = new_mask();
+        for (core_idx, cpu_id) in cpu_ids {
+            if self.offline.contains(&cpu_id) {
+                continue;
+            }
+
+            let spec = &specs[core_idx];
+            let mut cpu = Cpu {
+                id: cpu_id,
+                min_freq: self.freqs.0,
+                max_freq: self.freqs.1,
+                base_freq: self.freqs.1,
+                cpu_capacity: match spec.core_type {
+                    CoreType::Little => self.capacities.1,
+                    CoreType::Big { .. } => self.capacities.0,
+                },
+                smt_level: 0, // Will be initialized at instantiate().
+                pm_qos_resume_latency_us: 0,
+                trans_lat_ns: 0,
+                l2_id: spec.cluster_id as usize,
+                l3_id: spec.llc_id,
+                cache_size: self.cache_size,
+                core_type: spec.core_type.clone(),
+
+                core_id: core_idx,
+                llc_id: spec.llc_id,
+                node_id: spec.node_id,
+                package_id: spec.node_id,
+                cluster_id: spec.cluster_id,
+            };
+            if let Some(map_cpu) = &self.map_cpu {
+                map_cpu(&mut cpu);
+            }
+
+            let node = nodes.get_mut(&spec.node_id).unwrap();
+            let llc = node.llcs.entry(spec.llc_id).or_insert_with(|| {
+                Arc::new(Llc {
+                    id: spec.llc_id,
+                    kernel_id: spec.llc_id,
+                    cores: BTreeMap::new(),
+                    span: new_mask(),
+                    node_id: spec.node_id,
+                    all_cpus: BTreeMap::new(),
+                })
+            });
+            let llc_mut = Arc::get_mut(llc).unwrap();
+
+            let core = llc_mut.cores.entry(core_idx).or_insert_with(|| {
+                Arc::new(Core {
+                    id: core_idx,
+                    kernel_id: spec.node_idx,
+                    cluster_id: spec.cluster_id,
+                    cpus: BTreeMap::new(),
+                    span: new_mask(),
+                    core_type: spec.core_type.clone(),
+                    llc_id: spec.llc_id,
+                    node_id: spec.node_id,
+                })
+            });
+            let core_mut = Arc::get_mut(core).unwrap();
+
+            core_mut.cpus.insert(cpu_id, Arc::new(cpu));
+            core_mut.span.set_cpu(cpu_id)?;
+            llc_mut.span.set_cpu(cpu_id)?;
+            node.span.set_cpu(cpu_id)?;
+            span.set_cpu(cpu_id)?;
+        }
+
+        let mut topo = Topology::instantiate(span, nodes)?;
+        topo.smt_enabled = self.smt > 1 && self.little_cores_per_llc < self.cores_per_llc;
+        Ok(topo)
+    }
+}
+
+impl Default for TopologyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_sockets() {
+        let topo = TopologyBuilder::new()
+            .set_nodes(2)
+            .set_llcs_per_node(2)
+            .set_cores_per_llc(4)
+            .set_smt(2)
+            .set_node_distances(vec![vec![10, 21], vec![21, 10]])
+            .build()
+            .unwrap();
+
+        assert!(topo.smt_enabled);
+        assert_eq!(topo.nodes.len(), 2);
+        assert_eq!(topo.all_llcs.len(), 4);
+        assert_eq!(topo.all_cores.len(), 16);
+        assert_eq!(topo.all_cpus.len(), 32);
+        assert_eq!(topo.nodes[&1].distance, vec![21, 10]);
+
+        // x86 numbering, CPU 0 and 16 are siblings.
+        let core = &topo.all_cores[&0];
+        assert_eq!(core.cpus.keys().copied().collect::<Vec<_>>(), vec![0, 16]);
+        assert_eq!(topo.all_cpus[&16].smt_level, 2);
+        assert_eq!(
+            topo.nodes[&1].span.iter().collect::<Vec<_>>(),
+            (8..16).chain(24..32).collect::<Vec<_>>()
+        );
+        assert_eq!(topo.all_llcs[&3].node_id, 1);
+        assert_eq!(topo.all_llcs[&3].all_cpus.len(), 8);
+    }
+
+    #[test]
+    fn test_hybrid() {
+        // 6 P-cores with SMT2, the first two boosting, and 8 E-cores. Every
+        // 2 cores share a cluster.
+        let topo = TopologyBuilder::new()
+            .set_cores_per_llc(14)
+            .set_smt(2)
+            .set_little_cores(8)
+            .set_turbo_cores(2)
+            .set_cores_per_cluster(2)
+            .set_siblings_adjacent(true)
+            .map_cpus(|cpu| {
+                if cpu.core_type == CoreType::Little {
+                    cpu.max_freq = 3_800_000;
+                }
+            })
+            .build()
+            .unwrap();
+
+        assert!(topo.has_little_cores());
+        assert_eq!(topo.all_cpus.len(), 20);
+        assert_eq!(topo.all_cores[&0].core_type, CoreType::Big { turbo: true });
+        assert_eq!(topo.all_cores[&2].core_type, CoreType::Big { turbo: false });
+        assert_eq!(topo.all_cores[&6].core_type, CoreType::Little);
+        assert_eq!(
+            topo.all_cores[&6].cpus.keys().copied().collect::<Vec<_>>(),
+            vec![12]
+        );
+        assert_eq!(topo.all_cpus[&12].cpu_capacity, 512);
+        assert_eq!(topo.all_cpus[&12].max_freq, 3_800_000);
+        assert_eq!(
+            topo.all_cores[&1].cpus.keys().copied().collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(topo.all_cores[&7].cluster_id, topo.all_cores[&6].cluster_id);
+        assert_ne!(topo.all_cores[&8].cluster_id, topo.all_cores[&6].cluster_id);
+    }
+
+    #[test]
+    fn test_large_and_offline() {
+        let topo = TopologyBuilder::new()
+            .set_nodes(4)
+            .set_llcs_per_node(4)
+            .set_cores_per_llc(8)
+            .set_smt(2)
+            .set_offline(&[255])
+            .build()
+            .unwrap();
+
+        assert_eq!(topo.all_cpus.len(), 255);
+        assert_eq!(topo.span.weight(), 255);
+        assert!(topo.span.test_cpu(254));
+        assert!(!topo.span.test_cpu(255));
+        assert_eq!(topo.all_cores[&127].cpus.len(), 1);
+        assert_eq!(
+            topo.all_cores[&127].span.iter().collect::<Vec<_>>(),
+            vec![127]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(TopologyBuilder::new().set_smt(0).build().is_err());
+        assert!(TopologyBuilder::new()
+            .set_cores_per_llc(2)
+            .set_little_cores(2)
+            .set_turbo_cores(1)
+            .build()
+            .is_err());
+        assert!(TopologyBuilder::new()
+            .set_nodes(2)
+            .set_node_distances(vec![vec![10]])
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+//! # Topology Change Notification
+//!
+//! Schedulers usually handle CPU hotplug by exiting with
+//! `SCX_ECODE_RSN_HOTPLUG` and restarting so that the Topology is rebuilt.
+//! Schedulers which build their domains in userspace can instead follow
+//! the changes with a TopologyWatcher and reconfigure in place:
+//!
+//!```no_run
+//!     use scx_utils::TopologyWatcher;
+//!     use std::time::Duration;
+//!     let mut watcher = TopologyWatcher::new().unwrap();
+//!     loop {
+//!         if let Some(diff) = watcher.wait(Duration::from_secs(1)).unwrap() {
+//!             println!("CPUs added: {} removed: {}", diff.cpus_added, diff.cpus_removed);
+//!             let _topo = watcher.topology();
+//!             // Rebuild the domains from the new topology.
+//!         }
+//!     }
+//!```
+//!
+//! The watcher tracks the online CPUs and, optionally, a cgroup's
+//! cpuset.cpus.effective. CPU online and offline uevents are received over
+//! netlink to react immediately. cpuset changes don't generate events and
+//! are polled for, as are the online CPUs if the uevent socket can't be
+//! opened.
+
+use crate::topology::cpus_online;
+use crate::Cpumask;
+use crate::Topology;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use log::debug;
+use log::warn;
+use std::collections::BTreeMap;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Changes between two Topology objects. Core and LLC IDs are assigned in
+/// enumeration order and may differ between the two, so cores and LLCs
+/// are matched by their kernel IDs. Added IDs refer to the new Topology
+/// and removed ones to the old.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopologyDiff {
+    pub cpus_added: Cpumask,
+    pub cpus_removed: Cpumask,
+    pub cores_added: Vec<usize>,
+    pub cores_removed: Vec<usize>,
+    pub llcs_added: Vec<usize>,
+    pub llcs_removed: Vec<usize>,
+}
+
+/// (node, package, kernel ID) of all cores and their IDs.
+fn core_keys(topo: &Topology) -> BTreeMap<(usize, usize, usize), usize> {
+    topo.all_cores
+        .values()
+        .map(|core| {
+            let package_id = core.cpus.values().next().map_or(0, |cpu| cpu.package_id);
+            ((core.node_id, package_id, core.kernel_id), core.id)
+        })
+        .collect()
+}
+
+/// (node, package, kernel ID) of all LLCs and their IDs.
+fn llc_keys(topo: &Topology) -> BTreeMap<(usize, usize, usize), usize> {
+    topo.all_llcs
+        .values()
+        .map(|llc| {
+            let package_id = llc.all_cpus.values().next().map_or(0, |cpu| cpu.package_id);
+            ((llc.node_id, package_id, llc.kernel_id), llc.id)
+        })
+        .collect()
+}
+
+/// IDs of the entries of `from` whose keys aren't in `to`.
+fn missing_ids<K: Ord>(from: &BTreeMap<K, usize>, to: &BTreeMap<K, usize>) -> Vec<usize> {
+    let mut ids: Vec<usize> = from
+        .iter()
+        .filter(|(key, _)| !to.contains_key(key))
+        .map(|(_, &id)| id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+impl TopologyDiff {
+    pub fn new(old: &Topology, new: &Topology) -> Self {
+        let max_cpu = old.all_cpus.keys().chain(new.all_cpus.keys()).max();
+        let mut cpus_added = Cpumask::with_width(max_cpu.map_or(0, |cpu| cpu + 1));
+        let mut cpus_removed = cpus_added.clone();
+
+        for &cpu in new.all_cpus.keys() {
+            if !old.all_cpus.contains_key(&cpu) {
+                cpus_added.set_cpu(cpu).unwrap();
+            }
+        }
+        for &cpu in old.all_cpus.keys() {
+            if !new.all_cpus.contains_key(&cpu) {
+                cpus_removed.set_cpu(cpu).unwrap();
+            }
+        }
+
+        let (old_cores, new_cores) = (core_keys(old), core_keys(new));
+        let (old_llcs, new_llcs) = (llc_keys(old), llc_keys(new));
+
+        Self {
+            cpus_added,
+            cpus_removed,
+            cores_added: missing_ids(&new_cores, &old_cores),
+            cores_removed: missing_ids(&old_cores, &new_cores),
+            llcs_added: missing_ids(&new_llcs, &old_llcs),
+            llcs_removed: missing_ids(&old_llcs, &new_llcs),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpus_added.is_empty() && self.cpus_removed.is_empty()
+    }
+}
+
+fn open_uevent_socket() -> Result<OwnedFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    if fd < 0 {
+        bail!(
+            "Failed to open uevent socket ({})",
+            std::io::Error::last_os_error()
+        );
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // Group 1 is the kernel's uevent broadcast group.
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = 1;
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        bail!(
+            "Failed to bind uevent socket ({})",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(fd)
+}
+
+/// The online CPUs, restricted to the cpuset if `cpuset_path` is given.
+fn read_span(cpuset_path: Option<&Path>) -> Result<Cpumask> {
+    let mut span = cpus_online()?;
+    if let Some(path) = cpuset_path {
+        let cpulist = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let cpuset = match cpulist.trim() {
+            "" => Cpumask::new(),
+            cpulist => Cpumask::from_cpulist(cpulist)?,
+        };
+        span &= &cpuset;
+    }
+    Ok(span)
+}
+
+pub struct TopologyWatcher {
+    cpuset_path: Option<PathBuf>,
+    poll_intv: Duration,
+    uevent: Option<OwnedFd>,
+    span: Cpumask,
+    topo: Arc<Topology>,
+}
+
+impl TopologyWatcher {
+    /// Watch the online CPUs of the host.
+    pub fn new() -> Result<Self> {
+        Self::create(None)
+    }
+
+    /// Watch the online CPUs in the cpuset of a cgroup. `path` is the
+    /// cgroup's cpuset.cpus.effective file.
+    pub fn with_cpuset<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::create(Some(path.as_ref().to_path_buf()))
+    }
+
+    fn create(cpuset_path: Option<PathBuf>) -> Result<Self> {
+        let uevent = match open_uevent_socket() {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                debug!("Polling for CPU hotplug ({})", &e);
+                None
+            }
+        };
+
+        let span = read_span(cpuset_path.as_deref())?;
+        Ok(Self {
+            cpuset_path,
+            poll_intv: Duration::from_secs(1),
+            uevent,
+            topo: Arc::new(Topology::with_span(span.clone())?),
+            span,
+        })
+    }
+
+    /// How often to check for changes which aren't notified by uevents.
+    /// The default is one second.
+    pub fn set_poll_interval(mut self, intv: Duration) -> Self {
+        self.poll_intv = intv;
+        self
+    }
+
+    /// The current Topology.
+    pub fn topology(&self) -> Arc<Topology> {
+        self.topo.clone()
+    }
+
+    /// Check for changes without blocking. If the CPUs changed, the
+    /// Topology is rebuilt and the changes are returned. Reading the
+    /// Topology races with CPUs going online and offline. If it fails, the
+    /// changes are reported by a later check instead.
+    pub fn check(&mut self) -> Result<Option<TopologyDiff>> {
+        let span = read_span(self.cpuset_path.as_deref())?;
+        if span == self.span {
+            return Ok(None);
+        }
+
+        let topo = match Topology::with_span(span.clone()) {
+            Ok(topo) => Arc::new(topo),
+            Err(e) => {
+                warn!("Failed to read the topology, retrying ({:#})", &e);
+                return Ok(None);
+            }
+        };
+        let diff = TopologyDiff::new(&self.topo, &topo);
+        self.span = span;
+        self.topo = topo;
+        Ok(Some(diff))
+    }
+
+    /// Drain the pending uevents. They're only used for waking up as
+    /// check() compares the CPUs anyway.
+    fn drain_uevents(&self, fd: &OwnedFd) {
+        let mut buf = [0u8; 8192];
+        loop {
+            let ret = unsafe {
+                libc::recv(
+                    fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if ret <= 0 {
+                break;
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the CPUs to change. Returns the changes
+    /// or None on timeout.
+    pub fn wait(&mut self, timeout: Duration) -> Result<Option<TopologyDiff>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(diff) = self.check()? {
+                return Ok(Some(diff));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            let intv = self.poll_intv.min(deadline - now);
+
+            match &self.uevent {
+                Some(fd) => {
+                    let mut pfd = libc::pollfd {
+                        fd: fd.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    let ms = intv.as_millis().clamp(1, i32::MAX as u128) as i32;
+                    if unsafe { libc::poll(&mut pfd, 1, ms) } > 0 {
+                        self.drain_uevents(fd);
+                    }
+                }
+                None => std::thread::sleep(intv),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TopologyBuilder;
+
+    fn topo(offline: &[usize]) -> Topology {
+        // 2 LLCs of 2 SMT2 cores. Core N has CPU N and N + 4.
+        TopologyBuilder::new()
+            .set_llcs_per_node(2)
+            .set_cores_per_llc(2)
+            .set_smt(2)
+            .set_offline(offline)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let full = topo(&[]);
+        assert!(TopologyDiff::new(&full, &topo(&[])).is_empty());
+
+        let diff = TopologyDiff::new(&full, &topo(&[5]));
+        assert_eq!(diff.cpus_removed.iter().collect::<Vec<_>>(), vec![5]);
+        assert!(diff.cpus_added.is_empty());
+        assert!(diff.cores_removed.is_empty());
+
+        let diff = TopologyDiff::new(&full, &topo(&[3, 7]));
+        assert_eq!(diff.cores_removed, vec![3]);
+        assert!(diff.llcs_removed.is_empty());
+
+        let diff = TopologyDiff::new(&topo(&[2, 3, 6, 7]), &full);
+        assert_eq!(diff.cpus_added.iter().collect::<Vec<_>>(), vec![2, 3, 6, 7]);
+        assert_eq!(diff.cores_added, vec![2, 3]);
+        assert_eq!(diff.llcs_added, vec![1]);
+        assert!(diff.cpus_removed.is_empty());
+        assert!(diff.llcs_removed.is_empty());
+    }
+}
@@ -3,12 +3,22 @@
 // This software may be used and distributed according to the terms of the
 // GNU General Public License version 2.
 use crate::bindings;
+use crate::build_id;
 use crate::compat;
 use anyhow::bail;
+use anyhow::Context;
 use anyhow::Result;
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
 use std::ffi::CStr;
 use std::os::raw::c_char;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 pub struct UeiDumpPtr {
     pub ptr: *const c_char,
@@ -29,6 +39,15 @@ lazy_static::lazy_static! {
     compat::read_enum("scx_exit_code", "SCX_ECODE_ACT_RESTART").unwrap_or(0);
 }
 
+lazy_static::lazy_static! {
+    static ref DUMP_CPU_RE: Regex = Regex::new(r"^CPU\s+(\d+)\s*:").unwrap();
+    static ref DUMP_TASK_RE: Regex =
+        Regex::new(r"^ ([* ])(\S) (.*)\[(\d+)\] ([+-]?\d+)ms$").unwrap();
+}
+
+/// The recorder installed with UeiRecorder::install().
+static UEI_RECORDER: Mutex<Option<UeiRecorder>> = Mutex::new(None);
+
 pub enum ScxExitKind {
     None = bindings::scx_exit_kind_SCX_EXIT_NONE as isize,
     Done = bindings::scx_exit_kind_SCX_EXIT_DONE as isize,
@@ -117,7 +136,7 @@ macro_rules! uei_report {
 }
 
 /// Rust counterpart of C struct user_exit_info.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UserExitInfo {
     /// The C enum scx_exit_kind value. Test against ScxExitKind. None-zero
     /// value indicates that the BPF scheduler has exited.
@@ -186,14 +205,78 @@ impl UserExitInfo {
         }
     }
 
+    /// The C enum scx_exit_kind value. Test against ScxExitKind.
+    pub fn kind(&self) -> i32 {
+        self.kind
+    }
+
+    /// Name of the exit kind, e.g. "error_stall".
+    pub fn kind_name(&self) -> &'static str {
+        let names = [
+            (ScxExitKind::None as i32, "none"),
+            (ScxExitKind::Done as i32, "done"),
+            (ScxExitKind::Unreg as i32, "unreg"),
+            (ScxExitKind::UnregBPF as i32, "unreg_bpf"),
+            (ScxExitKind::UnregKern as i32, "unreg_kern"),
+            (ScxExitKind::SysRq as i32, "sysrq"),
+            (ScxExitKind::Error as i32, "error"),
+            (ScxExitKind::ErrorBPF as i32, "error_bpf"),
+            (ScxExitKind::ErrorStall as i32, "error_stall"),
+        ];
+        names
+            .iter()
+            .find(|(kind, _)| *kind == self.kind)
+            .map_or("unknown", |(_, name)| name)
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    pub fn msg(&self) -> Option<&str> {
+        self.msg.as_deref()
+    }
+
+    /// The raw debug dump. See parse_dump() for the parsed one.
+    pub fn dump(&self) -> Option<&str> {
+        self.dump.as_deref()
+    }
+
+    pub fn parse_dump(&self) -> Option<UeiDump> {
+        self.dump.as_deref().map(UeiDump::parse)
+    }
+
+    /// Test whether the BPF scheduler exited due to an error, including
+    /// sysrq-S. report() fails in these cases.
+    pub fn is_error(&self) -> bool {
+        self.kind > ScxExitKind::UnregKern as i32
+    }
+
+    /// Test whether the BPF scheduler exited without an error and with a
+    /// zero exit code. Exits with an exit code, e.g. to restart after CPU
+    /// hotplug, aren't clean.
+    pub fn is_clean(&self) -> bool {
+        !self.is_error() && self.exit_code().unwrap_or(0) == 0
+    }
+
     /// Print out the exit message to stderr if the exit was normal. After
     /// an error exit, it throws an error containing the exit message
     /// instead. If debug dump exists, it's always printed to stderr.
+    ///
+    /// If a UeiRecorder is installed, non-clean exits are also recorded.
     pub fn report(&self) -> Result<()> {
         if self.kind == 0 {
             return Ok(());
         }
 
+        if let Some(recorder) = UEI_RECORDER.lock().unwrap().as_ref() {
+            match recorder.record(self) {
+                Ok(Some(path)) => eprintln!("Exit record saved to {}", path.display()),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to save exit record ({:#})", &e),
+            }
+        }
+
         if let Some(dump) = &self.dump {
             eprintln!("\nDEBUG DUMP");
             eprintln!(
@@ -211,7 +294,7 @@ impl UserExitInfo {
             _ => "<UNKNOWN>".into(),
         };
 
-        if self.kind <= ScxExitKind::UnregKern as i32 {
+        if !self.is_error() {
             eprintln!("{}", why);
             Ok(())
         } else {
@@ -235,3 +318,346 @@ impl UserExitInfo {
         }
     }
 }
+
+/// A task in the debug dump.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UeiDumpTask {
+    pub comm: String,
+    pub pid: i32,
+    /// Whether the task was running on the CPU.
+    pub curr: bool,
+    /// Task state character as in /proc/PID/stat.
+    pub state: char,
+    /// Time since the task became runnable, negative if in the future.
+    pub runnable_ms: i64,
+    /// The whole block including the above line.
+    pub text: String,
+}
+
+/// A CPU and the sched_ext tasks on it in the debug dump.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UeiDumpCpu {
+    pub cpu: usize,
+    /// The block without the tasks.
+    pub text: String,
+    pub tasks: Vec<UeiDumpTask>,
+}
+
+/// Debug dump split into its sections. The sections which aren't parsed
+/// further are kept as text.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UeiDump {
+    /// Exit reason, backtrace and the output of ops.dump().
+    pub header: String,
+    pub cpus: Vec<UeiDumpCpu>,
+    /// Sections after the CPU states, e.g. the event counters.
+    pub trailer: String,
+    /// The dump didn't fit in the dump buffer.
+    pub truncated: bool,
+}
+
+impl UeiDump {
+    pub fn parse(dump: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut lines = dump.lines();
+        let mut in_cpus = false;
+
+        for line in lines.by_ref() {
+            if line == "CPU states" {
+                in_cpus = true;
+                break;
+            }
+            parsed.header += line;
+            parsed.header.push('\n');
+        }
+
+        for line in lines {
+            if line.trim() == "~~~~ TRUNCATED ~~~~" {
+                parsed.truncated = true;
+                continue;
+            }
+            if in_cpus && parsed.cpus.is_empty() && (line.is_empty() || line.starts_with("----")) {
+                continue;
+            }
+
+            if in_cpus {
+                if let Some(caps) = DUMP_CPU_RE.captures(line) {
+                    parsed.cpus.push(UeiDumpCpu {
+                        cpu: caps[1].parse().unwrap_or(0),
+                        text: format!("{}\n", line),
+                        tasks: vec![],
+                    });
+                    continue;
+                }
+            }
+
+            match parsed.cpus.last_mut() {
+                Some(cpu) if in_cpus && (line.is_empty() || line.starts_with(' ')) => {
+                    if let Some(caps) = DUMP_TASK_RE.captures(line) {
+                        cpu.tasks.push(UeiDumpTask {
+                            comm: caps[3].to_string(),
+                            pid: caps[4].parse().unwrap_or(0),
+                            curr: &caps[1] == "*",
+                            state: caps[2].chars().next().unwrap_or(' '),
+                            runnable_ms: caps[5].parse().unwrap_or(0),
+                            text: String::new(),
+                        });
+                    }
+                    let text = match cpu.tasks.last_mut() {
+                        Some(task) => &mut task.text,
+                        None => &mut cpu.text,
+                    };
+                    text.push_str(line);
+                    text.push('\n');
+                }
+                _ => {
+                    in_cpus = false;
+                    parsed.trailer += line;
+                    parsed.trailer.push('\n');
+                }
+            }
+        }
+
+        let trim = |text: &mut String| *text = text.trim_matches('\n').to_string();
+        trim(&mut parsed.header);
+        trim(&mut parsed.trailer);
+        for cpu in parsed.cpus.iter_mut() {
+            trim(&mut cpu.text);
+            for task in cpu.tasks.iter_mut() {
+                trim(&mut task.text);
+            }
+        }
+        parsed
+    }
+}
+
+/// Machine-readable record of a scheduler exit written by UeiRecorder.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UeiRecord {
+    pub sched: String,
+    /// build_id::full_version() of the scheduler.
+    pub version: String,
+    /// Milliseconds since the epoch.
+    pub timestamp_ms: u64,
+    pub kind: i32,
+    pub kind_name: String,
+    pub exit_code: i64,
+    pub reason: Option<String>,
+    pub msg: Option<String>,
+    pub dump: Option<UeiDump>,
+}
+
+/// Writes a JSON UeiRecord for every non-clean exit into a directory. Use
+/// record() directly or install() to record the exits reported with
+/// UserExitInfo::report(), e.g. through uei_report!():
+///
+///```no_run
+///     use scx_utils::UeiRecorder;
+///     UeiRecorder::new("scx_foo", env!("CARGO_PKG_VERSION"), "/var/log/scx").install();
+///```
+#[derive(Clone, Debug)]
+pub struct UeiRecorder {
+    sched: String,
+    version: String,
+    dir: PathBuf,
+}
+
+impl UeiRecorder {
+    /// `semver` is the scheduler's version which is expanded with
+    /// build_id::full_version().
+    pub fn new<P: AsRef<Path>>(sched: &str, semver: &str, dir: P) -> Self {
+        Self {
+            sched: sched.to_string(),
+            version: build_id::full_version(semver),
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Record `uei` unless it's a clean exit and return the path of the
+    /// record. See UserExitInfo::is_clean().
+    pub fn record(&self, uei: &UserExitInfo) -> Result<Option<PathBuf>> {
+        if uei.is_clean() {
+            return Ok(None);
+        }
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let record = UeiRecord {
+            sched: self.sched.clone(),
+            version: self.version.clone(),
+            timestamp_ms,
+            kind: uei.kind,
+            kind_name: uei.kind_name().to_string(),
+            exit_code: uei.exit_code,
+            reason: uei.reason.clone(),
+            msg: uei.msg.clone(),
+            dump: uei.parse_dump(),
+        };
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        // Write and rename so that readers never see partial records.
+        let name = format!(
+            "{}-{}-{}.json",
+            self.sched,
+            timestamp_ms,
+            std::process::id()
+        );
+        let path = self.dir.join(&name);
+        let tmp_path = self.dir.join(format!(".{}.tmp", &name));
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&record)? + "\n")
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to rename to {}", path.display()))?;
+        Ok(Some(path))
+    }
+
+    /// Record the non-clean exits reported with UserExitInfo::report() from
+    /// now on, replacing the previously installed recorder.
+    pub fn install(self) {
+        *UEI_RECORDER.lock().unwrap() = Some(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+kworker/u16:3[1234] triggered exit kind 1026:
+  runnable task stall (stress[2001] failed to run for 30.5s)
+
+Backtrace:
+  scx_watchdog_workfn+0x154/0x1e0
+
+CPU states
+----------
+
+CPU 0   : nr_run=2 flags=0x1 cpu_rel=0 ops_qseq=120 pnt_seq=45
+          curr=stress[2000] class=ext_sched_class
+
+ *R stress[2000] +0ms
+      scx_state/flags=3/0x5 dsq_flags=0x0 ops_state/qseq=0/0
+
+  R stress[2001] -30512ms
+      scx_state/flags=3/0x1 dsq_flags=0x1 ops_state/qseq=2/119
+
+CPU 3   : nr_run=0 flags=0x0 cpu_rel=0 ops_qseq=7 pnt_seq=3
+          curr=swapper/3[0] class=idle_sched_class
+
+Event counters
+--------------
+SCX_EV_SELECT_CPU_FALLBACK:    0
+
+~~~~ TRUNCATED ~~~~
+";
+
+    #[test]
+    fn test_parse_dump() {
+        let dump = UeiDump::parse(DUMP);
+        assert!(dump.header.starts_with("kworker/u16:3[1234] triggered"));
+        assert!(dump.header.ends_with("scx_watchdog_workfn+0x154/0x1e0"));
+        assert_eq!(dump.cpus.len(), 2);
+
+        let cpu0 = &dump.cpus[0];
+        assert_eq!(cpu0.cpu, 0);
+        assert!(cpu0
+            .text
+            .ends_with("curr=stress[2000] class=ext_sched_class"));
+        assert_eq!(cpu0.tasks.len(), 2);
+        assert!(cpu0.tasks[0].curr);
+        assert_eq!(cpu0.tasks[0].state, 'R');
+        assert_eq!(cpu0.tasks[1].comm, "stress");
+        assert_eq!(cpu0.tasks[1].pid, 2001);
+        assert!(!cpu0.tasks[1].curr);
+        assert_eq!(cpu0.tasks[1].runnable_ms, -30512);
+        assert!(cpu0.tasks[1].text.ends_with("ops_state/qseq=2/119"));
+
+        assert_eq!(dump.cpus[1].cpu, 3);
+        assert!(dump.cpus[1].tasks.is_empty());
+        assert!(dump.trailer.starts_with("Event counters"));
+        assert!(dump.truncated);
+
+        let dump = UeiDump::parse("no cpu states\n");
+        assert_eq!(dump.header, "no cpu states");
+        assert!(dump.cpus.is_empty());
+    }
+
+    fn uei(kind: ScxExitKind, exit_code: i64, dump: Option<&str>) -> UserExitInfo {
+        UserExitInfo {
+            kind: kind as i32,
+            exit_code,
+            reason: Some("reason".into()),
+            msg: Some("msg".into()),
+            dump: dump.map(|dump| dump.to_string()),
+        }
+    }
+
+    fn read_records(dir: &Path) -> Vec<UeiRecord> {
+        let mut records = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().unwrap(), "json", "{}", path.display());
+            let json = std::fs::read_to_string(&path).unwrap();
+            records.push(serde_json::from_str::<UeiRecord>(&json).unwrap());
+        }
+        records.sort_by(|a, b| a.sched.cmp(&b.sched));
+        records
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = |sched: &str| UeiRecorder::new(sched, "1.0.0", dir.path());
+
+        // Clean exits aren't recorded.
+        for uei in [
+            uei(ScxExitKind::None, 0, None),
+            uei(ScxExitKind::Unreg, 0, None),
+            uei(ScxExitKind::UnregBPF, 0, None),
+        ] {
+            assert!(uei.is_clean());
+            assert_eq!(recorder("scx_a").record(&uei).unwrap(), None);
+        }
+
+        // Exits with an exit code and error exits are.
+        let hotplug = uei(ScxExitKind::UnregBPF, 0x1_0000_0001, None);
+        assert!(!hotplug.is_clean());
+        let path = recorder("scx_a").record(&hotplug).unwrap().unwrap();
+        assert!(path.starts_with(dir.path()));
+        let stall = uei(ScxExitKind::ErrorStall, 0, Some(DUMP));
+        assert!(recorder("scx_b").record(&stall).unwrap().is_some());
+
+        // Only the renamed records are left.
+        let records = read_records(dir.path());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].sched, "scx_a");
+        assert_eq!(records[0].version, build_id::full_version("1.0.0"));
+        assert_eq!(records[0].kind_name, "unreg_bpf");
+        assert_eq!(records[0].exit_code, 0x1_0000_0001);
+        assert_eq!(records[0].reason.as_deref(), Some("reason"));
+        assert!(records[0].dump.is_none());
+        assert_eq!(records[1].kind, ScxExitKind::ErrorStall as i32);
+        assert_eq!(records[1].msg.as_deref(), Some("msg"));
+        assert_eq!(records[1].dump.as_ref().unwrap().cpus.len(), 2);
+        assert!(records[1].timestamp_ms > 0);
+    }
+
+    #[test]
+    fn test_install() {
+        let dir = tempfile::tempdir().unwrap();
+        UeiRecorder::new("scx_c", "1.0.0", dir.path()).install();
+
+        assert!(uei(ScxExitKind::Unreg, 0, None).report().is_ok());
+        assert!(read_records(dir.path()).is_empty());
+        assert!(uei(ScxExitKind::Error, 0, None).report().is_err());
+        *UEI_RECORDER.lock().unwrap() = None;
+
+        let records = read_records(dir.path());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].kind_name, "error");
+    }
+}
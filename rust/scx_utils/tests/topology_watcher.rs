@@ -0,0 +1,65 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+// SCX_SYSFS_PREFIX is read once per process, so the watcher is tested
+// against a fake sysfs tree in its own test binary.
+
+use scx_utils::TopologyWatcher;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const CPU_ROOT: &str = "sys/devices/system/cpu";
+
+fn add_cpu(root: &Path, cpu: usize) {
+    let top = root.join(format!("{}/cpu{}/topology", CPU_ROOT, cpu));
+    fs::create_dir_all(&top).unwrap();
+    fs::write(top.join("physical_package_id"), "0\n").unwrap();
+    fs::write(top.join("cluster_id"), "0\n").unwrap();
+    fs::write(top.join("core_id"), format!("{}\n", cpu)).unwrap();
+}
+
+fn set_online(root: &Path, cpulist: &str) {
+    fs::write(root.join(CPU_ROOT).join("online"), format!("{}\n", cpulist)).unwrap();
+}
+
+#[test]
+fn test_watcher() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    for cpu in 0..4 {
+        add_cpu(root, cpu);
+    }
+    set_online(root, "0-2");
+    unsafe { std::env::set_var("SCX_SYSFS_PREFIX", root) };
+
+    let mut watcher = TopologyWatcher::new()
+        .unwrap()
+        .set_poll_interval(Duration::from_millis(10));
+    assert_eq!(watcher.topology().all_cpus.len(), 3);
+    assert!(watcher.check().unwrap().is_none());
+
+    // CPU 3 comes online before its topology can be read. The change is
+    // reported once it can.
+    let core_id = root.join(format!("{}/cpu3/topology/core_id", CPU_ROOT));
+    fs::remove_file(&core_id).unwrap();
+    set_online(root, "0-3");
+    assert!(watcher.check().unwrap().is_none());
+    assert!(watcher.wait(Duration::from_millis(50)).unwrap().is_none());
+    assert_eq!(watcher.topology().all_cpus.len(), 3);
+
+    fs::write(&core_id, "3\n").unwrap();
+    let diff = watcher.wait(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(diff.cpus_added.iter().collect::<Vec<_>>(), vec![3]);
+    assert_eq!(diff.cores_added, vec![3]);
+    assert!(diff.cpus_removed.is_empty());
+    assert_eq!(watcher.topology().all_cpus.len(), 4);
+
+    set_online(root, "0-1,3");
+    let diff = watcher.wait(Duration::from_secs(1)).unwrap().unwrap();
+    assert_eq!(diff.cpus_removed.iter().collect::<Vec<_>>(), vec![2]);
+    assert_eq!(diff.cores_removed, vec![2]);
+    assert!(watcher.check().unwrap().is_none());
+}
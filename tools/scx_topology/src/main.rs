@@ -0,0 +1,38 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+
+// This software may be used and distributed according to the terms of the
+// GNU General Public License version 2.
+
+use anyhow::Context;
+use anyhow::Result;
+use clap::Parser;
+use scx_utils::Topology;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Dump the host topology as JSON which can be loaded with
+/// `Topology::from_file()`, e.g. to reproduce a scheduler's CPU ordering
+/// and domain construction on another machine.
+///
+/// SCX_SYSFS_PREFIX can point to a sysfs tree other than the host's.
+#[derive(Debug, Parser)]
+#[command(name = "scx_topology", version)]
+struct Opts {
+    /// File to write the topology to, stdout if not given.
+    output: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+
+    let topo = Topology::new().context("Failed to read the host topology")?;
+    let mut json = serde_json::to_string_pretty(&topo)?;
+    json.push('\n');
+
+    match &opts.output {
+        Some(path) => std::fs::write(path, json)
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => std::io::stdout().write_all(json.as_bytes())?,
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use clap::{Parser, Subcommand};
-use scx_loader::SchedMode;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,12 +16,11 @@ pub struct StartArgs {
     #[arg(
         short,
         long,
-        value_enum,
         default_value = "auto",
         conflicts_with = "args",
-        help = "Mode to start in"
+        help = "Mode to start in, either a built-in or a custom mode"
     )]
-    pub mode: Option<SchedMode>,
+    pub mode: Option<String>,
     #[arg(
         short,
         long,
@@ -41,11 +40,10 @@ pub struct SwitchArgs {
     #[arg(
         short,
         long,
-        value_enum,
         conflicts_with = "args",
-        help = "Mode to switch to"
+        help = "Mode to switch to, either a built-in or a custom mode"
     )]
-    pub mode: Option<SchedMode>,
+    pub mode: Option<String>,
     #[arg(
         short,
         long,
@@ -57,10 +55,54 @@ pub struct SwitchArgs {
     pub args: Option<Vec<String>>,
 }
 
+#[derive(Parser, Debug)]
+pub struct StatsArgs {
+    #[arg(short, long, help = "Keep printing the stats periodically")]
+    pub watch: bool,
+    #[arg(
+        short,
+        long,
+        default_value_t = 1.0,
+        requires = "watch",
+        value_parser = parse_interval,
+        help = "Interval between updates in seconds"
+    )]
+    pub interval: f64,
+    #[arg(
+        short,
+        long,
+        help = "Path of the stats socket [default: /var/run/scx/root/stats]"
+    )]
+    pub path: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct LogArgs {
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 50,
+        help = "Number of lines to print, 0 prints all kept lines"
+    )]
+    pub lines: u32,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     #[command(about = "Get the current scheduler and mode")]
     Get,
+    #[command(about = "Show the state of the current scheduler")]
+    Status,
+    #[command(about = "Print the stats of the running scheduler")]
+    Stats {
+        #[clap(flatten)]
+        args: StatsArgs,
+    },
+    #[command(about = "Print the last output of the schedulers")]
+    Log {
+        #[clap(flatten)]
+        args: LogArgs,
+    },
     #[command(about = "List all supported schedulers")]
     List,
     #[command(about = "Start a scheduler in a mode or with arguments")]
@@ -75,4 +117,17 @@ pub enum Commands {
     },
     #[command(about = "Stop the current scheduler")]
     Stop,
+    #[command(about = "Validate the scx_loader configuration")]
+    Validate,
+    #[command(about = "Reload the scx_loader configuration")]
+    Reload,
+}
+
+/// Parse an interval in seconds, which has to be positive and fit a Duration
+fn parse_interval(arg: &str) -> Result<f64, String> {
+    let interval = arg.parse::<f64>().map_err(|err| err.to_string())?;
+    if interval <= 0.0 || Duration::try_from_secs_f64(interval).is_err() {
+        return Err(format!("{arg} is not a positive number of seconds"));
+    }
+    Ok(interval)
 }
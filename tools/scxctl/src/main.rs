@@ -1,21 +1,109 @@
 mod cli;
 
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, LogArgs, StatsArgs};
 use colored::Colorize;
-use scx_loader::{dbus::LoaderClientProxyBlocking, SchedMode, SupportedSched};
+use scx_loader::{
+    dbus::LoaderClientProxyBlocking, validate::WARNING_PREFIX, SchedMode, SupportedSched,
+};
+use scx_stats::prelude::*;
+use scx_stats::printer::{StatsFormat, StatsPrinter};
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::process::exit;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
 use zbus::blocking::Connection;
 
 fn cmd_get(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::error::Error>> {
     let current_scheduler: String = scx_loader.current_scheduler().unwrap();
-    let sched_mode: SchedMode = scx_loader.scheduler_mode().unwrap();
+    let mode_name: String = scx_loader.scheduler_mode_name().unwrap();
     match current_scheduler.as_str() {
         "unknown" => println!("no scx scheduler running"),
         _ => {
             let sched = SupportedSched::try_from(current_scheduler.as_str()).unwrap();
-            println!("running {sched:?} in {sched_mode:?} mode");
+            println!(
+                "running {} in {} mode",
+                format_sched(&sched),
+                format_mode(&mode_name)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_status(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::error::Error>> {
+    let current_scheduler: String = scx_loader.current_scheduler()?;
+    let last_exit_reason: String = scx_loader.last_exit_reason()?;
+
+    if current_scheduler == "unknown" {
+        println!("{:<12}none", "scheduler:".bold());
+    } else {
+        let sched = SupportedSched::try_from(current_scheduler.as_str()).unwrap();
+        let mode_name: String = scx_loader.scheduler_mode_name()?;
+        let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(scx_loader.started_at()?);
+        let uptime = SystemTime::now()
+            .duration_since(started_at)
+            .unwrap_or_default();
+
+        println!("{:<12}{}", "scheduler:".bold(), format_sched(&sched));
+        println!("{:<12}{}", "mode:".bold(), format_mode(&mode_name));
+        println!("{:<12}{}", "uptime:".bold(), format_uptime(uptime));
+        println!("{:<12}{}", "restarts:".bold(), scx_loader.restart_count()?);
+    }
+
+    match last_exit_reason.as_str() {
+        "" => println!("{:<12}none", "last exit:".bold()),
+        _ => println!("{:<12}{}", "last exit:".bold(), last_exit_reason),
+    }
+    Ok(())
+}
+
+fn cmd_stats(args: StatsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let client = StatsClient::new();
+    let client = match &args.path {
+        Some(path) => client.set_path(path),
+        None => client,
+    };
+    let mut client = match client.connect() {
+        Ok(client) => client,
+        Err(e) => {
+            println!(
+                "{} failed to connect to the stats socket: {e}",
+                "error:".red().bold()
+            );
+            println!("is a scheduler with stats support running?");
+            exit(1);
+        }
+    };
+
+    let metas: BTreeMap<String, StatsMeta> = client.request("stats_meta", vec![])?;
+    let mut printer = StatsPrinter::new(metas, StatsFormat::Table)?;
+    let mut stdout = std::io::stdout().lock();
+
+    loop {
+        let stats: scx_stats::serde_json::Value = client.request("stats", vec![])?;
+        if args.watch {
+            // clear the screen like watch(1)
+            write!(stdout, "\x1b[2J\x1b[H")?;
+        }
+        printer.print(&mut stdout, &stats)?;
+        stdout.flush()?;
+
+        if !args.watch {
+            return Ok(());
         }
+        sleep(Duration::from_secs_f64(args.interval));
+    }
+}
+
+fn cmd_log(
+    scx_loader: LoaderClientProxyBlocking,
+    args: LogArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for line in scx_loader.get_scheduler_log(args.lines)? {
+        println!("{line}");
     }
     Ok(())
 }
@@ -28,19 +116,19 @@ fn cmd_list(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::er
                 .map(|s| remove_scx_prefix(&s.to_string()))
                 .collect::<Vec<String>>();
             println!("supported schedulers: {:?}", supported_scheds);
-            return Ok(());
+            Ok(())
         }
         Err(e) => {
             eprintln!("scheduler list failed: {e}");
             exit(1);
         }
-    };
+    }
 }
 
 fn cmd_start(
     scx_loader: LoaderClientProxyBlocking,
     sched_name: String,
-    mode_name: Option<SchedMode>,
+    mode_name: Option<String>,
     args: Option<Vec<String>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Verify scx_loader is not running a scheduler
@@ -56,15 +144,26 @@ fn cmd_start(
     }
 
     let sched: SupportedSched = validate_sched(scx_loader.clone(), sched_name);
-    let mode: SchedMode = mode_name.unwrap_or(SchedMode::Auto);
+    let mode_name: String = mode_name.unwrap_or("auto".to_owned());
     match args {
         Some(args) => {
             scx_loader.start_scheduler_with_args(sched.clone(), &args.clone())?;
-            println!("started {sched:?} with arguments \"{}\"", args.join(" "));
+            println!(
+                "started {} with arguments \"{}\"",
+                format_sched(&sched),
+                args.join(" ")
+            );
         }
         None => {
-            scx_loader.start_scheduler(sched.clone(), mode.clone())?;
-            println!("started {sched:?} in {mode:?} mode");
+            match SchedMode::from_str(&mode_name) {
+                Ok(mode) => scx_loader.start_scheduler(sched.clone(), mode)?,
+                Err(_) => scx_loader.start_scheduler_with_mode_name(sched.clone(), &mode_name)?,
+            }
+            println!(
+                "started {} in {} mode",
+                format_sched(&sched),
+                format_mode(&mode_name)
+            );
         }
     }
     Ok(())
@@ -73,7 +172,7 @@ fn cmd_start(
 fn cmd_switch(
     scx_loader: LoaderClientProxyBlocking,
     sched_name: Option<String>,
-    mode_name: Option<SchedMode>,
+    mode_name: Option<String>,
     args: Option<Vec<String>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Verify scx_loader is running a scheduler
@@ -92,21 +191,29 @@ fn cmd_switch(
         Some(sched_name) => validate_sched(scx_loader.clone(), sched_name),
         None => SupportedSched::try_from(scx_loader.current_scheduler().unwrap().as_str()).unwrap(),
     };
-    let mode: SchedMode = match mode_name {
+    let mode_name: String = match mode_name {
         Some(mode_name) => mode_name,
-        None => scx_loader.scheduler_mode().unwrap(),
+        None => scx_loader.scheduler_mode_name().unwrap(),
     };
     match args {
         Some(args) => {
             scx_loader.switch_scheduler_with_args(sched.clone(), &args.clone())?;
             println!(
-                "switched to {sched:?} with arguments \"{}\"",
+                "switched to {} with arguments \"{}\"",
+                format_sched(&sched),
                 args.join(" ")
             );
         }
         None => {
-            scx_loader.switch_scheduler(sched.clone(), mode.clone())?;
-            println!("switched to {sched:?} in {mode:?} mode");
+            match SchedMode::from_str(&mode_name) {
+                Ok(mode) => scx_loader.switch_scheduler(sched.clone(), mode)?,
+                Err(_) => scx_loader.switch_scheduler_with_mode_name(sched.clone(), &mode_name)?,
+            }
+            println!(
+                "switched to {} in {} mode",
+                format_sched(&sched),
+                format_mode(&mode_name)
+            );
         }
     }
     Ok(())
@@ -118,6 +225,54 @@ fn cmd_stop(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+fn cmd_validate(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::error::Error>> {
+    let messages: Vec<String> = scx_loader.validate_config()?;
+    if messages.is_empty() {
+        println!("config is valid");
+        return Ok(());
+    }
+
+    if print_validation(&messages) {
+        exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the errors and warnings of the validation, returns whether any errors were found
+fn print_validation(messages: &[String]) -> bool {
+    let mut has_errors = false;
+    for message in messages {
+        match message.strip_prefix(WARNING_PREFIX) {
+            Some(warning) => println!("{} {}", "warning:".yellow().bold(), warning),
+            None => {
+                println!("{} {}", "error:".red().bold(), message);
+                has_errors = true;
+            }
+        }
+    }
+    has_errors
+}
+
+fn cmd_reload(scx_loader: LoaderClientProxyBlocking) -> Result<(), Box<dyn std::error::Error>> {
+    let (changes, messages): (Vec<String>, Vec<String>) = scx_loader.reload_config()?;
+
+    // a config with errors is not applied
+    if print_validation(&messages) {
+        println!("config not reloaded, the previous one stays active");
+        exit(1);
+    }
+
+    if changes.is_empty() {
+        println!("config reloaded, nothing changed");
+    } else {
+        println!("config reloaded:");
+        for change in changes {
+            println!("  {change}");
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
     let conn = Connection::system()?;
@@ -125,10 +280,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     match cli.command {
         Commands::Get => cmd_get(scx_loader)?,
+        Commands::Status => cmd_status(scx_loader)?,
+        Commands::Stats { args } => cmd_stats(args)?,
+        Commands::Log { args } => cmd_log(scx_loader, args)?,
         Commands::List => cmd_list(scx_loader)?,
         Commands::Start { args } => cmd_start(scx_loader, args.sched, args.mode, args.args)?,
         Commands::Switch { args } => cmd_switch(scx_loader, args.sched, args.mode, args.args)?,
         Commands::Stop => cmd_stop(scx_loader)?,
+        Commands::Validate => cmd_validate(scx_loader)?,
+        Commands::Reload => cmd_reload(scx_loader)?,
     }
 
     Ok(())
@@ -154,12 +314,36 @@ fn remove_scx_prefix(input: &String) -> String {
     input.to_string()
 }
 
+/// Format the scheduler for output, custom schedulers are printed by their name
+fn format_sched(sched: &SupportedSched) -> String {
+    match sched {
+        SupportedSched::Custom(scx_name) => scx_name.clone(),
+        _ => format!("{sched:?}"),
+    }
+}
+
+/// Format the mode name for output, built-in modes are printed like the SchedMode variant
+fn format_mode(mode_name: &str) -> String {
+    match SchedMode::from_str(mode_name) {
+        Ok(mode) => format!("{mode:?}"),
+        Err(_) => mode_name.to_owned(),
+    }
+}
+
+/// Format the uptime for output, e.g. "1h 02m 03s"
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60),
+    }
+}
+
 fn validate_sched(scx_loader: LoaderClientProxyBlocking, sched: String) -> SupportedSched {
     let raw_supported_scheds: Vec<String> = scx_loader.supported_schedulers().unwrap();
-    let supported_scheds: Vec<String> = raw_supported_scheds
-        .iter()
-        .map(|s| remove_scx_prefix(s))
-        .collect();
+    let supported_scheds: Vec<String> =
+        raw_supported_scheds.iter().map(remove_scx_prefix).collect();
     if !supported_scheds.contains(&sched) && !raw_supported_scheds.contains(&sched) {
         println!(
             "{} invalid value '{}' for '{}'",